@@ -156,6 +156,53 @@ class Utils {
         return await Promise.all(promises);
     }
 
+    /**
+     * Same input as loadTextureImageUint8ArrayBuffers, but the browser decodes the images
+     *
+     * imagesData = {
+     *     {id: uniform_id, url: image_path}
+     * }
+     *
+     * @param imagesData
+     * @return {Promise<{id: string, source: ImageBitmap}[]>}
+     */
+    static async loadTextureImageBitmaps(imagesData) {
+        const promises = imagesData.map(async data => {
+            const blob = await (await fetch(data.url)).blob();
+
+            return {id: data.id, source: await createImageBitmap(blob)};
+        });
+
+        return await Promise.all(promises);
+    }
+
+    /**
+     * Starts muted, looping videos to be passed as texture sources, they stream a new frame every draw
+     *
+     * videosData = {
+     *     {id: uniform_id, url: video_path}
+     * }
+     *
+     * @param videosData
+     * @return {Promise<{id: string, source: HTMLVideoElement}[]>}
+     */
+    static async loadTextureVideos(videosData) {
+        const promises = videosData.map(data => {
+            return new Promise((resolve, reject) => {
+                const video = document.createElement('video');
+                video.muted = true;
+                video.loop = true;
+                video.playsInline = true;
+                video.crossOrigin = 'anonymous';
+                video.onloadeddata = () => video.play().then(() => resolve({id: data.id, source: video}), reject);
+                video.onerror = reject;
+                video.src = data.url;
+            });
+        });
+
+        return await Promise.all(promises);
+    }
+
     static rotationModelXYZ(t, x, y, z) {
         const viewMatrix = mat4.create();
         let c = Math.cos(t);
@@ -10,7 +10,10 @@ var program = null;
 const tracker = Tracker.create();
 const TEST_TIME_60 = 60; // 1 min
 const TEST_TIME_20 = 20; // 20 sec
-const ALTERNATIVE = 'wasm-opengl';
+// `?backend=webgpu` draws the scene with WebGPU, anything else is passed on to `Context.new`
+const BACKEND = new URLSearchParams(window.location.search).get('backend');
+const WEBGPU = BACKEND === 'webgpu';
+const ALTERNATIVE = WEBGPU ? 'wasm-webgpu' : 'wasm-opengl';
 
 const selector = {
     agent: $('#agent'),
@@ -95,11 +98,16 @@ function startTest(objName, initParams, name, testTime) {
             frag_str: fsSource,
         };
 
+        if (BACKEND && !WEBGPU) {
+            initParams.backend = BACKEND;
+        }
+
         let scene = SceneInit.new("rust-gl", obj_data, shaders, images, initParams);
 
-        program = wasmOpenGL.Context.new(scene);
+        program = WEBGPU ? await wasmOpenGL.GpuContext.create(scene) : wasmOpenGL.Context.new(scene);
 
-        bench = new GLBench(program.get_context(), {
+        // GPU profiling hooks WebGL draw calls, there is nothing to hook on a WebGPU context
+        bench = new GLBench(WEBGPU ? null : program.get_context(), {
             withoutUI: true,
             trackGPU: false,
             // chartHz: 5,
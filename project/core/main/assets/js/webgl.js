@@ -66,6 +66,26 @@ $(document).ready(() => {
     })();
 });
 
+// Inlines `#include "name"` chunks, which the wasm renderer resolves itself
+async function resolveIncludes(source, included = new Set()) {
+    const lines = [];
+    for (const line of source.split('\n')) {
+        const include = line.trim().match(/^#include\s+"([^"]+)"/);
+        if (!include) {
+            lines.push(line);
+            continue;
+        }
+        if (included.has(include[1])) {
+            continue;
+        }
+        included.add(include[1]);
+        const chunk = await fetch(`build/resources/shader/chunks/${include[1]}.glsl`);
+        lines.push(await resolveIncludes(await chunk.text(), included));
+    }
+
+    return lines.join('\n');
+}
+
 function startTest(objName, initParams, name, testTime) {
 
     (async () => {
@@ -81,7 +101,7 @@ function startTest(objName, initParams, name, testTime) {
         let obj_data = await source.text();
 
         let vsSource = await fetch('build/resources/shader/obj.vert.glsl');
-        vsSource = await vsSource.text();
+        vsSource = await resolveIncludes(await vsSource.text());
 
         let fsSource = await fetch('build/resources/shader/obj.frag.glsl');
         fsSource = await fsSource.text();
@@ -2,6 +2,9 @@ precision highp float;
 
 uniform sampler2D tex_norm;
 uniform sampler2D tex_diffuse;
+// Zero (unset) values keep the output opaque
+uniform float alpha_cutoff;
+uniform float alpha_blend;
 
 varying vec2 frag_uv;
 varying vec3 ts_light_pos;
@@ -16,11 +19,16 @@ void main(void)
     // Only perturb the texture coordinates if a parallax technique is selected
     vec2 uv = frag_uv;
 
-    vec3 albedo = texture2D(tex_diffuse, uv).rgb;
+    vec4 diffuse_color = texture2D(tex_diffuse, uv);
+    if (diffuse_color.a < alpha_cutoff) {
+        discard;
+    }
+
+    vec3 albedo = diffuse_color.rgb;
     vec3 ambient = 0.7 * albedo;
 
     // Normal mapping
     vec3 norm = normalize(texture2D(tex_norm, uv).rgb * 2.0 - 1.0);
     float diffuse = max(dot(light_dir, norm), 0.0);
-    gl_FragColor = vec4(diffuse * albedo + ambient, 1.0);
+    gl_FragColor = vec4(diffuse * albedo + ambient, mix(1.0, diffuse_color.a, alpha_blend));
 }
\ No newline at end of file
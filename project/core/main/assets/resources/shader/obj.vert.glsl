@@ -16,50 +16,7 @@ varying vec3 ts_light_pos; // Tangent space values
 varying vec3 ts_view_pos;  //
 varying vec3 ts_frag_pos;  //
 
-mat3 transpose(in mat3 inMatrix)
-{
-    vec3 i0 = inMatrix[0];
-    vec3 i1 = inMatrix[1];
-    vec3 i2 = inMatrix[2];
-
-    mat3 outMatrix = mat3(
-    vec3(i0.x, i1.x, i2.x),
-    vec3(i0.y, i1.y, i2.y),
-    vec3(i0.z, i1.z, i2.z)
-    );
-
-    return outMatrix;
-}
-
-float det(mat2 matrix) {
-    return matrix[0].x * matrix[1].y - matrix[0].y * matrix[1].x;
-}
-
-mat3 inverse(mat3 matrix) {
-    vec3 row0 = matrix[0];
-    vec3 row1 = matrix[1];
-    vec3 row2 = matrix[2];
-
-    vec3 minors0 = vec3(
-    det(mat2(row1.y, row1.z, row2.y, row2.z)),
-    det(mat2(row1.z, row1.x, row2.z, row2.x)),
-    det(mat2(row1.x, row1.y, row2.x, row2.y))
-    );
-    vec3 minors1 = vec3(
-    det(mat2(row2.y, row2.z, row0.y, row0.z)),
-    det(mat2(row2.z, row2.x, row0.z, row0.x)),
-    det(mat2(row2.x, row2.y, row0.x, row0.y))
-    );
-    vec3 minors2 = vec3(
-    det(mat2(row0.y, row0.z, row1.y, row1.z)),
-    det(mat2(row0.z, row0.x, row1.z, row1.x)),
-    det(mat2(row0.x, row0.y, row1.x, row1.y))
-    );
-
-    mat3 adj = transpose(mat3(minors0, minors1, minors2));
-
-    return (1.0 / dot(row0, minors0)) * adj;
-}
+#include "matrix"
 
 void main(void)
 {
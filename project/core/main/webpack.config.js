@@ -29,6 +29,11 @@ Encore
         from: './assets/resources',
         to: 'resources/[path][name].[ext]'
     })
+    // `#include` chunks of the shaders, resolved by the plain WebGL benchmark
+    .copyFiles({
+        from: '../wasm/src/shader/chunks',
+        to: 'resources/shader/chunks/[name].[ext]'
+    })
 
     // enables the Symfony UX Stimulus bridge (used in assets/bootstrap.js)
     .enableStimulusBridge('./assets/controllers.json')
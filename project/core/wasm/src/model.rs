@@ -1,4 +1,5 @@
 pub mod node;
+pub mod gl;
 pub mod texture;
 pub mod program;
 pub mod default_pipeline;
@@ -7,6 +8,52 @@ pub mod geometry;
 pub mod image;
 pub mod common;
 pub mod js_import;
+#[cfg(feature = "web")]
+pub mod render_target;
+#[cfg(feature = "web")]
+pub mod post_process;
+#[cfg(feature = "web")]
+pub mod picking;
+pub mod bounds;
+pub mod frustum;
+pub mod alpha_mode;
+pub mod render_queue;
+pub mod gl_state;
+pub mod reflection;
+pub mod uniform;
+pub mod preprocessor;
+#[cfg(feature = "web")]
+pub mod shader_variants;
+#[cfg(feature = "web")]
+pub mod program_cache;
+pub mod material;
+/// CPU reference of the PBR shader, only used to test shading
+#[cfg(test)]
+pub mod brdf;
+pub mod light;
+pub mod cubemap;
+pub mod ibl;
+#[cfg(feature = "web")]
+pub mod skybox;
+#[cfg(feature = "web")]
+pub mod environment;
+pub mod hdr;
+pub mod ktx;
+pub mod atlas;
+#[cfg(feature = "web")]
+pub mod video;
+#[cfg(feature = "web")]
+pub mod context_loss;
+pub mod backend;
+pub mod device;
+/// Device logging every call, used to test drawing without a browser
+#[cfg(test)]
+pub mod recording_device;
+pub mod scene;
+#[cfg(feature = "web")]
+pub mod webgpu;
+pub mod rasterizer;
+pub mod frame_uniforms;
 
 pub use self::node::Node;
 pub use self::texture::Texture;
@@ -15,6 +62,29 @@ pub use self::default_pipeline::DefaultPipeline;
 pub use self::primitive::Primitive;
 pub use self::geometry::Geometry;
 pub use self::image::Image;
+#[cfg(feature = "web")]
 pub use self::js_import::JsTextureImage;
 pub use self::js_import::JsRenderParams;
-pub use self::js_import::JsShaders;
\ No newline at end of file
+pub use self::js_import::JsShaders;
+pub use self::js_import::JsPostProcessPass;
+#[cfg(feature = "web")]
+pub use self::render_target::RenderTarget;
+#[cfg(feature = "web")]
+pub use self::post_process::PostProcessChain;
+#[cfg(feature = "web")]
+pub use self::picking::Picker;
+pub use self::bounds::BoundingVolume;
+pub use self::frustum::Frustum;
+pub use self::alpha_mode::AlphaMode;
+pub use self::render_queue::{DrawItem, RenderPass, RenderQueue};
+pub use self::gl_state::GlState;
+pub use self::reflection::ProgramReflection;
+pub use self::uniform::UniformValue;
+pub use self::preprocessor::{PreprocessedShader, ShaderFeatures};
+#[cfg(feature = "web")]
+pub use self::shader_variants::{ShaderSource, ShaderVariants};
+pub use self::material::Material;
+pub use self::light::Light;
+#[cfg(feature = "web")]
+pub use self::environment::Environment;
+pub use self::scene::Scene;
\ No newline at end of file
@@ -0,0 +1,37 @@
+/// How the alpha channel of the diffuse texture is interpreted
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored, output is fully opaque
+    #[default]
+    Opaque,
+    /// Fragments with alpha below the cutoff are discarded
+    Mask(f32),
+    /// Alpha blended, drawn back-to-front without depth writes
+    Blend,
+}
+
+impl AlphaMode {
+    pub const DEFAULT_CUTOFF: f32 = 0.5;
+
+    pub fn from_name(name: &str, cutoff: Option<f32>) -> Option<Self> {
+        match name {
+            "opaque" => Some(AlphaMode::Opaque),
+            "mask" => Some(AlphaMode::Mask(cutoff.unwrap_or(Self::DEFAULT_CUTOFF))),
+            "blend" => Some(AlphaMode::Blend),
+            _ => None,
+        }
+    }
+
+    /// Value of the `alpha_cutoff` uniform, zero never discards
+    pub fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Mask(cutoff) => *cutoff,
+            _ => 0.0,
+        }
+    }
+
+    pub fn is_blended(&self) -> bool {
+        *self == AlphaMode::Blend
+    }
+}
+
@@ -0,0 +1,405 @@
+//! Packs small images into shared pages so materials can share one texture bind
+
+use crate::model::Image;
+
+/// Placement of one image, `x` and `y` point at its first pixel inside the page
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRect {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    /// Maps a UV in [0, 1] over the image to the matching UV of the page
+    pub fn remap_uv(&self, uv: [f32; 2], page_size: u32) -> [f32; 2] {
+        let size = page_size as f32;
+        [
+            (self.x as f32 + uv[0] * self.width as f32) / size,
+            (self.y as f32 + uv[1] * self.height as f32) / size,
+        ]
+    }
+}
+
+/// Skyline bottom-left packer over square pages
+///
+/// `gutter` pixels of each image edge are repeated around it so filtering never reads a neighbor,
+/// `padding` empty pixels separate the gutters of neighboring images.
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasPacker {
+    pub page_size: u32,
+    pub padding: u32,
+    pub gutter: u32,
+}
+
+/// Horizontal run of the skyline at height `y`
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+struct Page {
+    skyline: Vec<Segment>,
+}
+
+impl Page {
+    fn new(size: u32) -> Self {
+        Self { skyline: vec![Segment { x: 0, y: 0, width: size }] }
+    }
+
+    /// Lowest then leftmost spot where `need` fits inside the page, as (segment, x, y, occupied width)
+    ///
+    /// The block covers `block` on the skyline, its trailing padding is cut at the right page edge.
+    fn find(&self, need: (u32, u32), block: (u32, u32), size: u32) -> Option<(usize, u32, u32, u32)> {
+        let mut best: Option<(usize, u32, u32, u32)> = None;
+
+        for (i, segment) in self.skyline.iter().enumerate() {
+            let x = segment.x;
+            if x + need.0 > size {
+                break;
+            }
+            let width = block.0.min(size - x);
+
+            // Rest on the highest segment under the block
+            let y = self.skyline[i..]
+                .iter()
+                .take_while(|next| next.x < x + width)
+                .map(|next| next.y)
+                .max()
+                .unwrap_or(0);
+
+            if y + need.1 <= size && best.is_none_or(|(_, best_x, best_y, _)| (y, x) < (best_y, best_x)) {
+                best = Some((i, x, y, width));
+            }
+        }
+
+        best
+    }
+
+    fn place(&mut self, index: usize, x: u32, y: u32, width: u32, height: u32) {
+        self.skyline.insert(index, Segment { x, y: y + height, width });
+
+        // Cut the segments now hidden under the new one
+        let right = x + width;
+        let i = index + 1;
+        while i < self.skyline.len() {
+            let segment = self.skyline[i];
+            if segment.x >= right {
+                break;
+            }
+
+            let end = segment.x + segment.width;
+            if end <= right {
+                self.skyline.remove(i);
+            } else {
+                self.skyline[i] = Segment { x: right, y: segment.y, width: end - right };
+                break;
+            }
+        }
+
+        // Merge neighbors of equal height
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].width += self.skyline[i + 1].width;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl AtlasPacker {
+    /// Places images of the given sizes, opening new pages as needed, results follow the input order
+    pub fn pack(&self, sizes: &[(u32, u32)]) -> Result<Vec<AtlasRect>, String> {
+        let border = 2 * self.gutter;
+        if let Some((width, height)) = sizes.iter().find(|(w, h)| w + border > self.page_size || h + border > self.page_size) {
+            return Err(format!("A {}x{} image does not fit a {} atlas page with its gutters", width, height, self.page_size));
+        }
+
+        // Tall images first keep the skyline flat
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by_key(|&i| (std::cmp::Reverse(sizes[i].1), std::cmp::Reverse(sizes[i].0)));
+
+        let mut pages: Vec<Page> = vec![];
+        let mut rects = vec![None; sizes.len()];
+
+        for i in order {
+            let (width, height) = sizes[i];
+            let need = (width + border, height + border);
+            // Padding trails each block and may hang over the page edges
+            let block = (need.0 + self.padding, need.1 + self.padding);
+
+            let found = pages
+                .iter()
+                .enumerate()
+                .find_map(|(page, skyline)| skyline.find(need, block, self.page_size).map(|spot| (page, spot)));
+
+            let (page, (index, x, y, occupied)) = match found {
+                Some(found) => found,
+                None => {
+                    pages.push(Page::new(self.page_size));
+                    let spot = pages[pages.len() - 1].find(need, block, self.page_size).expect("Images fit an empty page");
+                    (pages.len() - 1, spot)
+                }
+            };
+
+            pages[page].place(index, x, y, occupied, block.1);
+            rects[i] = Some(AtlasRect { page, x: x + self.gutter, y: y + self.gutter, width, height });
+        }
+
+        Ok(rects.into_iter().map(|rect| rect.expect("Every image is placed")).collect())
+    }
+}
+
+/// Textures of several materials packed with one shared layout
+///
+/// Every sampler gets its own pages, a material's images sit at the same rect in each of them
+/// so one remapped set of UVs serves all its maps.
+pub struct MaterialAtlas {
+    pub page_size: u32,
+    /// Rect of each input material, `None` when it was left out
+    pub rects: Vec<Option<AtlasRect>>,
+    /// Page images as (sampler, page, image)
+    pub pages: Vec<(String, usize, Image)>,
+}
+
+impl MaterialAtlas {
+    /// Packs materials given as their (sampler, image) pairs, `None` entries are skipped
+    ///
+    /// Only materials whose images all share one size take part, and only when at least two do.
+    pub fn build(materials: &[Option<Vec<(&str, &Image)>>], packer: &AtlasPacker) -> Result<Self, String> {
+        let fits = |(w, h): (u32, u32)| w + 2 * packer.gutter <= packer.page_size && h + 2 * packer.gutter <= packer.page_size;
+        let sizes: Vec<Option<(u32, u32)>> = materials
+            .iter()
+            .map(|textures| {
+                let textures = textures.as_ref()?;
+                let (_, first) = textures.first()?;
+                let size = (first.width, first.height);
+                let same_size = textures.iter().all(|(_, image)| (image.width, image.height) == size);
+
+                if same_size && fits(size) { Some(size) } else { None }
+            })
+            .collect();
+
+        let mut atlas = Self { page_size: packer.page_size, rects: vec![None; materials.len()], pages: vec![] };
+        let packed: Vec<usize> = (0..materials.len()).filter(|&i| sizes[i].is_some()).collect();
+        if packed.len() < 2 {
+            return Ok(atlas);
+        }
+
+        let rects = packer.pack(&packed.iter().map(|&i| sizes[i].expect("Packed materials have a size")).collect::<Vec<_>>())?;
+
+        let mut samplers: Vec<&str> = packed
+            .iter()
+            .flat_map(|&i| materials[i].iter().flatten().map(|(sampler, _)| *sampler))
+            .collect();
+        samplers.sort_unstable();
+        samplers.dedup();
+
+        let page_count = rects.iter().map(|rect| rect.page + 1).max().unwrap_or(0);
+        for sampler in samplers {
+            for page in 0..page_count {
+                let mut images = vec![];
+                let mut page_rects = vec![];
+                for (&i, rect) in packed.iter().zip(rects.iter()).filter(|(_, rect)| rect.page == page) {
+                    if let Some((_, image)) = materials[i].iter().flatten().find(|(name, _)| *name == sampler) {
+                        images.push(*image);
+                        page_rects.push(*rect);
+                    }
+                }
+                if images.is_empty() {
+                    continue;
+                }
+
+                let image = compose_page(&images, &page_rects, packer);
+                atlas.pages.push((sampler.to_string(), page, image));
+            }
+        }
+
+        for (&i, rect) in packed.iter().zip(rects) {
+            atlas.rects[i] = Some(rect);
+        }
+
+        Ok(atlas)
+    }
+}
+
+/// Copies images into one RGBA page, extruding their edges into the gutters
+fn compose_page(images: &[&Image], rects: &[AtlasRect], packer: &AtlasPacker) -> Image {
+    let size = packer.page_size as usize;
+    let mut page = vec![0; size * size * 4];
+    let gutter = packer.gutter as i64;
+
+    for (image, rect) in images.iter().zip(rects.iter()) {
+        let channels = image.channels as usize;
+
+        for dy in -gutter..rect.height as i64 + gutter {
+            for dx in -gutter..rect.width as i64 + gutter {
+                // Gutter pixels repeat the closest edge pixel
+                let sx = dx.clamp(0, rect.width as i64 - 1) as usize;
+                let sy = dy.clamp(0, rect.height as i64 - 1) as usize;
+                let src = (sy * image.width as usize + sx) * channels;
+
+                let px = (rect.x as i64 + dx) as usize;
+                let py = (rect.y as i64 + dy) as usize;
+                let dst = (py * size + px) * 4;
+
+                page[dst..dst + 3].copy_from_slice(&image.data[src..src + 3]);
+                page[dst + 3] = if channels == 4 { image.data[src + 3] } else { 255 };
+            }
+        }
+    }
+
+    Image::with_channels(page, packer.page_size, packer.page_size, 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Geometry;
+
+    fn overlaps(a: &AtlasRect, b: &AtlasRect, margin: u32) -> bool {
+        a.page == b.page
+            && a.x < b.x + b.width + margin
+            && b.x < a.x + a.width + margin
+            && a.y < b.y + b.height + margin
+            && b.y < a.y + a.height + margin
+    }
+
+    fn sizes() -> Vec<(u32, u32)> {
+        (0..40u32).map(|i| (4 + i * 7 % 29, 3 + i * 11 % 23)).collect()
+    }
+
+    #[test]
+    fn packed_rects_stay_apart_and_inside_their_page() {
+        let packer = AtlasPacker { page_size: 128, padding: 1, gutter: 2 };
+        let sizes = sizes();
+        let rects = packer.pack(&sizes).unwrap();
+
+        for (i, rect) in rects.iter().enumerate() {
+            assert_eq!((rect.width, rect.height), sizes[i]);
+            assert!(rect.x >= packer.gutter && rect.y >= packer.gutter);
+            assert!(rect.x + rect.width + packer.gutter <= packer.page_size);
+            assert!(rect.y + rect.height + packer.gutter <= packer.page_size);
+
+            // Both gutters and the padding lie between any two images
+            for other in rects[i + 1..].iter() {
+                assert!(!overlaps(rect, other, 2 * packer.gutter + packer.padding), "{:?} overlaps {:?}", rect, other);
+            }
+        }
+    }
+
+    #[test]
+    fn overflowing_images_open_new_pages() {
+        let packer = AtlasPacker { page_size: 16, padding: 0, gutter: 0 };
+        let rects = packer.pack(&[(16, 16), (8, 8), (8, 8), (8, 8), (8, 8), (8, 8)]).unwrap();
+
+        assert_eq!(rects[0], AtlasRect { page: 0, x: 0, y: 0, width: 16, height: 16 });
+        assert!(rects[1..5].iter().all(|rect| rect.page == 1));
+        assert_eq!(rects[5].page, 2);
+    }
+
+    #[test]
+    fn images_larger_than_a_page_are_errors() {
+        let packer = AtlasPacker { page_size: 16, padding: 0, gutter: 1 };
+
+        assert!(packer.pack(&[(15, 4)]).is_err());
+        assert!(packer.pack(&[(14, 14)]).is_ok());
+    }
+
+    #[test]
+    fn gutters_repeat_edge_pixels() {
+        let red = Image::from_vec(vec![255, 0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0], 2, 2);
+        let blue = Image::with_channels(vec![0, 0, 255, 128], 1, 1, 4);
+        let materials = vec![Some(vec![("tex_diffuse", &red)]), Some(vec![("tex_diffuse", &blue)])];
+        let atlas = MaterialAtlas::build(&materials, &AtlasPacker { page_size: 8, padding: 1, gutter: 1 }).unwrap();
+
+        assert_eq!(atlas.pages.len(), 1);
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * 8 + x) * 4) as usize;
+            atlas.pages[0].2.data[i..i + 4].to_vec()
+        };
+
+        let rect = atlas.rects[0].unwrap();
+        assert_eq!(pixel(rect.x - 1, rect.y - 1), vec![255, 0, 0, 255]);
+        assert_eq!(pixel(rect.x + 2, rect.y + 1), vec![255, 0, 0, 255]);
+
+        let rect = atlas.rects[1].unwrap();
+        assert_eq!(pixel(rect.x, rect.y + 1), vec![0, 0, 255, 128]);
+    }
+
+    #[test]
+    fn geometry_uvs_follow_their_image() {
+        let image = Image::from_vec(vec![0; 4 * 4 * 3], 4, 4);
+        let materials = vec![Some(vec![("tex_diffuse", &image)]), Some(vec![("tex_diffuse", &image)])];
+        let atlas = MaterialAtlas::build(&materials, &AtlasPacker { page_size: 16, padding: 0, gutter: 2 }).unwrap();
+        let rect = atlas.rects[1].unwrap();
+
+        let triangle = || Geometry::new(vec![0.0; 9], vec![0.0, 0.0, 1.0, 0.0, 0.5, 1.0], vec![], vec![], 1, 3);
+        let mut geometry = triangle();
+        geometry.remap_uvs(&rect, atlas.page_size).unwrap();
+
+        assert_eq!(&geometry.uvs[..2], &[rect.x as f32 / 16.0, rect.y as f32 / 16.0]);
+        assert_eq!(geometry.uvs[2], (rect.x + 4) as f32 / 16.0);
+        assert_eq!(geometry.uvs[5], (rect.y + 4) as f32 / 16.0);
+
+        // Repeating UVs would sample the neighbors
+        let mut tiled = triangle();
+        tiled.uvs[2] = 2.0;
+        assert!(tiled.remap_uvs(&rect, atlas.page_size).is_err());
+        assert_eq!(tiled.uvs[2], 2.0);
+    }
+
+    #[test]
+    fn materials_share_one_layout_across_samplers() {
+        let image = |w: u32, h: u32, value: u8| Image::from_vec(vec![value; (w * h * 3) as usize], w, h);
+        let (diffuse_a, normal_a) = (image(4, 4, 10), image(4, 4, 20));
+        let diffuse_b = image(8, 4, 30);
+        let mismatched = (image(4, 4, 40), image(2, 2, 50));
+
+        let materials = vec![
+            Some(vec![("tex_diffuse", &diffuse_a), ("tex_norm", &normal_a)]),
+            Some(vec![("tex_diffuse", &diffuse_b)]),
+            Some(vec![("tex_diffuse", &mismatched.0), ("tex_norm", &mismatched.1)]),
+            None,
+        ];
+        let atlas = MaterialAtlas::build(&materials, &AtlasPacker { page_size: 32, padding: 0, gutter: 1 }).unwrap();
+
+        assert!(atlas.rects[0].is_some() && atlas.rects[1].is_some());
+        assert_eq!(atlas.rects[2], None);
+        assert_eq!(atlas.rects[3], None);
+
+        let pages: Vec<(&str, usize)> = atlas.pages.iter().map(|(sampler, page, _)| (sampler.as_str(), *page)).collect();
+        assert_eq!(pages, vec![("tex_diffuse", 0), ("tex_norm", 0)]);
+
+        // The normal map of material 0 lands where its diffuse map is
+        let rect = atlas.rects[0].unwrap();
+        let i = ((rect.y * 32 + rect.x) * 4) as usize;
+        assert_eq!(atlas.pages[0].2.data[i], 10);
+        assert_eq!(atlas.pages[1].2.data[i], 20);
+    }
+
+    #[test]
+    fn a_single_material_is_left_alone() {
+        let image = Image::from_vec(vec![0; 4 * 4 * 3], 4, 4);
+        let atlas = MaterialAtlas::build(&[Some(vec![("tex_diffuse", &image)])], &AtlasPacker { page_size: 32, padding: 0, gutter: 0 }).unwrap();
+
+        assert_eq!(atlas.rects, vec![None]);
+        assert!(atlas.pages.is_empty());
+    }
+
+    #[test]
+    fn uvs_map_into_the_placed_rect() {
+        let rect = AtlasRect { page: 0, x: 16, y: 32, width: 32, height: 16 };
+
+        assert_eq!(rect.remap_uv([0.0, 0.0], 64), [0.25, 0.5]);
+        assert_eq!(rect.remap_uv([1.0, 1.0], 64), [0.75, 0.75]);
+        assert_eq!(rect.remap_uv([0.5, 0.5], 64), [0.5, 0.625]);
+    }
+}
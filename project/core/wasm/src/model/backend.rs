@@ -0,0 +1,131 @@
+//! WebGL version selection
+//!
+//! A WebGL2 context answers every WebGL1 call, so the renderer keeps one `WebGlRenderingContext`
+//! handle for the shared code and reaches for `WebGl2RenderingContext` only for WebGL2 features.
+//! WebGPU has its own renderer in `webgpu`, it shares scene loading but none of the GL code.
+
+#[cfg(feature = "web")]
+use wasm_bindgen::{JsCast, JsValue};
+#[cfg(feature = "web")]
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext, WebGlRenderingContext as WebGl};
+use crate::model::gl as GL;
+
+/// Graphics API of a context
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackendKind {
+    WebGl1,
+    WebGl2,
+    WebGpu,
+}
+
+impl BackendKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "webgl" | "webgl1" => Some(BackendKind::WebGl1),
+            "webgl2" => Some(BackendKind::WebGl2),
+            "webgpu" => Some(BackendKind::WebGpu),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::WebGl1 => "webgl1",
+            BackendKind::WebGl2 => "webgl2",
+            BackendKind::WebGpu => "webgpu",
+        }
+    }
+
+    /// Context id passed to `getContext`
+    #[cfg(feature = "web")]
+    fn context_id(&self) -> &'static str {
+        match self {
+            BackendKind::WebGl1 => "webgl",
+            BackendKind::WebGl2 => "webgl2",
+            BackendKind::WebGpu => "webgpu",
+        }
+    }
+
+    /// Backend of an existing context
+    #[cfg(feature = "web")]
+    pub fn of(gl: &WebGl) -> Self {
+        if webgl2(gl).is_some() { BackendKind::WebGl2 } else { BackendKind::WebGl1 }
+    }
+}
+
+/// The same context typed for WebGL2 calls, `None` for WebGL1 contexts, `WebGlDevice` resolves it once
+#[cfg(feature = "web")]
+pub fn webgl2(gl: &WebGl) -> Option<WebGl2RenderingContext> {
+    gl.dyn_ref::<WebGl2RenderingContext>().cloned()
+}
+
+/// Creates a context of the requested kind, WebGL2 falls back to WebGL1 when the browser lacks it
+#[cfg(feature = "web")]
+pub fn create_context(canvas: &HtmlCanvasElement, kind: BackendKind) -> Result<WebGl, JsValue> {
+    if kind == BackendKind::WebGpu {
+        return Err("WebGPU contexts are not WebGL contexts".into());
+    }
+    if kind == BackendKind::WebGl2 {
+        if let Some(context) = canvas.get_context(kind.context_id())? {
+            // Shared code only uses the WebGL1 subset of the API
+            return Ok(context.unchecked_into::<WebGl>());
+        }
+    }
+
+    let context = canvas
+        .get_context(BackendKind::WebGl1.context_id())?
+        .ok_or_else(|| JsValue::from_str("WebGL is not supported"))?;
+
+    Ok(context.dyn_into::<WebGl>()?)
+}
+
+/// Element type of an index buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexType {
+    U16,
+    U32,
+}
+
+impl IndexType {
+    /// Smallest type holding `vertex_count` vertices, `None` when indices don't fit what the device takes
+    ///
+    /// 32-bit indices are core in WebGL2 and need `OES_element_index_uint` in WebGL1.
+    pub fn pick(vertex_count: usize, uint_indices: bool) -> Option<Self> {
+        if vertex_count <= u16::MAX as usize + 1 {
+            Some(IndexType::U16)
+        } else if uint_indices {
+            Some(IndexType::U32)
+        } else {
+            None
+        }
+    }
+
+    pub fn gl_type(&self) -> u32 {
+        match self {
+            IndexType::U16 => GL::UNSIGNED_SHORT,
+            IndexType::U32 => GL::UNSIGNED_INT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_are_named_like_their_context_ids() {
+        assert_eq!(BackendKind::from_name("webgl"), Some(BackendKind::WebGl1));
+        assert_eq!(BackendKind::from_name("webgl2"), Some(BackendKind::WebGl2));
+        assert_eq!(BackendKind::from_name("webgpu"), Some(BackendKind::WebGpu));
+        assert_eq!(BackendKind::from_name("vulkan"), None);
+        assert_eq!(BackendKind::from_name(BackendKind::WebGl2.name()), Some(BackendKind::WebGl2));
+    }
+
+    #[test]
+    fn indices_use_the_smallest_type() {
+        assert_eq!(IndexType::pick(3, false), Some(IndexType::U16));
+        assert_eq!(IndexType::pick(65536, false), Some(IndexType::U16));
+        assert_eq!(IndexType::pick(65537, false), None);
+        assert_eq!(IndexType::pick(65537, true), Some(IndexType::U32));
+    }
+}
@@ -0,0 +1,157 @@
+use nalgebra as na;
+use na::{Matrix4, Point3, Vector3};
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Computes the box enclosing flat xyz position data
+    pub fn from_positions(positions: &[f32]) -> Self {
+        if positions.len() < 3 {
+            return Self::new(Vector3::zeros(), Vector3::zeros());
+        }
+
+        let mut min = Vector3::repeat(f32::MAX);
+        let mut max = Vector3::repeat(f32::MIN);
+
+        for p in positions.chunks_exact(3) {
+            let p = Vector3::new(p[0], p[1], p[2]);
+            min = min.inf(&p);
+            max = max.sup(&p);
+        }
+
+        Self::new(min, max)
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Returns the box enclosing this box after an affine transform (Arvo's method)
+    pub fn transform(&self, m: &Matrix4<f32>) -> Self {
+        let center = m.transform_point(&Point3::from(self.center())).coords;
+        let extents = self.extents();
+
+        let mut new_extents = Vector3::zeros();
+        for i in 0..3 {
+            for j in 0..3 {
+                new_extents[i] += m[(i, j)].abs() * extents[j];
+            }
+        }
+
+        Self::new(center - new_extents, center + new_extents)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Sphere centered on the box center containing every position
+    pub fn from_positions(positions: &[f32], aabb: &Aabb) -> Self {
+        let center = aabb.center();
+        let radius_sq = positions
+            .chunks_exact(3)
+            .map(|p| (Vector3::new(p[0], p[1], p[2]) - center).norm_squared())
+            .fold(0.0f32, f32::max);
+
+        Self::new(center, radius_sq.sqrt())
+    }
+
+    /// Transforms the sphere, the radius is scaled by the largest axis scale
+    pub fn transform(&self, m: &Matrix4<f32>) -> Self {
+        let center = m.transform_point(&Point3::from(self.center)).coords;
+        let scale = (0..3)
+            .map(|j| m.fixed_slice::<na::U3, na::U1>(0, j).norm())
+            .fold(0.0f32, f32::max);
+
+        Self::new(center, self.radius * scale)
+    }
+}
+
+/// Bounds computed once per geometry and transformed per node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingVolume {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl BoundingVolume {
+    pub fn from_positions(positions: &[f32]) -> Self {
+        let aabb = Aabb::from_positions(positions);
+        let sphere = BoundingSphere::from_positions(positions, &aabb);
+
+        Self { aabb, sphere }
+    }
+
+    pub fn transform(&self, m: &Matrix4<f32>) -> Self {
+        Self {
+            aabb: self.aabb.transform(m),
+            sphere: self.sphere.transform(m),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_CORNERS: [f32; 6] = [-1.0, -2.0, -3.0, 1.0, 2.0, 3.0];
+
+    #[test]
+    fn aabb_encloses_positions() {
+        let aabb = Aabb::from_positions(&[0.0, 5.0, -1.0, 2.0, -3.0, 4.0, 1.0, 1.0, 1.0]);
+        assert_eq!(aabb.min, Vector3::new(0.0, -3.0, -1.0));
+        assert_eq!(aabb.max, Vector3::new(2.0, 5.0, 4.0));
+    }
+
+    #[test]
+    fn sphere_contains_all_positions() {
+        let volume = BoundingVolume::from_positions(&CUBE_CORNERS);
+        assert_eq!(volume.sphere.center, Vector3::zeros());
+        assert!((volume.sphere.radius - 14.0f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn translation_moves_bounds() {
+        let volume = BoundingVolume::from_positions(&CUBE_CORNERS);
+        let moved = volume.transform(&Matrix4::new_translation(&Vector3::new(10.0, 0.0, -5.0)));
+
+        assert_eq!(moved.aabb.min, Vector3::new(9.0, -2.0, -8.0));
+        assert_eq!(moved.aabb.max, Vector3::new(11.0, 2.0, -2.0));
+        assert_eq!(moved.sphere.center, Vector3::new(10.0, 0.0, -5.0));
+        assert!((moved.sphere.radius - volume.sphere.radius).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rotation_grows_aabb_and_scale_grows_sphere() {
+        let volume = BoundingVolume::from_positions(&[-1.0, -1.0, 0.0, 1.0, 1.0, 0.0]);
+
+        let rotated = volume.aabb.transform(&Matrix4::from_euler_angles(0.0, 0.0, std::f32::consts::FRAC_PI_4));
+        let half = 2.0f32.sqrt();
+        assert!((rotated.max.x - half).abs() < 1e-5);
+        assert!((rotated.max.y - half).abs() < 1e-5);
+
+        let scaled = volume.sphere.transform(&Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 3.0, 2.0)));
+        assert!((scaled.radius - 3.0 * volume.sphere.radius).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,183 @@
+//! CPU reference of `shader/chunks/brdf.glsl`, kept in sync to test shading without a GPU
+
+use std::f32::consts::PI;
+use nalgebra::Vector3;
+use crate::model::Light;
+use crate::model::light::LightKind;
+
+pub const MIN_ROUGHNESS: f32 = 0.045;
+/// Dielectric reflectance at normal incidence
+pub const DIELECTRIC_F0: f32 = 0.04;
+
+/// GGX / Trowbridge-Reitz normal distribution, alpha = roughness^2
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+    a2 / (PI * d * d)
+}
+
+/// Schlick-GGX masking for one direction, k remapped for analytic lights
+pub fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+
+    n_dot_x / (n_dot_x * (1.0 - k) + k)
+}
+
+/// Smith shadowing-masking
+pub fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness)
+}
+
+pub fn fresnel_schlick(cos_theta: f32, f0: Vector3<f32>) -> Vector3<f32> {
+    let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    f0 + (Vector3::repeat(1.0) - f0) * weight
+}
+
+/// Surface parameters after texture lookups
+#[derive(Clone, Copy, Debug)]
+pub struct Surface {
+    pub normal: Vector3<f32>,
+    pub albedo: Vector3<f32>,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+/// Radiance reflected towards `v` from light arriving along `l`, all vectors normalized
+pub fn brdf_direct(surface: &Surface, v: Vector3<f32>, l: Vector3<f32>, radiance: Vector3<f32>) -> Vector3<f32> {
+    let n = surface.normal;
+    let roughness = surface.roughness.clamp(MIN_ROUGHNESS, 1.0);
+    let h = (v + l).normalize();
+
+    let n_dot_v = n.dot(&v).max(1e-4);
+    let n_dot_l = n.dot(&l).max(0.0);
+    let n_dot_h = n.dot(&h).max(0.0);
+    let h_dot_v = h.dot(&v).max(0.0);
+
+    let f0 = Vector3::repeat(DIELECTRIC_F0).lerp(&surface.albedo, surface.metallic);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    let f = fresnel_schlick(h_dot_v, f0);
+
+    let specular = f * (d * g / (4.0 * n_dot_v * n_dot_l + 1e-4));
+    // Metals have no diffuse term, energy reflected specularly is not diffused
+    let kd = (Vector3::repeat(1.0) - f) * (1.0 - surface.metallic);
+
+    (kd.component_mul(&surface.albedo) / PI + specular).component_mul(&radiance) * n_dot_l
+}
+
+/// Normalized direction towards the light and incoming radiance at a point, as the shader light loop computes them
+pub fn light_incident(light: &Light, point: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let position = Vector3::from(light.position);
+    let radiance = Vector3::from(light.radiance_uniform());
+
+    match light.kind {
+        LightKind::Directional => (position.normalize(), radiance),
+        LightKind::Point => {
+            let l = position - point;
+            let distance2 = l.norm_squared().max(1e-4);
+            (l.normalize(), radiance / distance2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn surface(albedo: Vector3<f32>, metallic: f32, roughness: f32) -> Surface {
+        Surface { normal: Vector3::z(), albedo, metallic, roughness }
+    }
+
+    #[test]
+    fn ggx_peaks_match_closed_form() {
+        // Fully rough: constant 1/pi over the hemisphere
+        assert!((distribution_ggx(1.0, 1.0) - 1.0 / PI).abs() < EPSILON);
+        assert!((distribution_ggx(0.3, 1.0) - 1.0 / PI).abs() < EPSILON);
+        // alpha = 0.25 at the peak: 1 / (pi * alpha^2)
+        assert!((distribution_ggx(1.0, 0.5) - 1.0 / (PI * 0.0625)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn smith_is_one_facing_the_normal() {
+        for roughness in [0.1, 0.5, 1.0].iter() {
+            assert!((geometry_smith(1.0, 1.0, *roughness) - 1.0).abs() < EPSILON);
+        }
+        // k = 0.5 for roughness 1: 0.5 / (0.5 * 0.5 + 0.5)
+        assert!((geometry_schlick_ggx(0.5, 1.0) - 2.0 / 3.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn schlick_goes_from_f0_to_white() {
+        let f0 = Vector3::new(0.04, 0.5, 1.0);
+
+        assert!((fresnel_schlick(1.0, f0) - f0).norm() < EPSILON);
+        assert!((fresnel_schlick(0.0, f0) - Vector3::repeat(1.0)).norm() < EPSILON);
+        // (1 - 0.5)^5 = 1/32
+        assert!((fresnel_schlick(0.5, Vector3::zeros()).x - 1.0 / 32.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn white_dielectric_lit_head_on() {
+        let n = Vector3::z();
+        let out = brdf_direct(&surface(Vector3::repeat(1.0), 0.0, 1.0), n, n, Vector3::repeat(1.0));
+
+        // D = 1/pi, G = 1, F = 0.04
+        let expected = 0.96 / PI + (1.0 / PI) * 0.04 / (4.0 + 1e-4);
+        assert!((out.x - expected).abs() < EPSILON);
+        assert!((out.x - 0.30876).abs() < EPSILON);
+    }
+
+    #[test]
+    fn metals_reflect_only_their_albedo() {
+        let n = Vector3::z();
+        let out = brdf_direct(&surface(Vector3::new(1.0, 0.0, 0.0), 1.0, 0.5), n, n, Vector3::repeat(1.0));
+
+        assert!(out.x > 0.0);
+        assert_eq!((out.y, out.z), (0.0, 0.0));
+    }
+
+    #[test]
+    fn light_below_the_horizon_contributes_nothing() {
+        let l = Vector3::new(0.0, 0.6, -0.8);
+        let out = brdf_direct(&surface(Vector3::repeat(1.0), 0.0, 0.5), Vector3::z(), l, Vector3::repeat(1.0));
+
+        assert_eq!(out, Vector3::zeros());
+    }
+
+    #[test]
+    fn smoother_surfaces_have_sharper_highlights() {
+        let v = Vector3::new(0.0, 0.6, 0.8);
+        let mirror = Vector3::new(0.0, -0.6, 0.8);
+        let off_mirror = Vector3::new(0.0, -0.2, 1.0).normalize();
+        let radiance = Vector3::repeat(1.0);
+
+        let smooth = surface(Vector3::repeat(0.0), 0.0, 0.2);
+        let rough = surface(Vector3::repeat(0.0), 0.0, 0.9);
+
+        assert!(brdf_direct(&smooth, v, mirror, radiance).x > brdf_direct(&rough, v, mirror, radiance).x);
+        assert!(brdf_direct(&smooth, v, mirror, radiance).x > brdf_direct(&smooth, v, off_mirror, radiance).x);
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_distance_squared() {
+        let light = Light { position: [0.0, 0.0, 2.0], intensity: 4.0, ..Light::default() };
+        let (l, radiance) = light_incident(&light, &Vector3::zeros());
+
+        assert_eq!(l, Vector3::z());
+        assert!((radiance.x - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn directional_lights_are_constant() {
+        let light = Light { kind: LightKind::Directional, position: [0.0, 3.0, 0.0], intensity: 2.0, ..Light::default() };
+        let (l, radiance) = light_incident(&light, &Vector3::new(10.0, -5.0, 3.0));
+
+        assert_eq!(l, Vector3::y());
+        assert_eq!(radiance, Vector3::repeat(2.0));
+    }
+}
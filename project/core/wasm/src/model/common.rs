@@ -26,7 +26,7 @@ pub fn obj_file_data_to_vertex_vector_data(obj_file_data: &str) -> (Vec<f32>, Ve
         // let line = line.unwrap();
         let vec_split: Vec<&str> = line.split_whitespace().collect();
 
-        if vec_split.len() == 0 {
+        if vec_split.is_empty() {
             continue;
         }
 
@@ -47,12 +47,12 @@ pub fn obj_file_data_to_vertex_vector_data(obj_file_data: &str) -> (Vec<f32>, Ve
         } else if vec_split[0] == "f" {
 
             let mut v_points = vec![];
-            for i in 1..vec_split.len() {
-                if vec_split[i] == "\r" {
+            for point in vec_split.iter().skip(1) {
+                if *point == "\r" {
                     continue;
                 }
 
-                v_points.push(vec_split[i]);
+                v_points.push(*point);
             }
 
             let mut index = 0;
@@ -63,15 +63,15 @@ pub fn obj_file_data_to_vertex_vector_data(obj_file_data: &str) -> (Vec<f32>, Ve
                 index += 1;
             }
 
-            for j in 0..triangles.len() {
+            for triangle in triangles.iter() {
                 triangles_count += 1;
 
                 let mut vs = vec![];
                 let mut uvs = vec![];
                 let mut temp_vec_vertex = vec![];
 
-                for k in 0..triangles[j].len() {
-                    let vec_split: Vec<&str> = triangles[j][k].split("/").collect();
+                for point in triangle.iter() {
+                    let vec_split: Vec<&str> = point.split('/').collect();
 
                     let ind1: usize = vec_split[0].parse().unwrap();
                     let ind2: usize = vec_split[1].parse().unwrap();
@@ -92,7 +92,7 @@ pub fn obj_file_data_to_vertex_vector_data(obj_file_data: &str) -> (Vec<f32>, Ve
                 let delta_uv_1 = uvs[1].sub(&uvs[0]);
                 let delta_uv_2 = uvs[2].sub(&uvs[0]);
 
-                let r = 1.0 / (&delta_uv_1.data[0] * &delta_uv_2.data[1] - &delta_uv_1.data[1] * &delta_uv_2.data[0]);
+                let r = 1.0 / (delta_uv_1.data[0] * delta_uv_2.data[1] - delta_uv_1.data[1] * delta_uv_2.data[0]);
 
                 // delta_pos_1 * delta_uv_2.y
                 let tangent_mul_1 = delta_pos_1.mul(delta_uv_2.data[1].to_owned());
@@ -112,8 +112,7 @@ pub fn obj_file_data_to_vertex_vector_data(obj_file_data: &str) -> (Vec<f32>, Ve
                 let res = bitangent_sub.mul(r.to_owned());
                 let bitangent = [res.data[0], res.data[1], res.data[2]];
 
-                for i in 0..temp_vec_vertex.len() {
-                    let temp_vertex = temp_vec_vertex[i];
+                for temp_vertex in temp_vec_vertex.iter() {
 
                     vec_obj_vertices.push(temp_vertex.0[0]);
                     vec_obj_vertices.push(temp_vertex.0[1]);
@@ -136,4 +135,17 @@ pub fn obj_file_data_to_vertex_vector_data(obj_file_data: &str) -> (Vec<f32>, Ve
     }
 
     (vec_obj_vertices, vec_obj_uvs, vec_obj_tangents, vec_obj_bitangent, triangles_count, vertex_count)
-}
\ No newline at end of file
+}
+/// Milliseconds from `performance.now()`, 0 when the page has no performance API
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0.0, |performance| performance.now())
+}
+
+/// Timings are 0 outside of the browser, where tests run
+#[cfg(not(all(target_arch = "wasm32", feature = "web")))]
+pub fn now_ms() -> f64 {
+    0.0
+}
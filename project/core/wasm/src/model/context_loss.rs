@@ -0,0 +1,156 @@
+//! Tracks `webglcontextlost` / `webglcontextrestored` so drawing pauses and resources get rebuilt
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::{Event, HtmlCanvasElement, WebglLoseContext};
+
+/// What the next frame has to do given the events seen since the last one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameAction {
+    Draw,
+    /// The context is lost, every GL call would be a no-op
+    Skip,
+    /// The context came back empty, GPU resources have to be created again before drawing
+    Restore,
+}
+
+/// Loss state shared with the event listeners
+#[derive(Debug, Default)]
+pub struct LossTracker {
+    lost: bool,
+    restore_pending: bool,
+    pub losses: u32,
+}
+
+impl LossTracker {
+    pub fn on_lost(&mut self) {
+        if !self.lost {
+            self.lost = true;
+            self.losses += 1;
+        }
+        // Resources rebuilt for a context that is gone again are useless
+        self.restore_pending = false;
+    }
+
+    pub fn on_restored(&mut self) {
+        if self.lost {
+            self.lost = false;
+            self.restore_pending = true;
+        }
+    }
+
+    /// Consumes a pending restore
+    pub fn next_frame(&mut self) -> FrameAction {
+        if self.lost {
+            FrameAction::Skip
+        } else if std::mem::take(&mut self.restore_pending) {
+            FrameAction::Restore
+        } else {
+            FrameAction::Draw
+        }
+    }
+}
+
+/// Listens for loss events on a canvas, listeners are removed on drop
+pub struct ContextLossMonitor {
+    canvas: HtmlCanvasElement,
+    tracker: Rc<RefCell<LossTracker>>,
+    on_lost: Closure<dyn FnMut(Event)>,
+    on_restored: Closure<dyn FnMut(Event)>,
+    /// `WEBGL_lose_context`, queried up front since extensions can't be fetched from a lost context
+    extension: Option<WebglLoseContext>,
+}
+
+impl ContextLossMonitor {
+    pub fn new(canvas: &HtmlCanvasElement, gl: &GL) -> Result<Self, JsValue> {
+        let tracker = Rc::new(RefCell::new(LossTracker::default()));
+
+        let lost_tracker = tracker.clone();
+        let on_lost = Closure::wrap(Box::new(move |event: Event| {
+            // Without this the browser never restores the context
+            event.prevent_default();
+            lost_tracker.borrow_mut().on_lost();
+        }) as Box<dyn FnMut(Event)>);
+
+        let restored_tracker = tracker.clone();
+        let on_restored = Closure::wrap(Box::new(move |_: Event| {
+            restored_tracker.borrow_mut().on_restored();
+        }) as Box<dyn FnMut(Event)>);
+
+        canvas.add_event_listener_with_callback("webglcontextlost", on_lost.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("webglcontextrestored", on_restored.as_ref().unchecked_ref())?;
+
+        let extension = gl.get_extension("WEBGL_lose_context")?.map(|extension| extension.unchecked_into());
+
+        Ok(Self { canvas: canvas.clone(), tracker, on_lost, on_restored, extension })
+    }
+
+    pub fn next_frame(&self) -> FrameAction {
+        self.tracker.borrow_mut().next_frame()
+    }
+
+    pub fn losses(&self) -> u32 {
+        self.tracker.borrow().losses
+    }
+
+    /// Loses the context through `WEBGL_lose_context`, events arrive asynchronously
+    pub fn lose_context(&self) -> Result<(), String> {
+        self.extension.as_ref().ok_or("WEBGL_lose_context is not available")?.lose_context();
+        Ok(())
+    }
+
+    /// Asks the browser to restore a context lost through `lose_context`
+    pub fn restore_context(&self) -> Result<(), String> {
+        self.extension.as_ref().ok_or("WEBGL_lose_context is not available")?.restore_context();
+        Ok(())
+    }
+}
+
+impl Drop for ContextLossMonitor {
+    fn drop(&mut self) {
+        let _ = self.canvas.remove_event_listener_with_callback("webglcontextlost", self.on_lost.as_ref().unchecked_ref());
+        let _ = self.canvas.remove_event_listener_with_callback("webglcontextrestored", self.on_restored.as_ref().unchecked_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_skipped_until_the_context_is_back() {
+        let mut tracker = LossTracker::default();
+        assert_eq!(tracker.next_frame(), FrameAction::Draw);
+
+        tracker.on_lost();
+        assert_eq!(tracker.next_frame(), FrameAction::Skip);
+        assert_eq!(tracker.next_frame(), FrameAction::Skip);
+
+        tracker.on_restored();
+        assert_eq!(tracker.next_frame(), FrameAction::Restore);
+        assert_eq!(tracker.next_frame(), FrameAction::Draw);
+        assert_eq!(tracker.losses, 1);
+    }
+
+    #[test]
+    fn a_loss_before_the_restore_is_handled_cancels_it() {
+        let mut tracker = LossTracker::default();
+        tracker.on_lost();
+        tracker.on_restored();
+        tracker.on_lost();
+
+        assert_eq!(tracker.next_frame(), FrameAction::Skip);
+        assert_eq!(tracker.losses, 2);
+
+        // Duplicate events count once
+        tracker.on_lost();
+        assert_eq!(tracker.losses, 2);
+        tracker.on_restored();
+        tracker.on_restored();
+        assert_eq!(tracker.next_frame(), FrameAction::Restore);
+        assert_eq!(tracker.next_frame(), FrameAction::Draw);
+    }
+}
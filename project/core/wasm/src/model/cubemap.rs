@@ -0,0 +1,292 @@
+use std::f32::consts::PI;
+use nalgebra::Vector3;
+use crate::model::hdr::HdrImage;
+
+/// Cube faces in GL order, `TEXTURE_CUBE_MAP_POSITIVE_X + index`
+pub const FACE_COUNT: usize = 6;
+
+/// Direction through the center of texel (x, y) of a face, following the GL cube map convention
+pub fn face_direction(face: usize, x: u32, y: u32, size: u32) -> Vector3<f32> {
+    let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+    let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+
+    let direction = match face {
+        0 => Vector3::new(1.0, -t, -s),
+        1 => Vector3::new(-1.0, -t, s),
+        2 => Vector3::new(s, 1.0, t),
+        3 => Vector3::new(s, -1.0, -t),
+        4 => Vector3::new(s, -t, 1.0),
+        _ => Vector3::new(-s, -t, -1.0),
+    };
+
+    direction.normalize()
+}
+
+/// Face and texture coordinates in [0, 1] hit by a direction
+pub fn direction_to_face(direction: &Vector3<f32>) -> (usize, f32, f32) {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+    } else if ay >= az {
+        if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+
+    (face, (sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5)
+}
+
+/// Exact solid angle covered by texel (x, y) of a face
+pub fn texel_solid_angle(x: u32, y: u32, size: u32) -> f32 {
+    let edge = |i: u32| 2.0 * i as f32 / size as f32 - 1.0;
+    let (s0, s1, t0, t1) = (edge(x), edge(x + 1), edge(y), edge(y + 1));
+
+    // Solid angle of the face region between the face center and (s, t)
+    let area = |s: f32, t: f32| (s * t).atan2((s * s + t * t + 1.0).sqrt());
+
+    area(s1, t1) - area(s0, t1) - area(s1, t0) + area(s0, t0)
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u8
+}
+
+/// Linear RGB cube map kept on the CPU for precomputation
+#[derive(Clone, Debug)]
+pub struct CubeImage {
+    pub size: u32,
+    /// Row-major texels of each face, row 0 is `t = 0`
+    pub faces: Vec<Vec<[f32; 3]>>,
+}
+
+impl CubeImage {
+    /// Cube filled by evaluating a function of the direction through every texel
+    pub fn from_fn<F: Fn(&Vector3<f32>) -> [f32; 3]>(size: u32, radiance: F) -> Self {
+        let faces = (0..FACE_COUNT)
+            .map(|face| {
+                (0..size * size)
+                    .map(|i| radiance(&face_direction(face, i % size, i / size, size)))
+                    .collect()
+            })
+            .collect();
+
+        Self { size, faces }
+    }
+
+    /// Cube from six square linear images in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn from_faces(images: &[HdrImage]) -> Result<Self, String> {
+        if images.len() != FACE_COUNT {
+            return Err(format!("A cube map needs {} faces, got {}", FACE_COUNT, images.len()));
+        }
+
+        let size = images[0].width;
+        if images.iter().any(|image| image.width != size || image.height != size) {
+            return Err("Cube map faces must be square and of equal size".to_string());
+        }
+        check_size(size)?;
+
+        Ok(Self { size, faces: images.iter().map(HdrImage::texels).collect() })
+    }
+
+    /// Resamples an equirectangular (latitude-longitude) panorama, +Y is the top row
+    pub fn from_equirect(image: &HdrImage, size: u32) -> Result<Self, String> {
+        let (width, height) = (image.width, image.height);
+        if image.data.len() != (width * height * 3) as usize || width == 0 || height == 0 {
+            return Err("Equirectangular image size does not match its data".to_string());
+        }
+        check_size(size)?;
+        let texels = image.texels();
+
+        Ok(Self::from_fn(size, |direction| {
+            // -Z maps to the image center
+            let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+            let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+            sample_bilinear(&texels, width, height, u * width as f32 - 0.5, v * height as f32 - 0.5, true)
+        }))
+    }
+
+    /// Bilinearly filtered radiance along a direction, filtering does not cross face edges
+    pub fn sample(&self, direction: &Vector3<f32>) -> [f32; 3] {
+        let (face, s, t) = direction_to_face(direction);
+        let size = self.size as f32;
+
+        sample_bilinear(&self.faces[face], self.size, self.size, s * size - 0.5, t * size - 0.5, false)
+    }
+
+    /// Downsamples by averaging 2x2 texel blocks, used for the coarsest mip levels
+    pub fn half_size(&self) -> Self {
+        let size = (self.size / 2).max(1);
+        let scale = self.size / size;
+
+        let faces = self.faces
+            .iter()
+            .map(|face| {
+                (0..size * size)
+                    .map(|i| {
+                        let (x, y) = (i % size, i / size);
+                        let mut sum = [0.0; 3];
+                        for dy in 0..scale {
+                            for dx in 0..scale {
+                                let texel = face[((y * scale + dy) * self.size + x * scale + dx) as usize];
+                                for c in 0..3 {
+                                    sum[c] += texel[c];
+                                }
+                            }
+                        }
+                        let count = (scale * scale) as f32;
+                        [sum[0] / count, sum[1] / count, sum[2] / count]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { size, faces }
+    }
+
+    /// Linear RGB data of a face, ready for float uploads
+    pub fn face_rgb(&self, face: usize) -> Vec<f32> {
+        self.faces[face].iter().flat_map(|texel| texel.to_vec()).collect()
+    }
+
+    /// 8-bit sRGB RGB data of a face, ready for `tex_image_2d`
+    pub fn face_rgb8(&self, face: usize) -> Vec<u8> {
+        self.faces[face]
+            .iter()
+            .flat_map(|texel| texel.iter().map(|c| linear_to_srgb(*c)).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+/// Bilinear lookup at continuous texel coordinates, wrapping horizontally or clamping
+/// The prefiltered mip chain needs a power of two, WebGL 1 leaves other sizes incomplete
+fn check_size(size: u32) -> Result<(), String> {
+    if size.is_power_of_two() {
+        Ok(())
+    } else {
+        Err(format!("Cube map size must be a power of two, got {}", size))
+    }
+}
+
+fn sample_bilinear(texels: &[[f32; 3]], width: u32, height: u32, x: f32, y: f32, wrap_x: bool) -> [f32; 3] {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let column = |x: i64| -> u32 {
+        if wrap_x {
+            x.rem_euclid(width as i64) as u32
+        } else {
+            x.clamp(0, width as i64 - 1) as u32
+        }
+    };
+    let row = |y: i64| -> u32 { y.clamp(0, height as i64 - 1) as u32 };
+    let texel = |x: u32, y: u32| texels[(y * width + x) as usize];
+
+    let (xa, xb) = (column(x0 as i64), column(x0 as i64 + 1));
+    let (ya, yb) = (row(y0 as i64), row(y0 as i64 + 1));
+
+    let mut out = [0.0; 3];
+    for (c, value) in out.iter_mut().enumerate() {
+        let top = texel(xa, ya)[c] * (1.0 - fx) + texel(xb, ya)[c] * fx;
+        let bottom = texel(xa, yb)[c] * (1.0 - fx) + texel(xb, yb)[c] * fx;
+        *value = top * (1.0 - fy) + bottom * fy;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::hdr::srgb_to_linear;
+
+    #[test]
+    fn face_directions_round_trip() {
+        let size = 4;
+        for face in 0..FACE_COUNT {
+            for (x, y) in [(0, 0), (3, 1), (2, 3)].iter() {
+                let (hit, s, t) = direction_to_face(&face_direction(face, *x, *y, size));
+
+                assert_eq!(hit, face);
+                assert!((s * size as f32 - (*x as f32 + 0.5)).abs() < 1e-4);
+                assert!((t * size as f32 - (*y as f32 + 0.5)).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn face_centers_point_along_the_axes() {
+        let centers = [
+            Vector3::x(), -Vector3::x(), Vector3::y(), -Vector3::y(), Vector3::z(), -Vector3::z(),
+        ];
+        for (face, center) in centers.iter().enumerate() {
+            // Texel (1, 1) of a 3x3 face is its center
+            assert!((face_direction(face, 1, 1, 3) - center).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn solid_angles_cover_the_sphere() {
+        let size = 16;
+        let total: f32 = (0..size * size).map(|i| texel_solid_angle(i % size, i / size, size)).sum::<f32>() * 6.0;
+
+        assert!((total - 4.0 * PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn equirect_top_rows_map_to_the_positive_y_face() {
+        // Upper half red, lower half blue
+        let (width, height) = (16, 8);
+        let data = (0..width * height)
+            .flat_map(|i| if i / width < height / 2 { vec![1.0, 0.0, 0.0] } else { vec![0.0, 0.0, 1.0] })
+            .collect();
+
+        let cube = CubeImage::from_equirect(&HdrImage { width, height, data }, 4).unwrap();
+
+        assert_eq!(cube.sample(&Vector3::y()), [1.0, 0.0, 0.0]);
+        assert_eq!(cube.sample(&-Vector3::y()), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn faces_must_be_square() {
+        let face = |width: u32, height: u32| HdrImage { width, height, data: vec![0.0; (width * height * 3) as usize] };
+        let mut faces: Vec<HdrImage> = (0..6).map(|_| face(2, 2)).collect();
+        assert!(CubeImage::from_faces(&faces).is_ok());
+
+        faces[3] = face(4, 2);
+        assert!(CubeImage::from_faces(&faces).is_err());
+        assert!(CubeImage::from_faces(&faces[..5]).is_err());
+    }
+
+    #[test]
+    fn sizes_must_be_powers_of_two() {
+        let face = |size: u32| HdrImage { width: size, height: size, data: vec![0.0; (size * size * 3) as usize] };
+        assert!(CubeImage::from_faces(&vec![face(3); 6]).is_err());
+        assert!(CubeImage::from_faces(&vec![face(0); 6]).is_err());
+
+        let equirect = face(2);
+        assert!(CubeImage::from_equirect(&equirect, 4).is_ok());
+        assert!(CubeImage::from_equirect(&equirect, 6).is_err());
+        assert!(CubeImage::from_equirect(&equirect, 0).is_err());
+    }
+
+    #[test]
+    fn half_size_averages_blocks() {
+        let cube = CubeImage::from_fn(4, |direction| [direction.x.max(0.0), 1.0, 0.0]);
+        let half = cube.half_size();
+
+        assert_eq!(half.size, 2);
+        assert!((half.faces[2].iter().map(|t| t[1]).sum::<f32>() - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_encoding_round_trips() {
+        for value in [0u8, 1, 64, 128, 200, 255].iter() {
+            assert_eq!(linear_to_srgb(srgb_to_linear(*value)), *value);
+        }
+    }
+}
@@ -1,81 +1,202 @@
-use web_sys::{WebGlRenderingContext as GL, WebGlUniformLocation};
-use crate::model::{program, Primitive};
-use std::collections::HashMap;
-
-pub struct DefaultPipeline {
-    pub program: program::Program,
-    pub perspective_loc: Option<WebGlUniformLocation>,
-    pub model_view_loc: Option<WebGlUniformLocation>,
-    pub extra_uniforms: HashMap<String, Option<WebGlUniformLocation>>,
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use crate::model::gl as GL;
+use crate::model::{program, GlState, Primitive, UniformValue};
+use crate::model::device::GraphicsDevice;
+use crate::model::frame_uniforms::bind_frame_block;
+
+/// Built-in metallic-roughness shaders, selected with `shading: "pbr"`
+pub const PBR_VERT: &str = include_str!("../shader/pbr/pbr.vert.glsl");
+pub const PBR_FRAG: &str = include_str!("../shader/pbr/pbr.frag.glsl");
+
+static NEXT_PIPELINE_ID: AtomicU32 = AtomicU32::new(1);
+
+pub struct DefaultPipeline<D: GraphicsDevice> {
+    pub program: Rc<program::Program<D>>,
+    /// Unique id keying the vertex arrays of primitives drawn with this pipeline
+    pub id: u32,
     pub vert_pos_loc: i32,
     pub vert_uv_loc: i32,
     pub vert_tang_loc: i32,
-    pub vert_bitang_loc: i32
+    pub vert_bitang_loc: i32,
+    /// First of the four locations of the `instance_model` matrix, -1 when drawn with a `model_view` uniform
+    pub instance_loc: i32,
+    /// Model matrices of instanced draws, only on devices supporting instancing
+    instance_buffer: Option<D::Buffer>,
 }
 
-impl DefaultPipeline {
-    pub fn new(gl: &GL, vert_src: &str, frag_src: &str) -> Self {
-        let program = program::Program::new(gl.clone(), vert_src, frag_src);
+impl<D: GraphicsDevice> DefaultPipeline<D> {
+    /// Resolves the fixed attribute layout of an already linked program
+    pub fn from_program(program: Rc<program::Program<D>>) -> Self {
         program.bind();
 
-        let perspective_loc = program.get_uniform_loc("perspective");
-        let model_view_loc = program.get_uniform_loc("model_view");
-
         let vert_pos_loc = program.get_attrib_loc("vert_pos");
         let vert_uv_loc = program.get_attrib_loc("vert_uv");
         let vert_tang_loc = program.get_attrib_loc("vert_tang");
         let vert_bitang_loc = program.get_attrib_loc("vert_bitang");
+        let instance_loc = program.get_attrib_loc("instance_model");
 
-        let extra_uniforms: HashMap<String, Option<WebGlUniformLocation>> = HashMap::new();
+        let instance_buffer = if instance_loc >= 0 && program.device.features().instancing {
+            program.device.create_buffer()
+        } else {
+            None
+        };
+        bind_frame_block(&program);
 
         Self {
             program,
-            perspective_loc,
-            extra_uniforms,
-            model_view_loc,
+            id: NEXT_PIPELINE_ID.fetch_add(1, Ordering::Relaxed),
             vert_pos_loc,
             vert_uv_loc,
             vert_tang_loc,
-            vert_bitang_loc
+            vert_bitang_loc,
+            instance_loc,
+            instance_buffer,
+        }
+    }
+
+    /// Nodes sharing a primitive can be drawn with one `draw_instanced` call
+    pub fn supports_instancing(&self) -> bool {
+        self.instance_buffer.is_some()
+    }
+
+    pub fn set_perspective(&self, perspective: &[f32]) -> Result<(), String> {
+        let mut matrix = [0.0; 16];
+        matrix.copy_from_slice(perspective);
+
+        self.program.set_optional_uniform("perspective", UniformValue::Mat4(matrix))
+    }
+
+    /// Binds the vertex attributes of the primitive unless they already are
+    ///
+    /// With vertex arrays the layout is recorded once into one of the primitive.
+    pub fn bind(&self, primitive: &Primitive<D>, state: &mut GlState<D>) {
+        if state.features.vertex_arrays {
+            let vertex_array = primitive.vertex_array(self.id, |vertex_array| {
+                state.bind_vertex_array(Some(vertex_array));
+                for loc in self.set_attribute_pointers(primitive, state) {
+                    self.program.device.set_attrib_array(loc, true);
+                }
+                self.set_instance_pointers(state);
+                primitive.bind_indices();
+            });
+            state.bind_vertex_array(vertex_array.as_ref());
+            return;
+        }
+
+        if !state.set_vertex_source(self.id, primitive.position_buffer.as_ref()) {
+            return;
         }
+
+        for loc in self.set_attribute_pointers(primitive, state) {
+            state.set_attrib_array(loc, true);
+        }
+        primitive.bind_indices();
     }
 
-    pub fn get_extra_uniform(&mut self, key: &str) -> Option<WebGlUniformLocation> {
-        if !self.extra_uniforms.contains_key(key) {
-            self.extra_uniforms.insert(key.to_string(), self.program.get_uniform_loc(key));
+    /// Points the attributes at the primitive buffers, returns the locations to enable
+    fn set_attribute_pointers(&self, primitive: &Primitive<D>, state: &mut GlState<D>) -> Vec<u32> {
+        let attributes = [
+            // position coordinates
+            (self.vert_pos_loc, primitive.position_buffer.as_ref(), 3),
+            // uv (texture) coordinates
+            (self.vert_uv_loc, primitive.uv_buffer.as_ref(), 2),
+            // tang coordinates
+            (self.vert_tang_loc, primitive.tang_buffer.as_ref(), 3),
+            // bitang coordinates
+            (self.vert_bitang_loc, primitive.bitang_buffer.as_ref(), 3),
+        ];
+
+        let mut locations = vec![];
+        for (loc, buffer, size) in attributes.iter() {
+            // Attributes optimized out of the shader have no location
+            if *loc < 0 {
+                continue;
+            }
+
+            state.bind_array_buffer(*buffer);
+            self.program.device.vertex_attrib_pointer(*loc as u32, *size, 0, 0);
+            state.count_change();
+            locations.push(*loc as u32);
         }
 
-        Option::Some(self.extra_uniforms.get(key).unwrap().clone().unwrap())
+        locations
     }
 
-    pub fn bind(&self, primitive: &Primitive) {
-
-        // position coordinates
-        self.program.gl.bind_buffer(GL::ARRAY_BUFFER, primitive.position_buffer.as_ref());
-        self.program.gl.vertex_attrib_pointer_with_i32(
-            self.vert_pos_loc as u32, 3, GL::FLOAT, false, 0, 0
-        );
-        self.program.gl.enable_vertex_attrib_array(self.vert_pos_loc as u32);
-
-        // uv (texture) coordinates
-        self.program.gl.bind_buffer(GL::ARRAY_BUFFER, primitive.uv_buffer.as_ref());
-        self.program.gl.vertex_attrib_pointer_with_i32(
-            self.vert_uv_loc as u32, 2, GL::FLOAT, false, 0, 0
-        );
-        self.program.gl.enable_vertex_attrib_array(self.vert_uv_loc as u32);
-
-        // tang coordinates
-        self.program.gl.bind_buffer(GL::ARRAY_BUFFER, primitive.tang_buffer.as_ref());
-        self.program.gl.vertex_attrib_pointer_with_i32(
-            self.vert_tang_loc as u32, 3, GL::FLOAT, false, 0, 0
-        );
-        self.program.gl.enable_vertex_attrib_array(self.vert_tang_loc as u32);
-
-        // bitang coordinates
-        self.program.gl.bind_buffer(GL::ARRAY_BUFFER, primitive.bitang_buffer.as_ref());
-        self.program.gl.vertex_attrib_pointer_with_i32(
-            self.vert_bitang_loc as u32, 3, GL::FLOAT, false, 0, 0
-        );
-        self.program.gl.enable_vertex_attrib_array(self.vert_bitang_loc as u32);
+    /// Feeds the four columns of `instance_model` from the instance buffer, one matrix per instance
+    fn set_instance_pointers(&self, state: &mut GlState<D>) {
+        let buffer = match self.instance_buffer.as_ref() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+
+        state.bind_array_buffer(Some(buffer));
+        let device = &self.program.device;
+        for column in 0..4 {
+            let loc = (self.instance_loc + column) as u32;
+            device.set_attrib_array(loc, true);
+            device.vertex_attrib_pointer(loc, 4, 64, column * 16);
+            device.vertex_attrib_divisor(loc, 1);
+            state.count_change();
+        }
     }
-}
\ No newline at end of file
+
+    /// Binds the geometry and draws the primitive with the given model matrix, material state must be bound
+    pub fn draw(&self, primitive: &Primitive<D>, model: &[f32; 16], state: &mut GlState<D>) -> Result<(), String> {
+        if self.supports_instancing() {
+            return self.draw_instanced(primitive, std::slice::from_ref(model), state);
+        }
+
+        self.bind(primitive, state);
+
+        self.program.set_optional_uniform("model_view", UniformValue::Mat4(*model))?;
+
+        primitive.draw();
+
+        Ok(())
+    }
+
+    /// Draws the primitive once per model matrix with a single call, material state must be bound
+    pub fn draw_instanced(&self, primitive: &Primitive<D>, models: &[[f32; 16]], state: &mut GlState<D>) -> Result<(), String> {
+        let buffer = self.instance_buffer.as_ref().ok_or("Instanced draws need device support and an instance_model attribute")?;
+
+        self.bind(primitive, state);
+
+        let u8_slice = unsafe {
+            std::slice::from_raw_parts(
+                models.as_ptr() as *const u8,
+                std::mem::size_of_val(models),
+            )
+        };
+        state.bind_array_buffer(Some(buffer));
+        self.program.device.buffer_data(GL::ARRAY_BUFFER, u8_slice, GL::DYNAMIC_DRAW);
+        state.count_change();
+
+        primitive.draw_instanced(models.len() as i32);
+
+        Ok(())
+    }
+
+    /// Unbinds the vertex array, or disables the vertex attribute arrays enabled by `bind` without vertex arrays
+    pub fn unbind(&self, state: &mut GlState<D>) {
+        if state.features.vertex_arrays {
+            state.bind_vertex_array(None);
+            return;
+        }
+
+        for loc in [self.vert_pos_loc, self.vert_uv_loc, self.vert_tang_loc, self.vert_bitang_loc].iter() {
+            if *loc >= 0 {
+                state.set_attrib_array(*loc as u32, false);
+            }
+        }
+        state.clear_vertex_source();
+    }
+}
+
+impl<D: GraphicsDevice> Drop for DefaultPipeline<D> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.instance_buffer.as_ref() {
+            self.program.device.delete_buffer(buffer);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Graphics API used by the renderer
+//!
+//! `WebGlDevice` implements it for the browser, model types take it as their device. Enums are the
+//! WebGL ones on every device, see `gl`.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+#[cfg(feature = "web")]
+use std::ops::Deref;
+#[cfg(feature = "web")]
+use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlRenderingContext as WebGl, WebGlShader, WebGlUniformLocation, WebGlVertexArrayObject};
+#[cfg(feature = "web")]
+use web_sys::{WebGlBuffer, WebGlTexture};
+use crate::model::{ProgramReflection, UniformValue};
+use crate::model::backend::IndexType;
+#[cfg(feature = "web")]
+use crate::model::backend::webgl2;
+#[cfg(feature = "web")]
+use crate::model::gl as GL;
+#[cfg(feature = "web")]
+use crate::model::program::{FRAG_ERROR, VERT_ERROR};
+
+/// Optional features a device offers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeviceFeatures {
+    /// Instanced draws and attribute divisors
+    pub instancing: bool,
+    pub vertex_arrays: bool,
+    /// 32-bit index buffers
+    pub uint_indices: bool,
+    pub uniform_buffers: bool,
+}
+
+/// Single draw of triangles from the bound vertex input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawCall {
+    /// Vertices, or indices of indexed draws
+    pub count: i32,
+    /// Type of the bound element buffer, `None` draws the vertices in order
+    pub index_type: Option<IndexType>,
+    /// `None` for plain draws, instanced ones need `DeviceFeatures::instancing`
+    pub instances: Option<i32>,
+}
+
+pub trait GraphicsDevice: Clone {
+    type Buffer: Clone + PartialEq + Debug;
+    type Texture: Clone + PartialEq + Debug;
+    type Shader;
+    type Program: Debug;
+    type UniformLocation: Clone + Debug;
+    type VertexArray: Clone + PartialEq + Debug;
+
+    fn features(&self) -> DeviceFeatures;
+
+    fn create_buffer(&self) -> Option<Self::Buffer>;
+    fn bind_buffer(&self, target: u32, buffer: Option<&Self::Buffer>);
+    /// Replaces the contents of the buffer bound to `target`
+    fn buffer_data(&self, target: u32, data: &[u8], usage: u32);
+    fn delete_buffer(&self, buffer: &Self::Buffer);
+
+    fn create_texture(&self) -> Option<Self::Texture>;
+    fn active_texture(&self, unit: u32);
+    fn bind_texture(&self, target: u32, texture: Option<&Self::Texture>);
+    fn tex_parameter(&self, target: u32, name: u32, value: i32);
+    fn pixel_store(&self, name: u32, value: i32);
+    /// Allocates a level of the bound texture with unsized `format`, uploading `data` when given
+    fn tex_image_2d(&self, target: u32, level: i32, format: u32, size: (u32, u32), data_type: u32, data: Option<&[u8]>) -> Result<(), String>;
+    /// Replaces the `(x, y, width, height)` rectangle of a level of the bound texture
+    fn tex_sub_image_2d(&self, target: u32, level: i32, region: (u32, u32, u32, u32), format: u32, data_type: u32, data: &[u8]) -> Result<(), String>;
+    fn delete_texture(&self, texture: &Self::Texture);
+
+    /// Compiles a `VERTEX_SHADER` or `FRAGMENT_SHADER`, errors start with `VERT_ERROR` or `FRAG_ERROR`
+    fn compile_shader(&self, shader_type: u32, source: &str) -> Result<Self::Shader, String>;
+    fn delete_shader(&self, shader: &Self::Shader);
+    /// Links the shaders into a program, the shaders are deleted either way
+    fn link_program(&self, vert: Self::Shader, frag: Self::Shader) -> Result<Self::Program, String>;
+    /// Active uniforms and attributes, with the location of every uniform outside of uniform blocks
+    fn reflect_program(&self, program: &Self::Program) -> (ProgramReflection, HashMap<String, Self::UniformLocation>);
+    fn use_program(&self, program: Option<&Self::Program>);
+    /// Sets a uniform of the program in use
+    fn set_uniform(&self, location: &Self::UniformLocation, value: &UniformValue);
+    /// Points a uniform block at a buffer binding point, false when the program has no such block
+    fn bind_uniform_block(&self, program: &Self::Program, name: &str, binding: u32) -> bool;
+    fn delete_program(&self, program: &Self::Program);
+
+    fn set_capability(&self, capability: u32, enabled: bool);
+    fn depth_mask(&self, enabled: bool);
+    fn cull_face(&self, mode: u32);
+
+    /// Reads float components of an attribute from the bound array buffer
+    fn vertex_attrib_pointer(&self, loc: u32, size: i32, stride: i32, offset: i32);
+    fn set_attrib_array(&self, loc: u32, enabled: bool);
+    fn vertex_attrib_divisor(&self, loc: u32, divisor: u32);
+    /// `None` where vertex arrays aren't supported
+    fn create_vertex_array(&self) -> Option<Self::VertexArray>;
+    fn bind_vertex_array(&self, vertex_array: Option<&Self::VertexArray>);
+    fn delete_vertex_array(&self, vertex_array: &Self::VertexArray);
+
+    fn draw(&self, call: &DrawCall);
+}
+
+/// WebGL context with its WebGL2 view and features resolved once, instead of on every call
+#[cfg(feature = "web")]
+#[derive(Clone, Debug)]
+pub struct WebGlDevice {
+    gl: WebGl,
+    gl2: Option<WebGl2RenderingContext>,
+    features: DeviceFeatures,
+}
+
+#[cfg(feature = "web")]
+impl WebGlDevice {
+    pub fn new(gl: WebGl) -> Self {
+        let gl2 = webgl2(&gl);
+        let features = DeviceFeatures {
+            instancing: gl2.is_some(),
+            vertex_arrays: gl2.is_some(),
+            uint_indices: gl2.is_some() || matches!(gl.get_extension("OES_element_index_uint"), Ok(Some(_))),
+            uniform_buffers: gl2.is_some(),
+        };
+
+        Self { gl, gl2, features }
+    }
+
+    /// The context typed for WebGL2 calls, `None` for WebGL1 contexts
+    pub fn webgl2(&self) -> Option<&WebGl2RenderingContext> {
+        self.gl2.as_ref()
+    }
+}
+
+/// WebGL1 calls go straight to the context
+#[cfg(feature = "web")]
+impl Deref for WebGlDevice {
+    type Target = WebGl;
+
+    fn deref(&self) -> &WebGl {
+        &self.gl
+    }
+}
+
+#[cfg(feature = "web")]
+impl PartialEq for WebGlDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.gl == other.gl
+    }
+}
+
+#[cfg(feature = "web")]
+impl GraphicsDevice for WebGlDevice {
+    type Buffer = WebGlBuffer;
+    type Texture = WebGlTexture;
+    type Shader = WebGlShader;
+    type Program = WebGlProgram;
+    type UniformLocation = WebGlUniformLocation;
+    type VertexArray = WebGlVertexArrayObject;
+
+    fn features(&self) -> DeviceFeatures {
+        self.features
+    }
+
+    fn create_buffer(&self) -> Option<WebGlBuffer> {
+        self.gl.create_buffer()
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>) {
+        self.gl.bind_buffer(target, buffer);
+    }
+
+    fn buffer_data(&self, target: u32, data: &[u8], usage: u32) {
+        self.gl.buffer_data_with_u8_array(target, data, usage);
+    }
+
+    fn delete_buffer(&self, buffer: &WebGlBuffer) {
+        self.gl.delete_buffer(Some(buffer));
+    }
+
+    fn create_texture(&self) -> Option<WebGlTexture> {
+        self.gl.create_texture()
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.gl.active_texture(unit);
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>) {
+        self.gl.bind_texture(target, texture);
+    }
+
+    fn tex_parameter(&self, target: u32, name: u32, value: i32) {
+        self.gl.tex_parameteri(target, name, value);
+    }
+
+    fn pixel_store(&self, name: u32, value: i32) {
+        self.gl.pixel_storei(name, value);
+    }
+
+    fn tex_image_2d(&self, target: u32, level: i32, format: u32, size: (u32, u32), data_type: u32, data: Option<&[u8]>) -> Result<(), String> {
+        self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            target,
+            level,
+            format as i32,
+            size.0 as i32,
+            size.1 as i32,
+            0,
+            format,
+            data_type,
+            data,
+        ).map_err(|_| "Failed to upload texture data".to_string())
+    }
+
+    fn tex_sub_image_2d(&self, target: u32, level: i32, region: (u32, u32, u32, u32), format: u32, data_type: u32, data: &[u8]) -> Result<(), String> {
+        let (x, y, width, height) = region;
+        self.gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            target,
+            level,
+            x as i32,
+            y as i32,
+            width as i32,
+            height as i32,
+            format,
+            data_type,
+            Some(data),
+        ).map_err(|_| "Failed to update texture region".to_string())
+    }
+
+    fn delete_texture(&self, texture: &WebGlTexture) {
+        self.gl.delete_texture(Some(texture));
+    }
+
+    fn compile_shader(&self, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
+        let shader = self.gl.create_shader(shader_type).ok_or("Failed to create shader")?;
+        self.gl.shader_source(&shader, source);
+        self.gl.compile_shader(&shader);
+
+        if !self.gl
+            .get_shader_parameter(&shader, GL::COMPILE_STATUS)
+            .as_bool()
+            .unwrap()
+        {
+            let msg = self.gl
+                .get_shader_info_log(&shader)
+                .unwrap_or_else(|| String::from("Unknown error"));
+            self.gl.delete_shader(Some(&shader));
+
+            let prefix = if shader_type == GL::VERTEX_SHADER { VERT_ERROR } else { FRAG_ERROR };
+            return Err(format!("{}:\n{}", prefix, msg));
+        }
+
+        Ok(shader)
+    }
+
+    fn delete_shader(&self, shader: &WebGlShader) {
+        self.gl.delete_shader(Some(shader));
+    }
+
+    fn link_program(&self, vert: WebGlShader, frag: WebGlShader) -> Result<WebGlProgram, String> {
+        let program = self.gl.create_program().ok_or("Failed to create program")?;
+
+        self.gl.attach_shader(&program, &vert);
+        self.gl.attach_shader(&program, &frag);
+        self.gl.link_program(&program);
+        self.gl.delete_shader(Some(&vert));
+        self.gl.delete_shader(Some(&frag));
+
+        if !self.gl
+            .get_program_parameter(&program, GL::LINK_STATUS)
+            .as_bool()
+            .unwrap()
+        {
+            let msg = self.gl
+                .get_program_info_log(&program)
+                .unwrap_or_else(|| String::from("Unknown error"));
+            self.gl.delete_program(Some(&program));
+
+            return Err(format!("Failed to link program: {}", msg));
+        }
+
+        Ok(program)
+    }
+
+    fn reflect_program(&self, program: &WebGlProgram) -> (ProgramReflection, HashMap<String, WebGlUniformLocation>) {
+        ProgramReflection::from_gl(&self.gl, program)
+    }
+
+    fn use_program(&self, program: Option<&WebGlProgram>) {
+        self.gl.use_program(program);
+    }
+
+    fn set_uniform(&self, location: &WebGlUniformLocation, value: &UniformValue) {
+        value.apply(&self.gl, Some(location));
+    }
+
+    fn bind_uniform_block(&self, program: &WebGlProgram, name: &str, binding: u32) -> bool {
+        let gl2 = match self.gl2.as_ref() {
+            Some(gl2) => gl2,
+            None => return false,
+        };
+
+        let index = gl2.get_uniform_block_index(program, name);
+        if index == WebGl2RenderingContext::INVALID_INDEX {
+            return false;
+        }
+        gl2.uniform_block_binding(program, index, binding);
+
+        true
+    }
+
+    fn delete_program(&self, program: &WebGlProgram) {
+        self.gl.delete_program(Some(program));
+    }
+
+    fn set_capability(&self, capability: u32, enabled: bool) {
+        if enabled {
+            self.gl.enable(capability);
+        } else {
+            self.gl.disable(capability);
+        }
+    }
+
+    fn depth_mask(&self, enabled: bool) {
+        self.gl.depth_mask(enabled);
+    }
+
+    fn cull_face(&self, mode: u32) {
+        self.gl.cull_face(mode);
+    }
+
+    fn vertex_attrib_pointer(&self, loc: u32, size: i32, stride: i32, offset: i32) {
+        self.gl.vertex_attrib_pointer_with_i32(loc, size, GL::FLOAT, false, stride, offset);
+    }
+
+    fn set_attrib_array(&self, loc: u32, enabled: bool) {
+        if enabled {
+            self.gl.enable_vertex_attrib_array(loc);
+        } else {
+            self.gl.disable_vertex_attrib_array(loc);
+        }
+    }
+
+    fn vertex_attrib_divisor(&self, loc: u32, divisor: u32) {
+        if let Some(gl2) = self.gl2.as_ref() {
+            gl2.vertex_attrib_divisor(loc, divisor);
+        }
+    }
+
+    fn create_vertex_array(&self) -> Option<WebGlVertexArrayObject> {
+        self.gl2.as_ref()?.create_vertex_array()
+    }
+
+    fn bind_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        if let Some(gl2) = self.gl2.as_ref() {
+            gl2.bind_vertex_array(vertex_array);
+        }
+    }
+
+    fn delete_vertex_array(&self, vertex_array: &WebGlVertexArrayObject) {
+        if let Some(gl2) = self.gl2.as_ref() {
+            gl2.delete_vertex_array(Some(vertex_array));
+        }
+    }
+
+    fn draw(&self, call: &DrawCall) {
+        match (call.index_type, call.instances) {
+            (None, None) => self.gl.draw_arrays(GL::TRIANGLES, 0, call.count),
+            (Some(index_type), None) => self.gl.draw_elements_with_i32(GL::TRIANGLES, call.count, index_type.gl_type(), 0),
+            (index_type, Some(instances)) => {
+                let gl2 = self.gl2.as_ref().expect("Instanced draws need WebGL2");
+                match index_type {
+                    Some(index_type) => gl2.draw_elements_instanced_with_i32(GL::TRIANGLES, call.count, index_type.gl_type(), 0, instances),
+                    None => gl2.draw_arrays_instanced(GL::TRIANGLES, 0, call.count, instances),
+                }
+            }
+        }
+    }
+}
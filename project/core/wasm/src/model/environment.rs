@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use web_sys::WebGlRenderingContext as GL;
+use crate::model::{GlState, Image, JsTextureImage, Program, ShaderVariants, Texture, UniformValue};
+use crate::model::js_import::JsEnvironment;
+use crate::model::cubemap::CubeImage;
+use crate::model::hdr::{self, HdrImage};
+use crate::model::texture::HdrFormat;
+use crate::model::ibl::{brdf_lut, irradiance_sh9, prefilter_specular, project_sh9, SPECULAR_LEVELS};
+use crate::model::skybox::Skybox;
+use crate::model::device::WebGlDevice;
+
+/// Units reserved for the environment, material texture slots have to stay below them
+pub const ENV_SPECULAR_UNIT: u32 = GL::TEXTURE6;
+pub const BRDF_LUT_UNIT: u32 = GL::TEXTURE7;
+
+const PREFILTER_SAMPLES: u32 = 64;
+const DEFAULT_CUBE_SIZE: u32 = 128;
+const BRDF_LUT_SIZE: u32 = 32;
+const BRDF_LUT_SAMPLES: u32 = 128;
+
+/// Whether any environment image is a Radiance `.hdr` file, which asks for float storage
+pub fn has_hdr_images(js: &JsEnvironment, images: &[JsTextureImage]) -> bool {
+    let ids = js.image_ids();
+    images.iter().any(|jti| ids.contains(&jti.id.as_str()) && jti.bytes().is_some_and(hdr::is_hdr))
+}
+
+/// Decodes the environment images referenced by JS into a cube, PNG, JPEG, WebP or Radiance `.hdr`
+pub fn load_cube(js: &JsEnvironment, images: &[JsTextureImage]) -> Result<CubeImage, String> {
+    let image = |id: &str| -> Result<HdrImage, String> {
+        let jti = images
+            .iter()
+            .find(|jti| jti.id == id)
+            .ok_or_else(|| format!("Environment image '{}' was not loaded", id))?;
+        // Prefiltering runs on the CPU, which can't read browser-decoded sources back cheaply
+        let data = jti.bytes().ok_or_else(|| format!("Environment image '{}' must be passed as encoded data", id))?;
+
+        if hdr::is_hdr(data) {
+            HdrImage::decode(data).map_err(|msg| format!("Environment image '{}': {}", id, msg))
+        } else {
+            let image = Image::decode(data).map_err(|msg| format!("Environment image '{}': {}", id, msg))?;
+            Ok(HdrImage::from_srgb(&image))
+        }
+    };
+
+    if !js.faces.is_empty() {
+        let faces = js.faces.iter().map(|id| image(id)).collect::<Result<Vec<_>, _>>()?;
+        return CubeImage::from_faces(&faces);
+    }
+
+    match js.equirect.as_ref() {
+        Some(id) => {
+            CubeImage::from_equirect(&image(id)?, js.size.unwrap_or(DEFAULT_CUBE_SIZE))
+        }
+        None => Err("Environment needs six faces or an equirectangular image".to_string()),
+    }
+}
+
+/// Image-based lighting resources derived from one environment cube
+pub struct Environment {
+    pub specular: Texture<WebGlDevice>,
+    pub brdf_lut: Texture<WebGlDevice>,
+    /// Irradiance SH9, convolved with the cosine lobe
+    pub irradiance: [[f32; 3]; 9],
+    pub skybox: Option<Skybox>,
+}
+
+impl Environment {
+    /// Runs the CPU precomputation and uploads its results
+    ///
+    /// The radiance is stored as 8-bit sRGB without an HDR format, the `skybox` defines match the scene shaders.
+    pub fn new(
+        gl: WebGlDevice,
+        cube: &CubeImage,
+        format: Option<HdrFormat>,
+        skybox: Option<(&mut ShaderVariants, &BTreeMap<String, String>)>,
+    ) -> Result<Self, String> {
+        let irradiance = irradiance_sh9(&project_sh9(cube));
+        let levels = prefilter_specular(cube, PREFILTER_SAMPLES);
+        let specular = Texture::from_cube_levels(gl.clone(), &levels, ENV_SPECULAR_UNIT, format)?;
+
+        let lut: Vec<u8> = brdf_lut(BRDF_LUT_SIZE, BRDF_LUT_SAMPLES)
+            .iter()
+            .flat_map(|[scale, bias]| vec![(scale * 255.0).round() as u8, (bias * 255.0).round() as u8, 0])
+            .collect();
+        let brdf_lut = Texture::from_image(gl.clone(), &Image::from_vec(lut, BRDF_LUT_SIZE, BRDF_LUT_SIZE), BRDF_LUT_UNIT);
+
+        let skybox = match skybox {
+            Some((shader_variants, defines)) => Some(Skybox::new(shader_variants, defines)?),
+            None => None,
+        };
+
+        Ok(Self { specular, brdf_lut, irradiance, skybox })
+    }
+
+    pub fn bind_textures(&self, state: &mut GlState<WebGlDevice>) {
+        state.bind_texture(ENV_SPECULAR_UNIT, self.specular.target, &self.specular.handle);
+        state.bind_texture(BRDF_LUT_UNIT, self.brdf_lut.target, &self.brdf_lut.handle);
+    }
+
+    /// Sets the IBL uniforms of a program in use, programs without the `ibl` chunk ignore them
+    pub fn set_uniforms(&self, program: &Program<WebGlDevice>) -> Result<(), String> {
+        program.set_optional_uniform("sh_irradiance", UniformValue::Vec3Array(self.irradiance.to_vec()))?;
+        program.set_optional_uniform("env_specular", UniformValue::Sampler((ENV_SPECULAR_UNIT - GL::TEXTURE0) as i32))?;
+        program.set_optional_uniform("brdf_lut", UniformValue::Sampler((BRDF_LUT_UNIT - GL::TEXTURE0) as i32))?;
+        program.set_optional_uniform("env_max_lod", UniformValue::Float((SPECULAR_LEVELS - 1) as f32))
+    }
+
+    pub fn draw_skybox(&self, inv_perspective: [f32; 16], state: &mut GlState<WebGlDevice>) -> Result<(), String> {
+        match self.skybox.as_ref() {
+            Some(skybox) => skybox.draw(&self.specular, ENV_SPECULAR_UNIT, inv_perspective, state),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! `Frame` uniform block of the scene shaders, see `shader/chunks/frame.glsl`
+//!
+//! On WebGL2 the per-frame values are written once into a buffer shared by every program
+//! instead of being set on each of them.
+
+#[cfg(feature = "web")]
+use web_sys::{WebGl2RenderingContext as GL2, WebGlBuffer};
+use crate::model::Program;
+use crate::model::device::GraphicsDevice;
+
+const BLOCK_NAME: &str = "Frame";
+/// Uniform buffer binding point of the block
+pub const FRAME_BINDING: u32 = 0;
+
+/// Values of the block in std140 layout
+///
+/// `mat4` at 0, then the `vec4` and `vec3` light arrays with a 16-byte stride each,
+/// and the ambient `vec3` padded to 16 bytes.
+pub fn pack_frame(perspective: &[f32], light_positions: &[[f32; 4]], light_colors: &[[f32; 3]], ambient: [f32; 3]) -> Vec<f32> {
+    let mut data = Vec::with_capacity(16 + 8 * light_positions.len() + 4);
+    data.extend_from_slice(&perspective[..16]);
+    for position in light_positions.iter() {
+        data.extend_from_slice(position);
+    }
+    for color in light_colors.iter() {
+        data.extend_from_slice(color);
+        data.push(0.0);
+    }
+    data.extend_from_slice(&ambient);
+    data.push(0.0);
+
+    data
+}
+
+/// Points the program's `Frame` block at `FRAME_BINDING`, false when it has none
+pub fn bind_frame_block<D: GraphicsDevice>(program: &Program<D>) -> bool {
+    program.device.bind_uniform_block(&program.handle, BLOCK_NAME, FRAME_BINDING)
+}
+
+/// Buffer backing the `Frame` block
+#[cfg(feature = "web")]
+pub struct FrameBuffer {
+    gl2: GL2,
+    buffer: Option<WebGlBuffer>,
+}
+
+#[cfg(feature = "web")]
+impl FrameBuffer {
+    pub fn new(gl2: GL2) -> Self {
+        let buffer = gl2.create_buffer();
+
+        Self { gl2, buffer }
+    }
+
+    /// Replaces the contents with packed values and binds the buffer to `FRAME_BINDING`
+    pub fn upload(&self, data: &[f32]) {
+        let u8_slice = unsafe {
+            std::slice::from_raw_parts(
+                data.as_ptr() as *const u8,
+                std::mem::size_of_val(data),
+            )
+        };
+
+        self.gl2.bind_buffer(GL2::UNIFORM_BUFFER, self.buffer.as_ref());
+        self.gl2.buffer_data_with_u8_array(GL2::UNIFORM_BUFFER, u8_slice, GL2::DYNAMIC_DRAW);
+        self.gl2.bind_buffer_base(GL2::UNIFORM_BUFFER, FRAME_BINDING, self.buffer.as_ref());
+    }
+}
+
+#[cfg(feature = "web")]
+impl Drop for FrameBuffer {
+    fn drop(&mut self) {
+        self.gl2.delete_buffer(self.buffer.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_block_follows_std140() {
+        let mut perspective = [0.0; 16];
+        perspective[15] = 1.0;
+        let data = pack_frame(&perspective, &[[1.0, 2.0, 3.0, 1.0], [4.0, 5.0, 6.0, 0.0]], &[[7.0; 3], [8.0; 3]], [0.5; 3]);
+
+        // 64 bytes of matrix, two 16-byte strides per array, one padded vec3
+        assert_eq!(data.len() * 4, 64 + 2 * 16 + 2 * 16 + 16);
+        assert_eq!(data[15], 1.0);
+        assert_eq!(data[16..24], [1.0, 2.0, 3.0, 1.0, 4.0, 5.0, 6.0, 0.0]);
+        assert_eq!(data[24..32], [7.0, 7.0, 7.0, 0.0, 8.0, 8.0, 8.0, 0.0]);
+        assert_eq!(data[32..36], [0.5, 0.5, 0.5, 0.0]);
+    }
+}
@@ -0,0 +1,122 @@
+use nalgebra as na;
+use na::{Matrix4, Vector3, Vector4};
+use crate::model::bounds::{Aabb, BoundingSphere, BoundingVolume};
+
+/// Plane in the form `normal . p + d = 0`, the normal points into the frustum
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = Vector3::new(row.x, row.y, row.z);
+        let len = normal.norm();
+
+        Self { normal: normal / len, d: row.w / len }
+    }
+
+    pub fn distance(&self, p: &Vector3<f32>) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
+
+/// View frustum made of six planes: left, right, bottom, top, near, far
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a projection * view matrix (Gribb & Hartmann)
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let row = |i: usize| m.row(i).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                Plane::from_row(r3 + r0),
+                Plane::from_row(r3 - r0),
+                Plane::from_row(r3 + r1),
+                Plane::from_row(r3 - r1),
+                Plane::from_row(r3 + r2),
+                Plane::from_row(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(&sphere.center) >= -sphere.radius)
+    }
+
+    /// Conservative test, a box is rejected only when fully behind one plane
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner furthest along the plane normal
+            let positive = Vector3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+
+            plane.distance(&positive) >= 0.0
+        })
+    }
+
+    /// Cheap sphere rejection first, then the tighter box test
+    pub fn is_visible(&self, volume: &BoundingVolume) -> bool {
+        self.intersects_sphere(&volume.sphere) && self.intersects_aabb(&volume.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frustum() -> Frustum {
+        let perspective = na::Perspective3::new(1.0, std::f32::consts::FRAC_PI_2, 0.1, 100.0);
+        Frustum::from_matrix(&perspective.to_homogeneous())
+    }
+
+    fn unit_volume_at(x: f32, y: f32, z: f32) -> BoundingVolume {
+        BoundingVolume::from_positions(&[-0.5, -0.5, -0.5, 0.5, 0.5, 0.5])
+            .transform(&Matrix4::new_translation(&Vector3::new(x, y, z)))
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        for plane in frustum().planes.iter() {
+            assert!((plane.normal.norm() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn object_in_front_is_visible() {
+        assert!(frustum().is_visible(&unit_volume_at(0.0, 0.0, -10.0)));
+    }
+
+    #[test]
+    fn object_behind_camera_is_culled() {
+        assert!(!frustum().is_visible(&unit_volume_at(0.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn object_beyond_far_plane_is_culled() {
+        assert!(!frustum().is_visible(&unit_volume_at(0.0, 0.0, -200.0)));
+    }
+
+    #[test]
+    fn object_outside_side_planes_is_culled() {
+        // 90 degree fov: at z = -10 the frustum spans x in [-10, 10]
+        assert!(!frustum().is_visible(&unit_volume_at(20.0, 0.0, -10.0)));
+        assert!(!frustum().is_visible(&unit_volume_at(0.0, -20.0, -10.0)));
+    }
+
+    #[test]
+    fn partially_visible_object_is_kept() {
+        assert!(frustum().is_visible(&unit_volume_at(10.2, 0.0, -10.0)));
+    }
+}
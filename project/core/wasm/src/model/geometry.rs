@@ -1,4 +1,9 @@
+use std::collections::HashMap;
+use crate::model::BoundingVolume;
+use crate::model::atlas::AtlasRect;
+
 /// CPU-side primitive geometry
+#[derive(Clone)]
 pub struct Geometry {
     pub vertices: Vec<f32>,
     pub uvs: Vec<f32>,
@@ -6,13 +11,90 @@ pub struct Geometry {
     pub tangents: Vec<f32>,
     pub bitangents: Vec<f32>,
     pub triangles: i32,
-    pub vertex_count: i32
+    /// Vertices drawn, `indices` can make the arrays shorter
+    pub vertex_count: i32,
+    pub bounds: BoundingVolume,
+    /// Triangle list into the vertex arrays, empty when vertices are drawn in order
+    pub indices: Vec<u32>
 }
 
 impl Geometry {
 
     pub fn new(vertices: Vec<f32>, uvs: Vec<f32>, tangents: Vec<f32>, bitangents: Vec<f32>, triangles: i32, vertex_count: i32) -> Self {
-        Self { vertices, uvs, tangents, bitangents, triangles, vertex_count }
+        let bounds = BoundingVolume::from_positions(&vertices);
+
+        Self { vertices, uvs, tangents, bitangents, triangles, vertex_count, bounds, indices: vec![] }
+    }
+
+    /// Returns the geometry with identical vertices merged and drawn through indices
+    pub fn indexed(&self) -> Self {
+        let mut indexed = Self { vertices: vec![], uvs: vec![], tangents: vec![], bitangents: vec![], indices: vec![], ..*self };
+        let mut unique: HashMap<Vec<u32>, u32> = HashMap::new();
+
+        for i in 0..self.vertex_count as usize {
+            let attributes = [
+                &self.vertices[i * 3..i * 3 + 3],
+                &self.uvs[i * 2..i * 2 + 2],
+                &self.tangents[i * 3..i * 3 + 3],
+                &self.bitangents[i * 3..i * 3 + 3],
+            ];
+            // Bit patterns, only exact copies are merged
+            let key: Vec<u32> = attributes.iter().flat_map(|values| values.iter().map(|value| value.to_bits())).collect();
+
+            let next = unique.len() as u32;
+            let index = *unique.entry(key).or_insert_with(|| {
+                indexed.vertices.extend_from_slice(attributes[0]);
+                indexed.uvs.extend_from_slice(attributes[1]);
+                indexed.tangents.extend_from_slice(attributes[2]);
+                indexed.bitangents.extend_from_slice(attributes[3]);
+                next
+            });
+            indexed.indices.push(index);
+        }
+
+        indexed
+    }
+
+    /// Moves UVs into an atlas rect, they have to stay in [0, 1] since atlas pages can't repeat an image
+    pub fn remap_uvs(&mut self, rect: &AtlasRect, page_size: u32) -> Result<(), String> {
+        // Leeway for exporters writing 1.0000001
+        let range = -1e-4..=1.0 + 1e-4;
+        if let Some(uv) = self.uvs.iter().find(|uv| !range.contains(*uv)) {
+            return Err(format!("UV coordinate {} repeats the texture and can't be moved into an atlas", uv));
+        }
+
+        for uv in self.uvs.chunks_exact_mut(2) {
+            let [u, v] = rect.remap_uv([uv[0].clamp(0.0, 1.0), uv[1].clamp(0.0, 1.0)], page_size);
+            uv[0] = u;
+            uv[1] = v;
+        }
+
+        Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_vertices_are_merged() {
+        // Two triangles of a quad, the diagonal appears twice
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+        let uvs = [0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        let frame = [1.0, 0.0, 0.0].repeat(6);
+        let geometry = Geometry::new(positions.to_vec(), uvs.to_vec(), frame.clone(), frame, 2, 6);
+
+        let indexed = geometry.indexed();
+        assert_eq!(indexed.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(indexed.vertices.len(), 4 * 3);
+        assert_eq!(indexed.uvs[4..8], [1.0, 1.0, 0.0, 1.0]);
+        assert_eq!((indexed.triangles, indexed.vertex_count), (2, 6));
+
+        // A different uv on the same position is a different vertex
+        let mut seam = geometry.clone();
+        seam.uvs[6] = 0.5;
+        assert_eq!(seam.indexed().indices, vec![0, 1, 2, 3, 2, 4]);
+    }
+}
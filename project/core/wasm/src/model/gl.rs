@@ -0,0 +1,72 @@
+//! WebGL enums used by the device independent code
+//!
+//! The values are the `WebGlRenderingContext` constants, so the same code builds without web-sys
+//! and every device takes the WebGL enums.
+
+macro_rules! gl_enums {
+    ($($name:ident = $value:expr,)*) => {
+        $(pub const $name: u32 = $value;)*
+
+        #[cfg(all(test, feature = "web"))]
+        #[test]
+        fn enums_match_web_sys() {
+            $(assert_eq!($name, web_sys::WebGlRenderingContext::$name, stringify!($name));)*
+        }
+    };
+}
+
+gl_enums! {
+    ACTIVE_ATTRIBUTES = 0x8B89,
+    ACTIVE_UNIFORMS = 0x8B86,
+    ARRAY_BUFFER = 0x8892,
+    BACK = 0x0405,
+    BLEND = 0x0BE2,
+    BOOL = 0x8B56,
+    BOOL_VEC2 = 0x8B57,
+    BOOL_VEC3 = 0x8B58,
+    BOOL_VEC4 = 0x8B59,
+    CLAMP_TO_EDGE = 0x812F,
+    COMPILE_STATUS = 0x8B81,
+    CULL_FACE = 0x0B44,
+    DEPTH_TEST = 0x0B71,
+    DYNAMIC_DRAW = 0x88E8,
+    ELEMENT_ARRAY_BUFFER = 0x8893,
+    FLOAT = 0x1406,
+    FLOAT_MAT2 = 0x8B5A,
+    FLOAT_MAT3 = 0x8B5B,
+    FLOAT_MAT4 = 0x8B5C,
+    FLOAT_VEC2 = 0x8B50,
+    FLOAT_VEC3 = 0x8B51,
+    FLOAT_VEC4 = 0x8B52,
+    FRAGMENT_SHADER = 0x8B30,
+    FRONT = 0x0404,
+    INT = 0x1404,
+    INT_VEC2 = 0x8B53,
+    INT_VEC3 = 0x8B54,
+    INT_VEC4 = 0x8B55,
+    LINEAR = 0x2601,
+    LINEAR_MIPMAP_LINEAR = 0x2703,
+    LINK_STATUS = 0x8B82,
+    NEAREST = 0x2600,
+    NEAREST_MIPMAP_NEAREST = 0x2700,
+    NO_ERROR = 0x0000,
+    RGB = 0x1907,
+    RGBA = 0x1908,
+    SAMPLER_2D = 0x8B5E,
+    SAMPLER_CUBE = 0x8B60,
+    STATIC_DRAW = 0x88E4,
+    TEXTURE0 = 0x84C0,
+    TEXTURE_2D = 0x0DE1,
+    TEXTURE_CUBE_MAP = 0x8513,
+    TEXTURE_CUBE_MAP_POSITIVE_X = 0x8515,
+    TEXTURE_MAG_FILTER = 0x2800,
+    TEXTURE_MIN_FILTER = 0x2801,
+    TEXTURE_WRAP_S = 0x2802,
+    TEXTURE_WRAP_T = 0x2803,
+    TRIANGLES = 0x0004,
+    UNPACK_ALIGNMENT = 0x0CF5,
+    UNSIGNED_BYTE = 0x1401,
+    UNSIGNED_INT = 0x1405,
+    UNSIGNED_SHORT = 0x1403,
+    VERTEX_SHADER = 0x8B31,
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use crate::model::gl as GL;
+use crate::model::Program;
+use crate::model::device::{DeviceFeatures, GraphicsDevice};
+
+/// Shadow copy of GL state that drops redundant state changes
+pub struct GlState<D: GraphicsDevice> {
+    pub device: D,
+    pub features: DeviceFeatures,
+    program: Option<u32>,
+    active_texture: Option<u32>,
+    /// Bound textures by (unit, target)
+    textures: HashMap<(u32, u32), D::Texture>,
+    array_buffer: Option<D::Buffer>,
+    /// Pipeline id and position buffer whose attribute layout is bound
+    vertex_source: Option<(u32, D::Buffer)>,
+    /// Bound vertex array, `None` is the default one
+    vertex_array: Option<D::VertexArray>,
+    capabilities: HashMap<u32, bool>,
+    depth_mask: Option<bool>,
+    cull_face: Option<u32>,
+    attrib_arrays: HashMap<u32, bool>,
+    /// GL calls actually issued since the last `begin_frame`
+    pub state_changes: i32,
+}
+
+impl<D: GraphicsDevice> GlState<D> {
+    pub fn new(device: D) -> Self {
+        Self {
+            features: device.features(),
+            device,
+            program: None,
+            active_texture: None,
+            textures: HashMap::new(),
+            array_buffer: None,
+            vertex_source: None,
+            vertex_array: None,
+            capabilities: HashMap::new(),
+            depth_mask: None,
+            cull_face: None,
+            attrib_arrays: HashMap::new(),
+            state_changes: 0,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.state_changes = 0;
+        self.vertex_source = None;
+    }
+
+    /// Forgets everything, needed after GL calls made outside of the cache
+    pub fn invalidate(&mut self) {
+        self.program = None;
+        self.active_texture = None;
+        self.textures.clear();
+        self.array_buffer = None;
+        self.vertex_source = None;
+        self.vertex_array = None;
+        self.capabilities.clear();
+        self.depth_mask = None;
+        self.cull_face = None;
+        self.attrib_arrays.clear();
+    }
+
+    pub fn use_program(&mut self, program: &Program<D>) {
+        if self.program != Some(program.id) {
+            program.bind();
+            self.program = Some(program.id);
+            self.state_changes += 1;
+        }
+    }
+
+    pub fn bind_texture(&mut self, texture_num: u32, target: u32, texture: &D::Texture) {
+        if self.textures.get(&(texture_num, target)) == Some(texture) {
+            return;
+        }
+
+        if self.active_texture != Some(texture_num) {
+            self.device.active_texture(texture_num);
+            self.active_texture = Some(texture_num);
+            self.state_changes += 1;
+        }
+
+        self.device.bind_texture(target, Some(texture));
+        self.textures.insert((texture_num, target), texture.clone());
+        self.state_changes += 1;
+    }
+
+    pub fn bind_array_buffer(&mut self, buffer: Option<&D::Buffer>) {
+        if self.array_buffer.as_ref() != buffer {
+            self.device.bind_buffer(GL::ARRAY_BUFFER, buffer);
+            self.array_buffer = buffer.cloned();
+            self.state_changes += 1;
+        }
+    }
+
+    /// Records the pipeline and buffer whose attribute layout is bound, returns false when it already is
+    pub fn set_vertex_source(&mut self, pipeline: u32, buffer: Option<&D::Buffer>) -> bool {
+        let source = buffer.map(|buffer| (pipeline, buffer.clone()));
+        if source.is_some() && self.vertex_source == source {
+            return false;
+        }
+
+        self.vertex_source = source;
+        true
+    }
+
+    /// Forgets the bound attribute layout, once its attribute arrays are disabled
+    pub fn clear_vertex_source(&mut self) {
+        self.vertex_source = None;
+    }
+
+    /// Binds a vertex array where supported, attribute arrays tracked by `set_attrib_array` belong to the default one
+    pub fn bind_vertex_array(&mut self, vertex_array: Option<&D::VertexArray>) {
+        if self.vertex_array.as_ref() == vertex_array || !self.features.vertex_arrays {
+            return;
+        }
+
+        self.device.bind_vertex_array(vertex_array);
+        self.vertex_array = vertex_array.cloned();
+        self.state_changes += 1;
+    }
+
+    pub fn set_capability(&mut self, capability: u32, enabled: bool) {
+        if self.capabilities.get(&capability) != Some(&enabled) {
+            self.device.set_capability(capability, enabled);
+            self.capabilities.insert(capability, enabled);
+            self.state_changes += 1;
+        }
+    }
+
+    pub fn set_depth_mask(&mut self, enabled: bool) {
+        if self.depth_mask != Some(enabled) {
+            self.device.depth_mask(enabled);
+            self.depth_mask = Some(enabled);
+            self.state_changes += 1;
+        }
+    }
+
+    pub fn set_cull_face(&mut self, mode: u32) {
+        if self.cull_face != Some(mode) {
+            self.device.cull_face(mode);
+            self.cull_face = Some(mode);
+            self.state_changes += 1;
+        }
+    }
+
+    pub fn set_attrib_array(&mut self, loc: u32, enabled: bool) {
+        if self.attrib_arrays.get(&loc) != Some(&enabled) {
+            self.device.set_attrib_array(loc, enabled);
+            self.attrib_arrays.insert(loc, enabled);
+            self.state_changes += 1;
+        }
+    }
+
+    /// Counts a state change issued directly by the caller
+    pub fn count_change(&mut self) {
+        self.state_changes += 1;
+    }
+}
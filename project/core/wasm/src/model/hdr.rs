@@ -0,0 +1,395 @@
+use crate::model::Image;
+
+/// Linear RGB image with f32 channels, decoded from Radiance `.hdr` or converted from sRGB
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGB triplets, first row at the top
+    pub data: Vec<f32>,
+}
+
+/// Whether the bytes start with a Radiance header
+pub fn is_hdr(data: &[u8]) -> bool {
+    data.starts_with(b"#?")
+}
+
+/// How HDR texels are stored on the GPU
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HdrEncoding {
+    /// RGB32F through `OES_texture_float`
+    Float,
+    /// RGB16F through `OES_texture_half_float`
+    HalfFloat,
+    /// Shared-exponent RGBA8, shaders decode it and it can't be filtered
+    Rgbe,
+}
+
+impl HdrImage {
+    /// Decodes a Radiance RGBE file, flat and run-length encoded scanlines are supported
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if !is_hdr(bytes) {
+            return Err("Not a Radiance HDR file".to_string());
+        }
+
+        let mut pos = 0;
+        let mut format_ok = true;
+
+        // Header lines up to an empty line
+        loop {
+            let line = read_line(bytes, &mut pos).ok_or("Truncated HDR header")?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                format_ok = format == "32-bit_rle_rgbe";
+            }
+        }
+        if !format_ok {
+            return Err("Only the 32-bit_rle_rgbe HDR format is supported".to_string());
+        }
+
+        let resolution = read_line(bytes, &mut pos).ok_or("Missing HDR resolution")?;
+        let (width, height, flip_y) = parse_resolution(&resolution)?;
+        if width == 0 || height == 0 {
+            return Err("Empty HDR image".to_string());
+        }
+
+        // Dimensions are checked against the bytes left before anything is allocated for them
+        (width as usize).checked_mul(height as usize).and_then(|texels| texels.checked_mul(3)).ok_or("HDR image too large")?;
+        let min_size = (height as usize).checked_mul(min_scanline_size(width as usize));
+        if min_size.is_none_or(|size| size > bytes.len() - pos) {
+            return Err("Truncated HDR data".to_string());
+        }
+
+        let mut data = vec![];
+        let mut scanline = vec![[0u8; 4]; width as usize];
+
+        for _ in 0..height {
+            read_scanline(bytes, &mut pos, &mut scanline)?;
+            data.reserve(scanline.len() * 3);
+            for rgbe in scanline.iter() {
+                data.extend_from_slice(&rgbe_to_rgb(*rgbe));
+            }
+        }
+
+        let mut image = Self { width, height, data };
+        if flip_y {
+            image.flip_rows();
+        }
+
+        Ok(image)
+    }
+
+    /// Decodes 8-bit sRGB texels to linear RGB, alpha is dropped
+    pub fn from_srgb(image: &Image) -> Self {
+        let data = image.data
+            .chunks_exact(image.channels as usize)
+            .flat_map(|texel| texel[..3].iter().map(|c| srgb_to_linear(*c)))
+            .collect();
+
+        Self { width: image.width, height: image.height, data }
+    }
+
+    /// RGB triplets as used by the cube map precomputation
+    pub fn texels(&self) -> Vec<[f32; 3]> {
+        self.data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect()
+    }
+
+    fn flip_rows(&mut self) {
+        let row = (self.width * 3) as usize;
+        let rows: Vec<&[f32]> = self.data.chunks_exact(row).rev().collect();
+        self.data = rows.concat();
+    }
+}
+
+fn read_line(bytes: &[u8], pos: &mut usize) -> Option<String> {
+    let start = *pos;
+    let end = start + bytes.get(start..)?.iter().position(|&b| b == b'\n')?;
+    *pos = end + 1;
+
+    Some(String::from_utf8_lossy(&bytes[start..end]).trim_end_matches('\r').to_string())
+}
+
+/// Standard orientation is `-Y <height> +X <width>`, `+Y` stores the bottom row first
+fn parse_resolution(line: &str) -> Result<(u32, u32, bool), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let error = || format!("Unsupported HDR resolution line: {}", line);
+
+    if parts.len() != 4 || parts[2] != "+X" {
+        return Err(error());
+    }
+    let flip_y = match parts[0] {
+        "-Y" => false,
+        "+Y" => true,
+        _ => return Err(error()),
+    };
+    let height = parts[1].parse().map_err(|_| error())?;
+    let width = parts[3].parse().map_err(|_| error())?;
+
+    Ok((width, height, flip_y))
+}
+
+/// Fewest bytes a scanline of `width` texels can take, with the longest runs when it's run-length encoded
+fn min_scanline_size(width: usize) -> usize {
+    if is_rle_width(width) {
+        // Scanline header, then each channel in runs of up to 127 values taking 2 bytes
+        4 + 4 * 2 * width.div_ceil(127)
+    } else {
+        4 * width
+    }
+}
+
+fn is_rle_width(width: usize) -> bool {
+    (8..0x8000).contains(&width)
+}
+
+fn read_scanline(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let width = scanline.len();
+    let header = bytes.get(*pos..*pos + 4).ok_or("Truncated HDR data")?;
+
+    // New-style RLE: 2, 2, width high, width low, then each channel run-length encoded
+    let is_rle = is_rle_width(width)
+        && header[0] == 2
+        && header[1] == 2
+        && header[2] & 0x80 == 0;
+
+    if !is_rle {
+        for texel in scanline.iter_mut() {
+            let rgbe = bytes.get(*pos..*pos + 4).ok_or("Truncated HDR data")?;
+            texel.copy_from_slice(rgbe);
+            *pos += 4;
+        }
+        return Ok(());
+    }
+
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return Err("HDR scanline width mismatch".to_string());
+    }
+    *pos += 4;
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(*pos).ok_or("Truncated HDR data")? as usize;
+            *pos += 1;
+
+            if count > 128 {
+                // Run of one repeated value
+                let count = count - 128;
+                let value = *bytes.get(*pos).ok_or("Truncated HDR data")?;
+                *pos += 1;
+                if count == 0 || x + count > width {
+                    return Err("Bad HDR run length".to_string());
+                }
+                for texel in scanline[x..x + count].iter_mut() {
+                    texel[channel] = value;
+                }
+                x += count;
+            } else {
+                // Literal values
+                if count == 0 || x + count > width {
+                    return Err("Bad HDR run length".to_string());
+                }
+                let values = bytes.get(*pos..*pos + count).ok_or("Truncated HDR data")?;
+                for (texel, value) in scanline[x..x + count].iter_mut().zip(values.iter()) {
+                    texel[channel] = *value;
+                }
+                *pos += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Same approximation as `srgb_to_linear` in the PBR shader
+pub fn srgb_to_linear(value: u8) -> f32 {
+    (value as f32 / 255.0).powf(2.2)
+}
+
+/// Shared-exponent RGBE to linear RGB
+pub fn rgbe_to_rgb(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+
+    // 2^(e - 128) / 256, the mantissas are 8-bit fractions
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    [rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale]
+}
+
+/// Linear RGB to shared-exponent RGBE, the inverse of `rgbe_to_rgb` up to 8-bit precision
+pub fn rgb_to_rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(exponent);
+    let channel = |c: f32| (c.max(0.0) * scale).min(255.0) as u8;
+
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2]), (exponent + 128).clamp(0, 255) as u8]
+}
+
+/// RGBA8 texels of RGB data, for GPUs without float textures
+pub fn encode_rgbe8(rgb: &[f32]) -> Vec<u8> {
+    rgb.chunks_exact(3).flat_map(|c| rgb_to_rgbe([c[0], c[1], c[2]]).to_vec()).collect()
+}
+
+/// Half float texels of RGB data, for `OES_texture_half_float`
+pub fn encode_f16(rgb: &[f32]) -> Vec<u16> {
+    rgb.iter().map(|c| f32_to_f16(*c)).collect()
+}
+
+/// IEEE 754 binary16 bits of an f32, rounded to nearest even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // Infinity or NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exponent <= 0 {
+        // Subnormal or zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half = mantissa >> shift;
+        let rest = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rest > halfway || (rest == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let round = rest > 0x1000 || (rest == 0x1000 && half & 1 == 1);
+
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity
+    sign | (half + round as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(resolution: &str) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n{}\n", resolution).into_bytes()
+    }
+
+    #[test]
+    fn rgbe_round_trips() {
+        for rgb in [[1.0, 0.5, 0.25], [100.0, 3.0, 0.0], [0.01, 0.02, 0.04]].iter() {
+            let decoded = rgbe_to_rgb(rgb_to_rgbe(*rgb));
+            for c in 0..3 {
+                assert!((decoded[c] - rgb[c]).abs() <= rgb[0].max(rgb[1]).max(rgb[2]) / 128.0);
+            }
+        }
+
+        assert_eq!(rgbe_to_rgb([128, 64, 0, 129]), [1.0, 0.5, 0.0]);
+        assert_eq!(rgb_to_rgbe([0.0; 3]), [0; 4]);
+    }
+
+    #[test]
+    fn srgb_images_drop_alpha() {
+        let image = HdrImage::from_srgb(&Image::with_channels(vec![255, 0, 0, 7, 0, 255, 0, 7], 2, 1, 4));
+        assert_eq!(image.data, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn encoded_texels_keep_their_layout() {
+        let rgb = [1.0, 0.5, 0.0, 0.0, 0.0, 2.0];
+
+        assert_eq!(encode_rgbe8(&rgb), vec![128, 64, 0, 129, 0, 0, 128, 130]);
+        assert_eq!(encode_f16(&rgb), vec![0x3c00, 0x3800, 0, 0, 0, 0x4000]);
+    }
+
+    #[test]
+    fn flat_scanlines_are_decoded() {
+        let mut bytes = header("-Y 2 +X 2");
+        bytes.extend_from_slice(&[128, 0, 0, 129, 0, 128, 0, 129, 0, 0, 128, 130, 0, 0, 0, 0]);
+
+        let image = HdrImage::decode(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.data, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn run_length_scanlines_are_decoded() {
+        let width = 8u8;
+        let mut bytes = header("-Y 1 +X 8");
+        bytes.extend_from_slice(&[2, 2, 0, width]);
+        // Red: a run of 8 x 128
+        bytes.extend_from_slice(&[128 + 8, 128]);
+        // Green: 3 literals, then a run of 5 x 0
+        bytes.extend_from_slice(&[3, 10, 20, 30, 128 + 5, 0]);
+        // Blue: a run of 8 x 0, exponent: a run of 8 x 129
+        bytes.extend_from_slice(&[128 + 8, 0, 128 + 8, 129]);
+
+        let image = HdrImage::decode(&bytes).unwrap();
+        assert_eq!(image.texels()[0], [1.0, 10.0 / 128.0, 0.0]);
+        assert_eq!(image.texels()[7], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn bottom_up_images_are_flipped() {
+        let mut bytes = header("+Y 2 +X 1");
+        bytes.extend_from_slice(&[128, 0, 0, 129, 0, 128, 0, 129]);
+
+        let image = HdrImage::decode(&bytes).unwrap();
+        assert_eq!(image.data, vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        assert!(HdrImage::decode(b"\x89PNG").is_err());
+        assert!(HdrImage::decode(&header("-Y 2 +X 2")).is_err());
+        assert!(HdrImage::decode(&header("-X 2 +Y 2")).is_err());
+        assert!(HdrImage::decode(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(HdrImage::decode(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n+Y 2 +X 0\n\0\0\0\0").is_err());
+        assert!(HdrImage::decode(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 2\n\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn oversized_dimensions_are_errors() {
+        let mut bytes = header("-Y 30000 +X 30000");
+        bytes.extend_from_slice(&[2, 2, 0x75, 0x30, 128 + 127, 0]);
+        assert_eq!(HdrImage::decode(&bytes), Err("Truncated HDR data".to_string()));
+
+        let bytes = header("-Y 4294967295 +X 4294967295");
+        assert!(HdrImage::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn half_floats_match_known_bit_patterns() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7c00, 0x7c00);
+        // Smallest subnormal and below
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+        assert_eq!(f32_to_f16(1e-9), 0x0000);
+        // 1 + 2^-11 is halfway and rounds to even
+        assert_eq!(f32_to_f16(1.000_488_3), 0x3c00);
+    }
+}
@@ -0,0 +1,290 @@
+//! Image-based lighting precomputation: SH9 irradiance, GGX-prefiltered radiance and the split-sum BRDF LUT
+
+use std::f32::consts::PI;
+use nalgebra::{Vector2, Vector3};
+use crate::model::cubemap::{face_direction, texel_solid_angle, CubeImage, FACE_COUNT};
+
+/// Mip levels with increasing roughness, coarser levels are plain downsamples at roughness 1
+pub const SPECULAR_LEVELS: u32 = 6;
+
+/// Real spherical harmonics basis up to band 2
+pub fn sh9_basis(n: &Vector3<f32>) -> [f32; 9] {
+    let (x, y, z) = (n.x, n.y, n.z);
+
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+/// Projects the radiance of a cube map onto SH9
+pub fn project_sh9(cube: &CubeImage) -> [[f32; 3]; 9] {
+    let mut coeffs = [[0.0; 3]; 9];
+
+    for face in 0..FACE_COUNT {
+        for y in 0..cube.size {
+            for x in 0..cube.size {
+                let radiance = cube.faces[face][(y * cube.size + x) as usize];
+                let weight = texel_solid_angle(x, y, cube.size);
+                let basis = sh9_basis(&face_direction(face, x, y, cube.size));
+
+                for (coeff, b) in coeffs.iter_mut().zip(basis.iter()) {
+                    for c in 0..3 {
+                        coeff[c] += radiance[c] * b * weight;
+                    }
+                }
+            }
+        }
+    }
+
+    coeffs
+}
+
+/// Convolves radiance coefficients with the clamped cosine lobe, the result evaluates to irradiance
+pub fn irradiance_sh9(radiance: &[[f32; 3]; 9]) -> [[f32; 3]; 9] {
+    // Ramamoorthi and Hanrahan, bands 0, 1 and 2
+    let bands = [PI, 2.0 * PI / 3.0, 2.0 * PI / 3.0, 2.0 * PI / 3.0, PI / 4.0, PI / 4.0, PI / 4.0, PI / 4.0, PI / 4.0];
+
+    let mut coeffs = *radiance;
+    for (coeff, band) in coeffs.iter_mut().zip(bands.iter()) {
+        for c in coeff.iter_mut() {
+            *c *= band;
+        }
+    }
+
+    coeffs
+}
+
+/// Evaluates SH9 coefficients in a direction, as `irradiance_sh` in the IBL shader chunk
+#[cfg(test)]
+pub fn eval_sh9(coeffs: &[[f32; 3]; 9], n: &Vector3<f32>) -> [f32; 3] {
+    let basis = sh9_basis(n);
+    let mut out = [0.0; 3];
+
+    for (coeff, b) in coeffs.iter().zip(basis.iter()) {
+        for c in 0..3 {
+            out[c] += coeff[c] * b;
+        }
+    }
+
+    out
+}
+
+/// Low-discrepancy point `i` of `count`
+pub fn hammersley(i: u32, count: u32) -> Vector2<f32> {
+    Vector2::new(i as f32 / count as f32, i.reverse_bits() as f32 * 2.328_306_4e-10)
+}
+
+/// Half vector around `n` distributed like the GGX lobe of the given roughness
+pub fn importance_sample_ggx(xi: Vector2<f32>, n: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+    let a = roughness * roughness;
+
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let up = if n.z.abs() < 0.999 { Vector3::z() } else { Vector3::x() };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(&tangent);
+
+    (tangent * (phi.cos() * sin_theta) + bitangent * (phi.sin() * sin_theta) + n * cos_theta).normalize()
+}
+
+/// Roughness stored in a mip level of the prefiltered cube
+pub fn level_roughness(level: u32) -> f32 {
+    (level as f32 / (SPECULAR_LEVELS - 1) as f32).min(1.0)
+}
+
+/// Mip chain down to 1x1, level `i` convolved with the GGX lobe of `level_roughness(i)`
+///
+/// Level 0 is the source itself, levels past `SPECULAR_LEVELS` are box-filtered from the previous one.
+pub fn prefilter_specular(source: &CubeImage, samples: u32) -> Vec<CubeImage> {
+    let mut levels = vec![source.clone()];
+    let mut size = source.size / 2;
+    let mut level = 1;
+
+    while size >= 1 {
+        let next = if level < SPECULAR_LEVELS {
+            let roughness = level_roughness(level);
+            CubeImage::from_fn(size, |n| prefilter_texel(source, n, roughness, samples))
+        } else {
+            levels[levels.len() - 1].half_size()
+        };
+
+        levels.push(next);
+        size /= 2;
+        level += 1;
+    }
+
+    levels
+}
+
+/// Split-sum radiance integral with the n = v = r assumption
+fn prefilter_texel(source: &CubeImage, n: &Vector3<f32>, roughness: f32, samples: u32) -> [f32; 3] {
+    let mut color = [0.0; 3];
+    let mut total_weight = 0.0;
+
+    for i in 0..samples {
+        let h = importance_sample_ggx(hammersley(i, samples), n, roughness);
+        let l = h * (2.0 * n.dot(&h)) - n;
+        let n_dot_l = n.dot(&l);
+
+        if n_dot_l > 0.0 {
+            let radiance = source.sample(&l);
+            for c in 0..3 {
+                color[c] += radiance[c] * n_dot_l;
+            }
+            total_weight += n_dot_l;
+        }
+    }
+
+    if total_weight > 0.0 {
+        for c in color.iter_mut() {
+            *c /= total_weight;
+        }
+    }
+
+    color
+}
+
+/// Smith term with the IBL remapping k = alpha / 2
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let g = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+
+    g(n_dot_v) * g(n_dot_l)
+}
+
+/// Scale and bias applied to F0 by the split-sum approximation
+pub fn integrate_brdf(n_dot_v: f32, roughness: f32, samples: u32) -> [f32; 2] {
+    let v = Vector3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let n = Vector3::z();
+    let (mut scale, mut bias) = (0.0, 0.0);
+
+    for i in 0..samples {
+        let h = importance_sample_ggx(hammersley(i, samples), &n, roughness);
+        let l = h * (2.0 * v.dot(&h)) - v;
+
+        let n_dot_l = l.z.max(0.0);
+        let n_dot_h = h.z.max(0.0);
+        let v_dot_h = v.dot(&h).max(0.0);
+
+        if n_dot_l > 0.0 {
+            let g = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = (1.0 - v_dot_h).powi(5);
+
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+
+    [scale / samples as f32, bias / samples as f32]
+}
+
+/// `size` x `size` LUT, x is n.v and y is roughness, sampled at texel centers
+pub fn brdf_lut(size: u32, samples: u32) -> Vec<[f32; 2]> {
+    (0..size * size)
+        .map(|i| {
+            let n_dot_v = ((i % size) as f32 + 0.5) / size as f32;
+            let roughness = ((i / size) as f32 + 0.5) / size as f32;
+            integrate_brdf(n_dot_v, roughness, samples)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(size: u32, value: f32) -> CubeImage {
+        CubeImage::from_fn(size, |_| [value; 3])
+    }
+
+    #[test]
+    fn constant_environment_gives_pi_irradiance() {
+        let sh = irradiance_sh9(&project_sh9(&constant(16, 1.0)));
+
+        for n in [Vector3::x(), -Vector3::y(), Vector3::new(1.0, 1.0, -1.0).normalize()].iter() {
+            assert!((eval_sh9(&sh, n)[0] - PI).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn sky_light_irradiance_matches_closed_form() {
+        // Radiance 1 from the upper hemisphere: E(up) = pi, E(down) = 0, E(horizon) = pi / 2
+        let sh = irradiance_sh9(&project_sh9(&CubeImage::from_fn(32, |d| if d.y > 0.0 { [1.0; 3] } else { [0.0; 3] })));
+
+        assert!((eval_sh9(&sh, &Vector3::y())[0] - PI).abs() < 0.1);
+        assert!(eval_sh9(&sh, &-Vector3::y())[0].abs() < 0.1);
+        assert!((eval_sh9(&sh, &Vector3::x())[0] - PI / 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn ggx_samples_stay_around_the_normal() {
+        let n = Vector3::new(0.0, 1.0, 0.0);
+        for i in 0..32 {
+            let h = importance_sample_ggx(hammersley(i, 32), &n, 0.3);
+            assert!(h.dot(&n) > 0.0);
+            assert!((h.norm() - 1.0).abs() < 1e-5);
+        }
+
+        // Zero roughness is a mirror
+        let h = importance_sample_ggx(hammersley(5, 32), &n, 0.0);
+        assert!((h - n).norm() < 1e-5);
+    }
+
+    #[test]
+    fn prefiltering_keeps_a_constant_environment_constant() {
+        let levels = prefilter_specular(&constant(8, 0.5), 32);
+
+        assert_eq!(levels.iter().map(|level| level.size).collect::<Vec<_>>(), vec![8, 4, 2, 1]);
+        for level in levels.iter() {
+            for face in level.faces.iter() {
+                for texel in face.iter() {
+                    assert!((texel[0] - 0.5).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rough_levels_blur_a_bright_spot() {
+        let source = CubeImage::from_fn(16, |d| if d.z > 0.95 { [10.0; 3] } else { [0.0; 3] });
+        let levels = prefilter_specular(&source, 64);
+
+        // Looking away from the spot: black in the mirror level, lit once blurred
+        let aside = Vector3::new(0.5, 0.0, 1.0).normalize();
+        assert_eq!(levels[0].sample(&aside)[0], 0.0);
+        assert!(levels[3].sample(&aside)[0] > 0.0);
+        assert!(levels[3].sample(&Vector3::z())[0] < levels[0].sample(&Vector3::z())[0]);
+    }
+
+    #[test]
+    fn smooth_head_on_brdf_reflects_everything() {
+        let [scale, bias] = integrate_brdf(1.0, 0.0, 64);
+
+        assert!((scale - 1.0).abs() < 0.01);
+        assert!(bias.abs() < 0.01);
+    }
+
+    #[test]
+    fn brdf_lut_never_gains_energy() {
+        let lut = brdf_lut(8, 128);
+
+        assert_eq!(lut.len(), 64);
+        for [scale, bias] in lut.iter() {
+            assert!(*scale >= 0.0 && *bias >= 0.0);
+            assert!(scale + bias <= 1.01);
+        }
+        // Grazing angles get more Fresnel bias than head-on ones
+        assert!(lut[0][1] > lut[7][1]);
+    }
+}
@@ -1,31 +1,244 @@
+use std::borrow::Cow;
+use crate::model::hdr;
+
+/// Encoded image formats, all but `Hdr` are 8-bit ones understood by `Image::decode`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+    /// Radiance RGBE, decoded by `HdrImage::decode`
+    Hdr,
+}
+
+impl ImageFormat {
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageFormat::Jpeg)
+        } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(ImageFormat::WebP)
+        } else if hdr::is_hdr(data) {
+            Some(ImageFormat::Hdr)
+        } else {
+            None
+        }
+    }
+}
+
+/// Time spent decoding one texture during initialization
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodeTiming {
+    pub id: String,
+    /// `None` when the browser decoded the image
+    pub format: Option<ImageFormat>,
+    pub width: u32,
+    pub height: u32,
+    pub decode_ms: f64,
+}
+
 pub struct Image {
     pub data: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    pub channels: u32,
 }
 
 impl Image {
     /// Creates an image from raw data as vector
     pub fn from_vec(data: Vec<u8>, width: u32, height: u32) -> Self {
-        let channels = 3; // RGB
+        Self::with_channels(data, width, height, 3) // RGB
+    }
 
+    /// Creates an image from raw 8-bit data with 3 (RGB) or 4 (RGBA) channels
+    pub fn with_channels(data: Vec<u8>, width: u32, height: u32, channels: u32) -> Self {
+        assert!(channels == 3 || channels == 4);
         assert!(data.len() as u32 == width * height * channels);
         Self {
             data,
             width,
             height,
+            channels,
+        }
+    }
+
+    /// Pixels with an opaque alpha channel added to RGB images, for APIs without 3-channel formats
+    pub fn rgba_data(&self) -> Cow<'_, [u8]> {
+        if self.channels == 4 {
+            return Cow::Borrowed(&self.data);
+        }
+
+        Cow::Owned(self.data.chunks_exact(3).flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255]).collect())
+    }
+
+    /// Encodes 8-bit PNG, rendered frames are stored this way
+    pub fn encode_png(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+            encoder.set_color(if self.channels == 4 { png::ColorType::RGBA } else { png::ColorType::RGB });
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(|e| format!("Failed writing png header: {}", e))?;
+            writer.write_image_data(&self.data).map_err(|e| format!("Failed writing png data: {}", e))?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Decodes PNG, JPEG or WebP data, the format is detected from its magic bytes
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        match ImageFormat::detect(data) {
+            Some(ImageFormat::Png) => Self::decode_png(data),
+            Some(ImageFormat::Jpeg) => Self::decode_jpeg(data),
+            Some(ImageFormat::WebP) => Self::decode_webp(data),
+            Some(ImageFormat::Hdr) => Err("Radiance HDR images are decoded by HdrImage".to_string()),
+            None => Err("Unknown image format".to_string()),
         }
     }
 
-    /// Creates an image from png data
-    pub fn from_png(png_data: &[u8]) -> Self {
-        let decoder = png::Decoder::new(png_data);
-        let (info, mut reader) = decoder.read_info().expect("Failed reading png info");
-        let mut data: Vec<u8> = vec![0; info.buffer_size()];
+    fn decode_png(png_data: &[u8]) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(png_data);
+        // Palette images expand to RGB(A), 16-bit channels are reduced to 8-bit
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info().map_err(|e| format!("Failed reading png info: {}", e))?;
+        let mut data: Vec<u8> = vec![0; reader.output_buffer_size()];
         reader
             .next_frame(data.as_mut_slice())
-            .expect("Failed to read png frame");
+            .map_err(|e| format!("Failed to read png frame: {}", e))?;
+
+        let (color_type, _) = reader.output_color_type();
+        match color_type {
+            png::ColorType::RGB => Ok(Image::from_vec(data, info.width, info.height)),
+            png::ColorType::RGBA => Ok(Image::with_channels(data, info.width, info.height, 4)),
+            png::ColorType::Grayscale => Ok(Image::from_vec(gray_to_rgb(&data), info.width, info.height)),
+            png::ColorType::GrayscaleAlpha => {
+                let data = data.chunks_exact(2).flat_map(|la| vec![la[0], la[0], la[0], la[1]]).collect();
+                Ok(Image::with_channels(data, info.width, info.height, 4))
+            }
+            png::ColorType::Indexed => Err("Unexpanded indexed png".to_string()),
+        }
+    }
+
+    /// Baseline and progressive JPEG
+    fn decode_jpeg(jpeg_data: &[u8]) -> Result<Self, String> {
+        let mut decoder = jpeg_decoder::Decoder::new(jpeg_data);
+        let data = decoder.decode().map_err(|e| format!("Failed to decode jpeg: {}", e))?;
+        let info = decoder.info().ok_or("Missing jpeg info")?;
+        let (width, height) = (info.width as u32, info.height as u32);
+
+        let data = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => data,
+            jpeg_decoder::PixelFormat::L8 => gray_to_rgb(&data),
+            // Native endian samples, the high byte is enough for 8-bit textures
+            jpeg_decoder::PixelFormat::L16 => {
+                let luma: Vec<u8> = data.chunks_exact(2).map(|l| (u16::from_ne_bytes([l[0], l[1]]) >> 8) as u8).collect();
+                gray_to_rgb(&luma)
+            }
+            // The decoder already inverts Adobe CMYK, so 255 means no ink
+            jpeg_decoder::PixelFormat::CMYK32 => data
+                .chunks_exact(4)
+                .flat_map(|cmyk| {
+                    let k = cmyk[3] as u32;
+                    vec![(cmyk[0] as u32 * k / 255) as u8, (cmyk[1] as u32 * k / 255) as u8, (cmyk[2] as u32 * k / 255) as u8]
+                })
+                .collect(),
+        };
+
+        Ok(Image::from_vec(data, width, height))
+    }
 
-        Image::from_vec(data, info.width, info.height)
+    /// Lossy and lossless WebP, animations decode to their first frame
+    fn decode_webp(webp_data: &[u8]) -> Result<Self, String> {
+        let mut decoder = image_webp::WebPDecoder::new(std::io::Cursor::new(webp_data))
+            .map_err(|e| format!("Failed reading webp header: {}", e))?;
+        let (width, height) = decoder.dimensions();
+        let channels = if decoder.has_alpha() { 4 } else { 3 };
+
+        let size = decoder.output_buffer_size().ok_or("WebP image is too large")?;
+        let mut data = vec![0; size];
+        decoder.read_image(&mut data).map_err(|e| format!("Failed to decode webp: {}", e))?;
+
+        Ok(Image::with_channels(data, width, height, channels))
+    }
+}
+
+fn gray_to_rgb(luma: &[u8]) -> Vec<u8> {
+    luma.iter().flat_map(|&l| vec![l, l, l]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8x8 RGB gradient, smooth enough to survive lossy compression
+    fn gradient() -> Vec<u8> {
+        (0..64u32).flat_map(|i| vec![(i % 8 * 32) as u8, (i / 8 * 32) as u8, 128]).collect()
+    }
+
+    fn jpeg(progressive: bool) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut encoder = jpeg_encoder::Encoder::new(&mut bytes, 95);
+        encoder.set_progressive(progressive);
+        encoder.encode(&gradient(), 8, 8, jpeg_encoder::ColorType::Rgb).unwrap();
+        bytes
+    }
+
+    fn assert_close(image: &Image, expected: &[u8], tolerance: i32) {
+        assert_eq!((image.width, image.height, image.channels), (8, 8, 3));
+        for (a, b) in image.data.iter().zip(expected.iter()) {
+            assert!((*a as i32 - *b as i32).abs() <= tolerance, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn formats_are_detected_from_magic_bytes() {
+        assert_eq!(ImageFormat::detect(&jpeg(false)), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::detect(b"\x89PNG\r\n\x1a\n...."), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(ImageFormat::WebP));
+        assert_eq!(ImageFormat::detect(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(ImageFormat::detect(b"#?RADIANCE"), Some(ImageFormat::Hdr));
+        assert!(Image::decode(b"GIF89a").is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn baseline_and_progressive_jpegs_decode() {
+        for progressive in [false, true].iter() {
+            assert_close(&Image::decode(&jpeg(*progressive)).unwrap(), &gradient(), 12);
+        }
+    }
+
+    #[test]
+    fn lossless_webp_decodes_exactly() {
+        let mut bytes = vec![];
+        image_webp::WebPEncoder::new(&mut bytes).encode(&gradient(), 8, 8, image_webp::ColorType::Rgb8).unwrap();
+
+        assert_close(&Image::decode(&bytes).unwrap(), &gradient(), 0);
+    }
+
+    #[test]
+    fn rgb_gets_an_opaque_alpha() {
+        let rgb = Image::from_vec(vec![1, 2, 3, 4, 5, 6], 2, 1);
+        assert_eq!(&*rgb.rgba_data(), &[1, 2, 3, 255, 4, 5, 6, 255]);
+
+        let rgba = Image::with_channels(vec![1, 2, 3, 4], 1, 1, 4);
+        assert!(matches!(rgba.rgba_data(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn encoded_png_decodes_to_the_same_pixels() {
+        let rgba = Image::with_channels(vec![10, 20, 30, 40, 50, 60, 70, 80], 2, 1, 4);
+        let decoded = Image::decode(&rgba.encode_png().unwrap()).unwrap();
+
+        assert_eq!((decoded.width, decoded.height, decoded.channels), (2, 1, 4));
+        assert_eq!(decoded.data, rgba.data);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let bytes = jpeg(false);
+        assert!(Image::decode(&bytes[..bytes.len() / 2]).is_err());
+        assert!(Image::decode(b"RIFF\0\0\0\0WEBPVP8 ").is_err());
+    }
+}
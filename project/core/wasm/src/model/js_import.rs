@@ -1,15 +1,81 @@
+#[cfg(feature = "web")]
 extern crate serde_json;
 
-#[derive(Serialize, Deserialize)]
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "web")]
+use wasm_bindgen::JsValue;
+use crate::model::UniformValue;
+use crate::model::material::PbrParams;
+#[cfg(feature = "web")]
+use crate::model::texture::DomImageSource;
+
+/// Texture pixels from JS, encoded bytes decoded in wasm or a source the browser already decoded
+#[cfg(feature = "web")]
+pub enum TextureData {
+    Encoded(Box<[u8]>),
+    /// Alternative encodings, typically KTX files per compression family plus an uncompressed fallback
+    Variants(Vec<Box<[u8]>>),
+    Dom(DomImageSource),
+}
+
+#[cfg(feature = "web")]
 pub struct JsTextureImage {
     pub id: String,
-    pub data: Box<[u8]>
+    pub data: TextureData
 }
 
-#[derive(Serialize, Deserialize)]
+#[cfg(feature = "web")]
+impl JsTextureImage {
+    /// Reads `{id, data}` with encoded bytes, `{id, variants: [data, ...]}` with alternative encodings,
+    /// or `{id, source}` with an `ImageBitmap`, `<img>`, `<canvas>` or `<video>`
+    pub fn from_js(value: &JsValue) -> Result<Self, String> {
+        let field = |name: &str| js_sys::Reflect::get(value, &JsValue::from_str(name)).unwrap_or(JsValue::UNDEFINED);
+        let id = field("id").as_string().ok_or("Texture image needs a string id")?;
+
+        let source = field("source");
+        if !source.is_undefined() {
+            let source = DomImageSource::from_js(&source).ok_or_else(|| {
+                format!("Texture '{}': source must be an ImageBitmap, HTMLImageElement, HTMLCanvasElement or HTMLVideoElement", id)
+            })?;
+            return Ok(Self { id, data: TextureData::Dom(source) });
+        }
+
+        // Accepts plain arrays of bytes as well as typed arrays and array buffers
+        let bytes = |data: &JsValue| js_sys::Uint8Array::new(data).to_vec().into_boxed_slice();
+
+        let variants = field("variants");
+        if !variants.is_undefined() {
+            let variants: Vec<_> = js_sys::Array::from(&variants).iter().map(|data| bytes(&data)).collect();
+            return Ok(Self { id, data: TextureData::Variants(variants) });
+        }
+
+        let data = field("data");
+        if data.is_undefined() {
+            return Err(format!("Texture '{}' needs encoded data, variants or a source", id));
+        }
+
+        Ok(Self { id, data: TextureData::Encoded(bytes(&data)) })
+    }
+
+    /// Encoded bytes, `None` for variants and browser-decoded sources
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match &self.data {
+            TextureData::Encoded(bytes) => Some(bytes),
+            TextureData::Variants(_) | TextureData::Dom(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct JsShaders {
     pub vert_str: String,
-    pub frag_str: String
+    pub frag_str: String,
+    /// Extra `#define`s injected into both shaders
+    #[serde(default)]
+    pub defines: BTreeMap<String, String>,
+    /// Chunks resolvable through `#include "name"`
+    #[serde(default)]
+    pub chunks: HashMap<String, String>
 }
 
 #[derive(Serialize, Deserialize)]
@@ -18,5 +84,132 @@ pub struct JsRenderParams {
     pub min_max_x: Box<[f32; 2]>,
     pub min_max_y: Box<[f32; 2]>,
     pub min_max_z: Box<[f32; 2]>,
-    pub multiple: bool
-}
\ No newline at end of file
+    pub multiple: bool,
+    #[serde(default)]
+    pub alpha_mode: Option<String>,
+    #[serde(default)]
+    pub alpha_cutoff: Option<f32>,
+    #[serde(default)]
+    pub shadows: bool,
+    #[serde(default)]
+    pub light_count: Option<u32>,
+    #[serde(default)]
+    pub post_process: Vec<JsPostProcessPass>,
+    /// Spawned nodes cycle through the materials, all scene textures are used when empty
+    #[serde(default)]
+    pub materials: Vec<JsMaterial>,
+    /// "lambert" (the JS shaders, default) or "pbr" (built-in metallic-roughness shaders)
+    #[serde(default)]
+    pub shading: Option<String>,
+    /// Lights of the PBR shading model, a single point light when empty
+    #[serde(default)]
+    pub lights: Vec<JsLight>,
+    #[serde(default)]
+    pub ambient: Option<[f32; 3]>,
+    /// Image-based lighting for the PBR shading model
+    #[serde(default)]
+    pub environment: Option<JsEnvironment>,
+    /// Packs same-sized material textures into shared pages
+    #[serde(default)]
+    pub atlas: Option<JsAtlas>,
+    /// "webgl" (default) or "webgl2", WebGL2 falls back to WebGL1 where it is missing
+    #[serde(default)]
+    pub backend: Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsPostProcessPass {
+    pub effect: String,
+    #[serde(default)]
+    pub params: HashMap<String, f32>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsMaterial {
+    pub name: String,
+    /// Shading model drawing the material, "lambert" or "pbr", the scene `shading` when missing
+    #[serde(default)]
+    pub shading: Option<String>,
+    /// Sampler uniform name to texture id, all scene textures under their ids when empty
+    #[serde(default)]
+    pub textures: HashMap<String, String>,
+    #[serde(default)]
+    pub uniforms: HashMap<String, UniformValue>,
+    /// Factors for the PBR shading model, glTF defaults when missing
+    #[serde(default)]
+    pub pbr: Option<PbrParams>,
+    /// "back", "front" or "none"
+    #[serde(default)]
+    pub cull: Option<String>,
+    #[serde(default)]
+    pub depth_test: Option<bool>,
+    #[serde(default)]
+    pub depth_write: Option<bool>,
+    #[serde(default)]
+    pub alpha_mode: Option<String>,
+    #[serde(default)]
+    pub alpha_cutoff: Option<f32>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsLight {
+    /// "point" or "directional"
+    pub kind: String,
+    /// View space position, or direction towards the light for directional lights
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub color: Option<[f32; 3]>,
+    #[serde(default)]
+    pub intensity: Option<f32>
+}
+
+/// Environment images are taken out of the scene textures by id
+#[derive(Serialize, Deserialize)]
+pub struct JsEnvironment {
+    /// Six square images in +X, -X, +Y, -Y, +Z, -Z order
+    #[serde(default)]
+    pub faces: Vec<String>,
+    /// Equirectangular panorama, used when `faces` is empty
+    #[serde(default)]
+    pub equirect: Option<String>,
+    /// Cube face size the panorama is resampled to, a power of two
+    #[serde(default)]
+    pub size: Option<u32>,
+    #[serde(default = "default_skybox")]
+    pub skybox: bool
+}
+
+fn default_skybox() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsAtlas {
+    #[serde(default = "default_atlas_page_size")]
+    pub page_size: u32,
+    /// Empty pixels between neighboring images
+    #[serde(default = "default_atlas_padding")]
+    pub padding: u32,
+    /// Edge pixels repeated around each image for filtering
+    #[serde(default = "default_atlas_gutter")]
+    pub gutter: u32
+}
+
+fn default_atlas_page_size() -> u32 {
+    2048
+}
+
+fn default_atlas_padding() -> u32 {
+    1
+}
+
+fn default_atlas_gutter() -> u32 {
+    2
+}
+
+impl JsEnvironment {
+    /// Ids of the images used by the environment
+    pub fn image_ids(&self) -> Vec<&str> {
+        self.faces.iter().map(|id| id.as_str()).chain(self.equirect.as_deref()).collect()
+    }
+}
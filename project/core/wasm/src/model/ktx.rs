@@ -0,0 +1,501 @@
+//! KTX 1 and KTX 2 containers holding GPU block-compressed 2D textures
+
+const KTX1_IDENTIFIER: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'1', b'1', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+const KTX2_IDENTIFIER: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+
+/// Whether the bytes start with a KTX 1 or KTX 2 identifier
+pub fn is_ktx(data: &[u8]) -> bool {
+    data.starts_with(&KTX1_IDENTIFIER) || data.starts_with(&KTX2_IDENTIFIER)
+}
+
+/// Compression families, each behind its own WebGL extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionFamily {
+    /// `WEBGL_compressed_texture_astc`
+    Astc,
+    /// `WEBGL_compressed_texture_s3tc`, BC1 to BC3
+    S3tc,
+    /// `WEBGL_compressed_texture_s3tc_srgb`
+    S3tcSrgb,
+    /// `WEBGL_compressed_texture_etc`, ETC2 and EAC
+    Etc,
+    /// `WEBGL_compressed_texture_etc1`
+    Etc1,
+}
+
+impl CompressionFamily {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionFamily::Astc => "WEBGL_compressed_texture_astc",
+            CompressionFamily::S3tc => "WEBGL_compressed_texture_s3tc",
+            CompressionFamily::S3tcSrgb => "WEBGL_compressed_texture_s3tc_srgb",
+            CompressionFamily::Etc => "WEBGL_compressed_texture_etc",
+            CompressionFamily::Etc1 => "WEBGL_compressed_texture_etc1",
+        }
+    }
+
+    /// Lower is better: ASTC has the best quality per bit, ETC1 lacks alpha
+    fn rank(&self) -> u32 {
+        match self {
+            CompressionFamily::Astc => 0,
+            CompressionFamily::S3tc | CompressionFamily::S3tcSrgb => 1,
+            CompressionFamily::Etc => 2,
+            CompressionFamily::Etc1 => 3,
+        }
+    }
+}
+
+/// A block-compressed format, identified by its GL internal format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompressedFormat {
+    pub gl_format: u32,
+    pub family: CompressionFamily,
+    pub block_width: u32,
+    pub block_height: u32,
+    pub block_bytes: u32,
+}
+
+impl CompressedFormat {
+    pub fn from_gl(gl_format: u32) -> Option<Self> {
+        formats().find(|(_, format)| format.gl_format == gl_format).map(|(_, format)| format)
+    }
+
+    pub fn from_vk(vk_format: u32) -> Option<Self> {
+        formats().find(|(vk, _)| *vk == Some(vk_format)).map(|(_, format)| format)
+    }
+
+    /// Size in bytes of one level, partial blocks at the edges are stored whole. `None` when it overflows
+    pub fn level_size(&self, width: u32, height: u32) -> Option<usize> {
+        let blocks_x = width.div_ceil(self.block_width) as usize;
+        let blocks_y = height.div_ceil(self.block_height) as usize;
+
+        blocks_x.checked_mul(blocks_y)?.checked_mul(self.block_bytes as usize)
+    }
+}
+
+const fn format(gl_format: u32, family: CompressionFamily, block_bytes: u32) -> CompressedFormat {
+    CompressedFormat { gl_format, family, block_width: 4, block_height: 4, block_bytes }
+}
+
+/// Vulkan format used by KTX 2 files and the matching WebGL format, 4x4 blocks
+const BLOCK_4X4_FORMATS: [(Option<u32>, CompressedFormat); 19] = [
+    // BC1 to BC3, unorm and sRGB
+    (Some(131), format(0x83f0, CompressionFamily::S3tc, 8)),
+    (Some(132), format(0x8c4c, CompressionFamily::S3tcSrgb, 8)),
+    (Some(133), format(0x83f1, CompressionFamily::S3tc, 8)),
+    (Some(134), format(0x8c4d, CompressionFamily::S3tcSrgb, 8)),
+    (Some(135), format(0x83f2, CompressionFamily::S3tc, 16)),
+    (Some(136), format(0x8c4e, CompressionFamily::S3tcSrgb, 16)),
+    (Some(137), format(0x83f3, CompressionFamily::S3tc, 16)),
+    (Some(138), format(0x8c4f, CompressionFamily::S3tcSrgb, 16)),
+    // ETC2 RGB, punch-through alpha and RGBA, unorm and sRGB
+    (Some(147), format(0x9274, CompressionFamily::Etc, 8)),
+    (Some(148), format(0x9275, CompressionFamily::Etc, 8)),
+    (Some(149), format(0x9276, CompressionFamily::Etc, 8)),
+    (Some(150), format(0x9277, CompressionFamily::Etc, 8)),
+    (Some(151), format(0x9278, CompressionFamily::Etc, 16)),
+    (Some(152), format(0x9279, CompressionFamily::Etc, 16)),
+    // EAC R11 and RG11, unsigned and signed
+    (Some(153), format(0x9270, CompressionFamily::Etc, 8)),
+    (Some(154), format(0x9271, CompressionFamily::Etc, 8)),
+    (Some(155), format(0x9272, CompressionFamily::Etc, 16)),
+    (Some(156), format(0x9273, CompressionFamily::Etc, 16)),
+    // ETC1 has no Vulkan format, it only comes in KTX 1 files
+    (None, format(0x8d64, CompressionFamily::Etc1, 8)),
+];
+
+const ASTC_BLOCKS: [(u32, u32); 14] = [
+    (4, 4), (5, 4), (5, 5), (6, 5), (6, 6), (8, 5), (8, 6), (8, 8), (10, 5), (10, 6), (10, 8), (10, 10), (12, 10), (12, 12),
+];
+
+/// Every known format, ASTC unorm and sRGB alternate in Vulkan while WebGL keeps them in two runs
+fn formats() -> impl Iterator<Item = (Option<u32>, CompressedFormat)> {
+    let astc = ASTC_BLOCKS.iter().enumerate().flat_map(|(i, &(block_width, block_height))| {
+        let i = i as u32;
+        let astc = |gl_format| CompressedFormat { gl_format, family: CompressionFamily::Astc, block_width, block_height, block_bytes: 16 };
+
+        vec![(Some(157 + 2 * i), astc(0x93b0 + i)), (Some(158 + 2 * i), astc(0x93d0 + i))]
+    });
+
+    BLOCK_4X4_FORMATS.iter().copied().chain(astc)
+}
+
+/// Compressed 2D texture with its mip levels, level 0 first
+#[derive(Clone, Debug)]
+pub struct KtxTexture {
+    pub format: CompressedFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl KtxTexture {
+    /// Parses a KTX 1 or uncompressed KTX 2 file
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.starts_with(&KTX1_IDENTIFIER) {
+            Self::parse_ktx1(data)
+        } else if data.starts_with(&KTX2_IDENTIFIER) {
+            Self::parse_ktx2(data)
+        } else {
+            Err("Not a KTX file".to_string())
+        }
+    }
+
+    /// Dimensions of mip level `level`
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Whether the levels go all the way down to 1x1, as WebGL 1 mipmapping requires
+    pub fn has_full_mip_chain(&self) -> bool {
+        self.levels.len() == full_mip_count(self.width, self.height)
+    }
+
+    /// Bytes taken on the GPU, the sum of all stored levels
+    pub fn byte_size(&self) -> usize {
+        self.levels.iter().map(|level| level.len()).sum()
+    }
+
+    fn parse_ktx1(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(data, 12);
+
+        let little_endian = match reader.u32_le()? {
+            0x0403_0201 => true,
+            0x0102_0304 => false,
+            _ => return Err("Bad KTX endianness".to_string()),
+        };
+        reader.little_endian = little_endian;
+
+        let _gl_type = reader.u32()?;
+        let _gl_type_size = reader.u32()?;
+        let _gl_format = reader.u32()?;
+        let gl_internal_format = reader.u32()?;
+        let _gl_base_internal_format = reader.u32()?;
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let depth = reader.u32()?;
+        let array_elements = reader.u32()?;
+        let faces = reader.u32()?;
+        let level_count = reader.u32()?.max(1);
+        let key_value_bytes = reader.u32()?;
+
+        let format = CompressedFormat::from_gl(gl_internal_format)
+            .ok_or_else(|| format!("Unsupported KTX format 0x{:x}", gl_internal_format))?;
+        check_2d(width, height, depth, array_elements, faces)?;
+        reader.skip(key_value_bytes as usize)?;
+
+        let mut texture = Self { format, width, height, levels: vec![] };
+        for level in 0..level_count as usize {
+            let size = reader.u32()? as usize;
+            texture.push_level(level, reader.bytes(size)?)?;
+            // Levels are padded to 4 bytes
+            reader.skip((4 - size % 4) % 4)?;
+        }
+
+        Ok(texture)
+    }
+
+    fn parse_ktx2(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(data, 12);
+
+        let vk_format = reader.u32()?;
+        let _type_size = reader.u32()?;
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let depth = reader.u32()?;
+        let layers = reader.u32()?;
+        let faces = reader.u32()?;
+        let level_count = reader.u32()?.max(1);
+        let supercompression = reader.u32()?;
+
+        if vk_format == 0 {
+            return Err("Basis Universal KTX2 files need transcoding, which is not supported".to_string());
+        }
+        if supercompression != 0 {
+            return Err(format!("Unsupported KTX2 supercompression scheme {}", supercompression));
+        }
+        let format = CompressedFormat::from_vk(vk_format)
+            .ok_or_else(|| format!("Unsupported KTX2 format {}", vk_format))?;
+        check_2d(width, height, depth, layers, faces)?;
+
+        // Data format descriptor, key/value data and supercompression global data are not needed
+        reader.skip(4 * 4 + 8 * 2)?;
+
+        let mut texture = Self { format, width, height, levels: vec![] };
+        for level in 0..level_count as usize {
+            let offset = reader.u64()? as usize;
+            let length = reader.u64()? as usize;
+            let _uncompressed_length = reader.u64()?;
+
+            let bytes = offset
+                .checked_add(length)
+                .and_then(|end| data.get(offset..end))
+                .ok_or("Truncated KTX2 level data")?;
+            texture.push_level(level, bytes)?;
+        }
+
+        Ok(texture)
+    }
+
+    fn push_level(&mut self, level: usize, bytes: &[u8]) -> Result<(), String> {
+        if level >= full_mip_count(self.width, self.height) {
+            return Err("More KTX levels than the texture size allows".to_string());
+        }
+
+        let (width, height) = self.level_size(level);
+        let expected = self.format.level_size(width, height).ok_or("KTX texture too large")?;
+        if bytes.len() != expected {
+            return Err(format!("KTX level {} has {} bytes, {} expected", level, bytes.len(), expected));
+        }
+
+        self.levels.push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+fn full_mip_count(width: u32, height: u32) -> usize {
+    (32 - width.max(height).max(1).leading_zeros()) as usize
+}
+
+fn check_2d(width: u32, height: u32, depth: u32, layers: u32, faces: u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("KTX texture has no size".to_string());
+    }
+    if depth > 1 || layers > 1 || faces > 1 {
+        return Err("Only single 2D KTX textures are supported, not 3D, arrays or cube maps".to_string());
+    }
+
+    Ok(())
+}
+
+/// Device support of the compression families
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompressionSupport {
+    pub families: Vec<CompressionFamily>,
+}
+
+impl CompressionSupport {
+    pub fn supports(&self, format: &CompressedFormat) -> bool {
+        self.families.contains(&format.family)
+    }
+
+    /// Index of the best variant the device can sample, by family rank then smallest size
+    pub fn pick(&self, variants: &[KtxTexture]) -> Option<usize> {
+        variants
+            .iter()
+            .enumerate()
+            .filter(|(_, variant)| self.supports(&variant.format))
+            .min_by_key(|(_, variant)| (variant.format.family.rank(), variant.byte_size()))
+            .map(|(i, _)| i)
+    }
+}
+
+/// Encoded file chosen among the variants of one texture
+pub enum Variant<'a> {
+    Compressed(KtxTexture),
+    /// PNG, JPEG, WebP or HDR data decoded on the CPU
+    Encoded(&'a [u8]),
+}
+
+/// Picks the best compressed variant the device supports, then falls back to the first uncompressed one
+pub fn select_variant<'a>(variants: &[&'a [u8]], support: &CompressionSupport) -> Result<Variant<'a>, String> {
+    let compressed = variants
+        .iter()
+        .filter(|data| is_ktx(data))
+        .map(|data| KtxTexture::parse(data))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(best) = support.pick(&compressed) {
+        return Ok(Variant::Compressed(compressed.into_iter().nth(best).expect("Picked variant exists")));
+    }
+
+    match variants.iter().find(|data| !is_ktx(data)) {
+        Some(data) => Ok(Variant::Encoded(data)),
+        None => Err(format!(
+            "No compressed format supported by this device among {:?}",
+            compressed.iter().map(|ktx| ktx.format.family).collect::<Vec<_>>()
+        )),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos, little_endian: true }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or("Truncated KTX file")?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u32_le(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        let bytes = [b[0], b[1], b[2], b[3]];
+        Ok(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let b = self.bytes(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DXT1: u32 = 0x83f0;
+
+    fn ktx1(gl_format: u32, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = KTX1_IDENTIFIER.to_vec();
+        let key_value = b"\x0c\x00\x00\x00KTXorient\x00S\x00".to_vec();
+        let header = [0x0403_0201, 0, 1, 0, gl_format, 0x1907, width, height, 0, 0, 1, levels.len() as u32, key_value.len() as u32];
+        for value in header.iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&key_value);
+        for level in levels.iter() {
+            bytes.extend_from_slice(&(level.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(level);
+        }
+
+        bytes
+    }
+
+    fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        // Empty descriptor and metadata sections
+        bytes.extend_from_slice(&[0; 4 * 4 + 8 * 2]);
+
+        let index_len = levels.len() * 24;
+        let mut offset = bytes.len() + index_len;
+        for level in levels.iter() {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            offset += level.len();
+        }
+        for level in levels.iter() {
+            bytes.extend_from_slice(level);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn formats_round_trip_between_gl_and_vulkan() {
+        let bc3 = CompressedFormat::from_vk(137).unwrap();
+        assert_eq!((bc3.gl_format, bc3.block_bytes), (0x83f3, 16));
+
+        let astc = CompressedFormat::from_vk(172).unwrap();
+        assert_eq!((astc.gl_format, astc.block_width, astc.block_height), (0x93d7, 8, 8));
+        assert_eq!(CompressedFormat::from_gl(0x93bd).unwrap().block_width, 12);
+
+        assert_eq!(CompressedFormat::from_gl(0x8d64).unwrap().family, CompressionFamily::Etc1);
+        assert!(CompressedFormat::from_vk(0).is_none());
+        assert!(CompressedFormat::from_vk(37).is_none());
+    }
+
+    #[test]
+    fn level_sizes_round_partial_blocks_up() {
+        let dxt1 = CompressedFormat::from_gl(DXT1).unwrap();
+        assert_eq!(dxt1.level_size(8, 8), Some(32));
+        assert_eq!(dxt1.level_size(1, 1), Some(8));
+
+        let astc_6x5 = CompressedFormat::from_vk(163).unwrap();
+        assert_eq!(astc_6x5.level_size(13, 11), Some(3 * 3 * 16));
+    }
+
+    #[test]
+    fn ktx1_mip_chains_are_parsed() {
+        let levels = vec![vec![1; 32], vec![2; 8], vec![3; 8], vec![4; 8]];
+        let texture = KtxTexture::parse(&ktx1(DXT1, 8, 8, &levels)).unwrap();
+
+        assert_eq!(texture.format.family, CompressionFamily::S3tc);
+        assert_eq!(texture.levels, levels);
+        assert!(texture.has_full_mip_chain());
+        assert_eq!(texture.byte_size(), 56);
+        assert_eq!(texture.level_size(3), (1, 1));
+    }
+
+    #[test]
+    fn ktx2_levels_are_read_through_the_index() {
+        let levels = vec![vec![7; 4 * 16], vec![8; 16]];
+        let texture = KtxTexture::parse(&ktx2(157, 8, 8, &levels)).unwrap();
+
+        assert_eq!(texture.format.gl_format, 0x93b0);
+        assert_eq!(texture.levels, levels);
+        assert!(!texture.has_full_mip_chain());
+    }
+
+    #[test]
+    fn unsupported_files_are_errors() {
+        // Wrong level size
+        assert!(KtxTexture::parse(&ktx1(DXT1, 8, 8, &[vec![0; 16]])).is_err());
+        // Uncompressed RGBA8
+        assert!(KtxTexture::parse(&ktx1(0x8058, 1, 1, &[vec![0; 4]])).is_err());
+        // Basis Universal
+        assert!(KtxTexture::parse(&ktx2(0, 4, 4, &[vec![0; 8]])).is_err());
+        // Truncated
+        let bytes = ktx2(131, 4, 4, &[vec![0; 8]]);
+        assert!(KtxTexture::parse(&bytes[..bytes.len() - 1]).is_err());
+        // Level size overflows
+        let bytes = ktx2(157, u32::MAX, u32::MAX, &[vec![0; 16]]);
+        assert_eq!(KtxTexture::parse(&bytes).unwrap_err(), "KTX texture too large");
+        assert!(!is_ktx(b"\x89PNG"));
+    }
+
+    #[test]
+    fn the_best_supported_variant_is_picked() {
+        let variant = |vk_format, size| KtxTexture::parse(&ktx2(vk_format, 4, 4, &[vec![0; size]])).unwrap();
+        let variants = vec![variant(147, 8), variant(131, 8), variant(157, 16)];
+
+        let desktop = CompressionSupport { families: vec![CompressionFamily::S3tc] };
+        let mobile = CompressionSupport { families: vec![CompressionFamily::Etc, CompressionFamily::Astc] };
+
+        assert_eq!(desktop.pick(&variants), Some(1));
+        assert_eq!(mobile.pick(&variants), Some(2));
+        assert_eq!(CompressionSupport::default().pick(&variants), None);
+    }
+
+    #[test]
+    fn uncompressed_variants_are_the_fallback() {
+        let etc = ktx2(147, 4, 4, &[vec![0; 8]]);
+        let png = b"\x89PNG".to_vec();
+        let mobile = CompressionSupport { families: vec![CompressionFamily::Etc] };
+
+        match select_variant(&[&png, &etc], &mobile).unwrap() {
+            Variant::Compressed(ktx) => assert_eq!(ktx.format.gl_format, 0x9274),
+            Variant::Encoded(_) => panic!("ETC is supported"),
+        }
+        match select_variant(&[&etc, &png], &CompressionSupport::default()).unwrap() {
+            Variant::Encoded(data) => assert_eq!(data, &png[..]),
+            Variant::Compressed(_) => panic!("Nothing is supported"),
+        }
+        assert!(select_variant(&[&etc], &CompressionSupport::default()).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::model::UniformValue;
+use crate::model::js_import::JsLight;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Parallel rays, `position` is the direction towards the light
+    Directional,
+    /// Inverse-square falloff from `position`
+    Point,
+}
+
+/// Analytic light in view space, the camera sits at the origin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn from_js(js: &JsLight) -> Result<Self, String> {
+        let kind = match js.kind.as_str() {
+            "directional" => LightKind::Directional,
+            "point" => LightKind::Point,
+            kind => return Err(format!("Unknown light kind: {}", kind)),
+        };
+
+        Ok(Self {
+            kind,
+            position: js.position,
+            color: js.color.unwrap_or([1.0, 1.0, 1.0]),
+            intensity: js.intensity.unwrap_or(1.0),
+        })
+    }
+
+    /// `xyz` position or direction, `w` 1 for point and 0 for directional lights
+    pub fn position_uniform(&self) -> [f32; 4] {
+        let w = match self.kind {
+            LightKind::Directional => 0.0,
+            LightKind::Point => 1.0,
+        };
+
+        [self.position[0], self.position[1], self.position[2], w]
+    }
+
+    /// Colour scaled by intensity
+    pub fn radiance_uniform(&self) -> [f32; 3] {
+        [self.color[0] * self.intensity, self.color[1] * self.intensity, self.color[2] * self.intensity]
+    }
+}
+
+impl Default for Light {
+    /// Point light where the Lambert shader places its light
+    fn default() -> Self {
+        Self { kind: LightKind::Point, position: [1.0, 2.0, 0.0], color: [1.0, 1.0, 1.0], intensity: 10.0 }
+    }
+}
+
+/// Size of the shader light arrays, the requested count or one slot per light
+///
+/// GLSL has no empty arrays, so a count of 0 is an error.
+pub fn light_count(requested: Option<u32>, lights: &[Light]) -> Result<u32, String> {
+    match requested.unwrap_or(lights.len() as u32) {
+        0 => Err("lightCount must be at least 1".to_string()),
+        count => Ok(count),
+    }
+}
+
+/// Light positions and colours, padded with black lights to `count`
+pub fn light_arrays(lights: &[Light], count: usize) -> (Vec<[f32; 4]>, Vec<[f32; 3]>) {
+    let mut positions = vec![[0.0, 0.0, 1.0, 0.0]; count];
+    let mut colors = vec![[0.0; 3]; count];
+
+    for (i, light) in lights.iter().take(count).enumerate() {
+        positions[i] = light.position_uniform();
+        colors[i] = light.radiance_uniform();
+    }
+
+    (positions, colors)
+}
+
+/// `light_position` and `light_color` array uniforms, see `light_arrays`
+pub fn light_uniforms(lights: &[Light], count: usize) -> (UniformValue, UniformValue) {
+    let (positions, colors) = light_arrays(lights, count);
+
+    (UniformValue::Vec4Array(positions), UniformValue::Vec3Array(colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directional_lights_have_no_position() {
+        let light = Light { kind: LightKind::Directional, ..Light::default() };
+
+        assert_eq!(light.position_uniform()[3], 0.0);
+        assert_eq!(Light::default().position_uniform()[3], 1.0);
+    }
+
+    #[test]
+    fn light_counts_are_at_least_one() {
+        let lights = [Light::default(), Light::default()];
+
+        assert_eq!(light_count(None, &lights), Ok(2));
+        assert_eq!(light_count(Some(4), &lights), Ok(4));
+        assert!(light_count(Some(0), &lights).is_err());
+        assert!(light_count(None, &[]).is_err());
+    }
+
+    #[test]
+    fn uniforms_are_padded_and_truncated() {
+        let lights = [Light::default(), Light { intensity: 1.0, ..Light::default() }];
+
+        let (positions, colors) = light_uniforms(&lights, 3);
+        assert_eq!(positions, UniformValue::Vec4Array(vec![[1.0, 2.0, 0.0, 1.0], [1.0, 2.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.0]]));
+        assert_eq!(colors, UniformValue::Vec3Array(vec![[10.0; 3], [1.0; 3], [0.0; 3]]));
+
+        let (positions, _) = light_uniforms(&lights, 1);
+        assert_eq!(positions, UniformValue::Vec4Array(vec![[1.0, 2.0, 0.0, 1.0]]));
+    }
+}
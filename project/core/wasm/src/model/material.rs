@@ -0,0 +1,293 @@
+use crate::model::gl as GL;
+use crate::model::{AlphaMode, DefaultPipeline, GlState, Texture, UniformValue};
+use crate::model::js_import::JsMaterial;
+use crate::model::device::GraphicsDevice;
+
+/// Faces removed by back-face culling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+impl CullMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(CullMode::None),
+            "back" => Some(CullMode::Back),
+            "front" => Some(CullMode::Front),
+            _ => None,
+        }
+    }
+}
+
+/// Fixed-function state set before drawing with a material
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderState {
+    pub cull: CullMode,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub alpha_mode: AlphaMode,
+}
+
+impl RenderState {
+    /// Blended materials are tested against but never write to the depth buffer
+    pub fn writes_depth(&self) -> bool {
+        self.depth_write && !self.alpha_mode.is_blended()
+    }
+
+    pub fn apply<D: GraphicsDevice>(&self, state: &mut GlState<D>) {
+        match self.cull {
+            CullMode::None => state.set_capability(GL::CULL_FACE, false),
+            CullMode::Back => {
+                state.set_capability(GL::CULL_FACE, true);
+                state.set_cull_face(GL::BACK);
+            }
+            CullMode::Front => {
+                state.set_capability(GL::CULL_FACE, true);
+                state.set_cull_face(GL::FRONT);
+            }
+        }
+
+        state.set_capability(GL::DEPTH_TEST, self.depth_test);
+        state.set_depth_mask(self.writes_depth());
+        state.set_capability(GL::BLEND, self.alpha_mode.is_blended());
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self { cull: CullMode::Back, depth_test: true, depth_write: true, alpha_mode: AlphaMode::Opaque }
+    }
+}
+
+/// Texture bound to a sampler uniform, the slot index is the texture unit
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureSlot {
+    pub sampler: String,
+    /// Index into the context textures
+    pub texture: usize,
+}
+
+/// Metallic-roughness factors, multiplied with the maps the PBR shader samples
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PbrParams {
+    /// Linear RGBA
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub occlusion_strength: f32,
+}
+
+impl PbrParams {
+    pub fn uniforms(&self) -> [(&'static str, UniformValue); 5] {
+        [
+            ("base_color_factor", UniformValue::Vec4(self.base_color)),
+            ("metallic_factor", UniformValue::Float(self.metallic)),
+            ("roughness_factor", UniformValue::Float(self.roughness)),
+            ("emissive_factor", UniformValue::Vec3(self.emissive)),
+            ("occlusion_strength", UniformValue::Float(self.occlusion_strength)),
+        ]
+    }
+}
+
+impl Default for PbrParams {
+    /// glTF defaults, factors of one leave the maps unchanged
+    fn default() -> Self {
+        Self { base_color: [1.0; 4], metallic: 1.0, roughness: 1.0, emissive: [0.0; 3], occlusion_strength: 1.0 }
+    }
+}
+
+/// Look of a node: the pipeline drawing it and the parameters it is drawn with
+#[derive(Debug)]
+pub struct Material {
+    pub name: String,
+    /// Index into the context pipelines
+    pub pipeline: usize,
+    pub textures: Vec<TextureSlot>,
+    pub uniforms: Vec<(String, UniformValue)>,
+    /// Set for materials drawn by the PBR pipeline
+    pub pbr: Option<PbrParams>,
+    pub render_state: RenderState,
+}
+
+impl Material {
+    /// Material using every scene texture under its own id
+    pub fn new(name: &str, pipeline: usize, texture_ids: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            pipeline,
+            textures: texture_ids
+                .iter()
+                .enumerate()
+                .map(|(texture, id)| TextureSlot { sampler: id.to_string(), texture })
+                .collect(),
+            uniforms: vec![],
+            pbr: None,
+            render_state: RenderState::default(),
+        }
+    }
+
+    /// Builds a material from JS, texture ids are resolved against the loaded scene textures
+    pub fn from_js(js: &JsMaterial, pipeline: usize, texture_ids: &[&str]) -> Result<Self, String> {
+        let mut material = Self::new(&js.name, pipeline, texture_ids);
+
+        if !js.textures.is_empty() {
+            let mut samplers: Vec<(&String, &String)> = js.textures.iter().collect();
+            samplers.sort();
+
+            material.textures = samplers
+                .into_iter()
+                .map(|(sampler, id)| {
+                    texture_ids
+                        .iter()
+                        .position(|texture_id| texture_id == id)
+                        .map(|texture| TextureSlot { sampler: sampler.clone(), texture })
+                        .ok_or_else(|| format!("Material '{}' uses unknown texture '{}'", js.name, id))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        let mut uniforms: Vec<(String, UniformValue)> = js.uniforms.iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        uniforms.sort_by(|a, b| a.0.cmp(&b.0));
+        material.uniforms = uniforms;
+        material.pbr = js.pbr;
+
+        let render_state = &mut material.render_state;
+        if let Some(cull) = js.cull.as_ref() {
+            render_state.cull = CullMode::from_name(cull)
+                .ok_or_else(|| format!("Unknown cull mode: {}", cull))?;
+        }
+        if let Some(alpha_mode) = js.alpha_mode.as_ref() {
+            render_state.alpha_mode = AlphaMode::from_name(alpha_mode, js.alpha_cutoff)
+                .ok_or_else(|| format!("Unknown alpha mode: {}", alpha_mode))?;
+        }
+        render_state.depth_test = js.depth_test.unwrap_or(render_state.depth_test);
+        render_state.depth_write = js.depth_write.unwrap_or(render_state.depth_write);
+
+        Ok(material)
+    }
+
+    pub fn sampler_names(&self) -> Vec<&str> {
+        self.textures.iter().map(|slot| slot.sampler.as_str()).collect()
+    }
+
+    /// Checks the parameters against the uniforms declared by the pipeline program
+    pub fn validate<D: GraphicsDevice>(&self, pipeline: &DefaultPipeline<D>) -> Result<Vec<String>, String> {
+        for (name, value) in self.uniforms.iter() {
+            pipeline.program.check_uniform(name, value)
+                .map_err(|msg| format!("Material '{}': {}", self.name, msg))?;
+        }
+
+        pipeline.program.reflection.validate_samplers(&self.sampler_names())
+            .map(|warnings| warnings.into_iter().map(|w| format!("Material '{}': {}", self.name, w)).collect())
+    }
+
+    /// Makes the pipeline current and sets textures, uniforms and render state of the material
+    pub fn bind<D: GraphicsDevice>(&self, pipeline: &DefaultPipeline<D>, textures: &[Texture<D>], state: &mut GlState<D>) -> Result<(), String> {
+        let program = &pipeline.program;
+        state.use_program(program);
+
+        for (slot, texture_slot) in self.textures.iter().enumerate() {
+            let texture = &textures[texture_slot.texture];
+            state.bind_texture(GL::TEXTURE0 + slot as u32, texture.target, &texture.handle);
+            program.set_optional_uniform(&texture_slot.sampler, UniformValue::Sampler(slot as i32))?;
+        }
+
+        if let Some(pbr) = self.pbr.as_ref() {
+            for (name, value) in pbr.uniforms().iter() {
+                program.set_optional_uniform(name, value.clone())?;
+            }
+        }
+
+        for (name, value) in self.uniforms.iter() {
+            program.set_uniform(name, value.clone())?;
+        }
+
+        let alpha_mode = self.render_state.alpha_mode;
+        // Optional, shaders without alpha handling simply ignore them
+        program.set_optional_uniform("alpha_cutoff", UniformValue::Float(alpha_mode.cutoff()))?;
+        program.set_optional_uniform("alpha_blend", UniformValue::Float(if alpha_mode.is_blended() { 1.0 } else { 0.0 }))?;
+
+        self.render_state.apply(state);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn js_material(textures: &[(&str, &str)]) -> JsMaterial {
+        JsMaterial {
+            name: "test".to_string(),
+            shading: None,
+            textures: textures.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            uniforms: HashMap::new(),
+            pbr: None,
+            cull: None,
+            depth_test: None,
+            depth_write: None,
+            alpha_mode: None,
+            alpha_cutoff: None,
+        }
+    }
+
+    #[test]
+    fn default_material_uses_all_textures_in_order() {
+        let material = Material::new("default", 0, &["tex_diffuse", "tex_norm"]);
+
+        assert_eq!(material.sampler_names(), vec!["tex_diffuse", "tex_norm"]);
+        assert_eq!(material.textures[1].texture, 1);
+        assert_eq!(material.render_state, RenderState::default());
+    }
+
+    #[test]
+    fn js_textures_are_resolved_by_id() {
+        let js = js_material(&[("tex_norm", "bricks_norm"), ("tex_diffuse", "bricks")]);
+        let material = Material::from_js(&js, 0, &["wood", "bricks", "bricks_norm"]).unwrap();
+
+        assert_eq!(material.textures, vec![
+            TextureSlot { sampler: "tex_diffuse".to_string(), texture: 1 },
+            TextureSlot { sampler: "tex_norm".to_string(), texture: 2 },
+        ]);
+    }
+
+    #[test]
+    fn unknown_texture_and_cull_mode_are_errors() {
+        let js = js_material(&[("tex_diffuse", "missing")]);
+        assert!(Material::from_js(&js, 0, &["wood"]).unwrap_err().contains("'missing'"));
+
+        let mut js = js_material(&[]);
+        js.cull = Some("sideways".to_string());
+        assert!(Material::from_js(&js, 0, &["wood"]).is_err());
+    }
+
+    #[test]
+    fn render_state_comes_from_js() {
+        let mut js = js_material(&[]);
+        js.cull = Some("none".to_string());
+        js.alpha_mode = Some("mask".to_string());
+        js.alpha_cutoff = Some(0.25);
+        js.depth_write = Some(false);
+
+        let state = Material::from_js(&js, 0, &[]).unwrap().render_state;
+        assert_eq!(state.cull, CullMode::None);
+        assert_eq!(state.alpha_mode, AlphaMode::Mask(0.25));
+        assert!(state.depth_test);
+        assert!(!state.writes_depth());
+    }
+
+    #[test]
+    fn blended_materials_never_write_depth() {
+        let state = RenderState { alpha_mode: AlphaMode::Blend, ..RenderState::default() };
+        assert!(!state.writes_depth());
+    }
+}
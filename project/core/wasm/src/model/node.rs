@@ -1,15 +1,21 @@
-use crate::model::Primitive;
+use crate::model::BoundingVolume;
 
+/// Placed instance of a mesh, plain data so every backend draws the same nodes
 pub struct Node {
     pub id: u32,
     pub coords: (f32, f32, f32),
     pub model: [f32; 16],
-    pub primitive: Primitive,
+    /// Index into the scene meshes, nodes of a mesh can be drawn instanced
+    pub mesh: usize,
+    /// Local space bounds, nodes without bounds are never culled
+    pub bounds: Option<BoundingVolume>,
+    /// Index into the context materials
+    pub material: usize,
     pub children: Vec<Node>,
 }
 
 impl Node {
-    pub fn new(primitive: Primitive) -> Self {
+    pub fn new(mesh: usize) -> Self {
         let model =
             [
                 0.0,0.0,0.0,0.0,
@@ -22,15 +28,36 @@ impl Node {
             id: 0,
             coords: (0.0, 0.0, 0.0),
             model,
-            primitive,
+            mesh,
+            bounds: None,
+            material: 0,
             children: vec![],
         }
     }
 
+    /// Node drawing a mesh, its bounds are kept for culling
+    pub fn with_bounds(mesh: usize, bounds: BoundingVolume) -> Self {
+        let mut node = Self::new(mesh);
+        node.bounds = Some(bounds);
+
+        node
+    }
+
     pub fn set_x_y_z(&mut self, x: f32, y: f32, z: f32) {
         self.coords = (x, y, z);
     }
 
+    /// Distance from the camera (at the origin, looking down -z) to the node center
+    pub fn view_depth(&self) -> f32 {
+        let (x, y, z) = match self.bounds.as_ref() {
+            Some(bounds) => (bounds.sphere.center.x, bounds.sphere.center.y, bounds.sphere.center.z),
+            None => (0.0, 0.0, 0.0),
+        };
+        let m = &self.model;
+
+        -(m[2] * x + m[6] * y + m[10] * z + m[14])
+    }
+
     pub fn rotate_model(&mut self, c: f32, s: f32) {
         self.model = [
             c.powi(2), -c*s, s, 0.0,
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use web_sys::WebGlRenderingContext as GL;
+use crate::model::{Program, RenderTarget, Scene, ShaderSource, ShaderVariants, UniformValue};
+use crate::model::material::RenderState;
+use crate::model::device::WebGlDevice;
+
+const PICK_VERT: &str = include_str!("../shader/pick/pick.vert.glsl");
+const PICK_FRAG: &str = include_str!("../shader/pick/pick.frag.glsl");
+
+/// Encodes a node id into an RGBA8 colour, zero is reserved for the background
+pub fn encode_id(id: u32) -> [u8; 4] {
+    id.wrapping_add(1).to_le_bytes()
+}
+
+/// Decodes an RGBA8 colour written by `encode_id`, `None` for the background
+pub fn decode_id(rgba: [u8; 4]) -> Option<u32> {
+    match u32::from_le_bytes(rgba) {
+        0 => None,
+        value => Some(value - 1),
+    }
+}
+
+/// Renders node ids into an offscreen target and reads back single texels
+pub struct Picker {
+    pub program: Rc<Program<WebGlDevice>>,
+    pub target: RenderTarget,
+    pub vert_pos_loc: i32,
+}
+
+impl Picker {
+    pub fn new(shader_variants: &mut ShaderVariants, width: u32, height: u32) -> Result<Self, String> {
+        let program = shader_variants.get(
+            &ShaderSource { name: "pick.vert", source: PICK_VERT },
+            &ShaderSource { name: "pick.frag", source: PICK_FRAG },
+            &BTreeMap::new(),
+        )?;
+
+        Ok(Self {
+            target: RenderTarget::new((*shader_variants.gl).clone(), width, height),
+            vert_pos_loc: program.get_attrib_loc("vert_pos"),
+            program,
+        })
+    }
+
+    /// Returns the id of the node covering pixel (x, y), measured from the top-left corner
+    ///
+    /// Ids are drawn opaque and depth tested whatever state the scene materials left behind.
+    pub fn pick(&mut self, scene: &mut Scene<WebGlDevice>, perspective: &[f32; 16], x: i32, y: i32, width: u32, height: u32) -> Option<u32> {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            return None;
+        }
+
+        let gl = self.program.device.clone();
+        // GL window coordinates start at the bottom-left corner
+        let gl_y = height as i32 - 1 - y;
+
+        self.target.resize(width, height);
+        self.target.bind();
+
+        // Only the requested pixel needs to be rasterized
+        gl.enable(GL::SCISSOR_TEST);
+        gl.scissor(x, gl_y, 1, 1);
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        RenderState::default().apply(&mut scene.state);
+        gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+
+        self.program.bind();
+        self.program.set_uniform("perspective", UniformValue::Mat4(*perspective)).expect("Invalid picking shader");
+        gl.enable_vertex_attrib_array(self.vert_pos_loc as u32);
+
+        for node in scene.nodes.iter() {
+            let color = encode_id(node.id);
+            let id_color = [
+                color[0] as f32 / 255.0,
+                color[1] as f32 / 255.0,
+                color[2] as f32 / 255.0,
+                color[3] as f32 / 255.0,
+            ];
+            self.program.set_uniform("id_color", UniformValue::Vec4(id_color)).expect("Invalid picking shader");
+            self.program.set_uniform("model_view", UniformValue::Mat4(node.model)).expect("Invalid picking shader");
+
+            let primitive = &scene.primitives[node.mesh];
+            gl.bind_buffer(GL::ARRAY_BUFFER, primitive.position_buffer.as_ref());
+            gl.vertex_attrib_pointer_with_i32(self.vert_pos_loc as u32, 3, GL::FLOAT, false, 0, 0);
+            primitive.bind_indices();
+            primitive.draw();
+        }
+
+        gl.disable_vertex_attrib_array(self.vert_pos_loc as u32);
+
+        let mut pixel = [0u8; 4];
+        gl.read_pixels_with_opt_u8_array(x, gl_y, 1, 1, GL::RGBA, GL::UNSIGNED_BYTE, Some(&mut pixel))
+            .expect("Failed to read picking pixel");
+
+        gl.disable(GL::SCISSOR_TEST);
+        RenderTarget::bind_default(&gl, width, height);
+
+        decode_id(pixel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn background_decodes_to_none() {
+        assert_eq!(decode_id([0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn first_id_is_not_background() {
+        assert_eq!(encode_id(0), [1, 0, 0, 0]);
+        assert_eq!(decode_id([1, 0, 0, 0]), Some(0));
+    }
+
+    #[test]
+    fn ids_round_trip_through_all_channels() {
+        for id in [0, 1, 254, 255, 256, 65_535, 70_000, 16_777_216, u32::MAX - 1].iter() {
+            assert_eq!(decode_id(encode_id(*id)), Some(*id));
+        }
+    }
+
+    #[test]
+    fn channels_are_little_endian() {
+        assert_eq!(encode_id(0x0403_0200), [0x01, 0x02, 0x03, 0x04]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use web_sys::{WebGlRenderingContext as GL, WebGlBuffer};
+use crate::model::{Program, RenderTarget, JsPostProcessPass, ShaderSource, ShaderVariants, UniformValue};
+use crate::model::device::WebGlDevice;
+
+const POST_VERT: &str = include_str!("../shader/post/post.vert.glsl");
+
+/// Full-screen triangle covering the whole viewport
+pub const FULL_SCREEN_TRIANGLE: [f32; 6] = [-1.0, -1.0, 3.0, -1.0, -1.0, 3.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostEffect {
+    ToneMapping,
+    Fxaa,
+    Gamma,
+    Vignette,
+    Blur,
+}
+
+impl PostEffect {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tone_mapping" => Some(PostEffect::ToneMapping),
+            "fxaa" => Some(PostEffect::Fxaa),
+            "gamma" => Some(PostEffect::Gamma),
+            "vignette" => Some(PostEffect::Vignette),
+            "blur" => Some(PostEffect::Blur),
+            _ => None,
+        }
+    }
+
+    pub fn frag_src(&self) -> &'static str {
+        match self {
+            PostEffect::ToneMapping => include_str!("../shader/post/tone_mapping.frag.glsl"),
+            PostEffect::Fxaa => include_str!("../shader/post/fxaa.frag.glsl"),
+            PostEffect::Gamma => include_str!("../shader/post/gamma.frag.glsl"),
+            PostEffect::Vignette => include_str!("../shader/post/vignette.frag.glsl"),
+            PostEffect::Blur => include_str!("../shader/post/blur.frag.glsl"),
+        }
+    }
+
+    /// Float uniforms of the effect shader with their default values
+    pub fn default_params(&self) -> &'static [(&'static str, f32)] {
+        match self {
+            PostEffect::ToneMapping => &[("exposure", 1.0)],
+            PostEffect::Fxaa => &[("span_max", 8.0)],
+            PostEffect::Gamma => &[("gamma", 2.2)],
+            PostEffect::Vignette => &[("strength", 0.5), ("radius", 0.75)],
+            PostEffect::Blur => &[("radius", 1.0)],
+        }
+    }
+}
+
+/// Single full-screen pass with its own program
+pub struct PostProcessPass {
+    pub program: Rc<Program<WebGlDevice>>,
+    pub vert_pos_loc: i32,
+    pub params: Vec<(&'static str, f32)>,
+}
+
+impl PostProcessPass {
+    pub fn new(shader_variants: &mut ShaderVariants, js_pass: &JsPostProcessPass) -> Result<Self, String> {
+        let effect = PostEffect::from_name(js_pass.effect.as_str())
+            .ok_or_else(|| format!("Unknown post-process effect: {}", js_pass.effect))?;
+
+        for key in js_pass.params.keys() {
+            if !effect.default_params().iter().any(|(name, _)| name == key) {
+                return Err(format!("Unknown parameter '{}' for post-process effect: {}", key, js_pass.effect));
+            }
+        }
+
+        let frag_name = format!("{}.frag", js_pass.effect);
+        let program = shader_variants.get(
+            &ShaderSource { name: "post.vert", source: POST_VERT },
+            &ShaderSource { name: &frag_name, source: effect.frag_src() },
+            &BTreeMap::new(),
+        )?;
+
+        let params = effect.default_params()
+            .iter()
+            .map(|(name, default)| {
+                (*name, js_pass.params.get(*name).copied().unwrap_or(*default))
+            })
+            .collect();
+
+        Ok(Self {
+            vert_pos_loc: program.get_attrib_loc("vert_pos"),
+            params,
+            program,
+        })
+    }
+}
+
+/// Ordered chain of post-process passes applied to the rendered scene
+pub struct PostProcessChain {
+    pub gl: GL,
+    pub passes: Vec<PostProcessPass>,
+    pub scene_target: RenderTarget,
+    pub ping_pong: [RenderTarget; 2],
+    pub quad_buffer: Option<WebGlBuffer>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PostProcessChain {
+    pub fn new(shader_variants: &mut ShaderVariants, js_passes: &[JsPostProcessPass], width: u32, height: u32) -> Result<Self, String> {
+        let passes = js_passes
+            .iter()
+            .map(|js_pass| PostProcessPass::new(shader_variants, js_pass))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let gl = (*shader_variants.gl).clone();
+
+        let quad_buffer = gl.create_buffer();
+        gl.bind_buffer(GL::ARRAY_BUFFER, quad_buffer.as_ref());
+        let u8_slice = unsafe {
+            std::slice::from_raw_parts(
+                FULL_SCREEN_TRIANGLE.as_ptr() as *const u8,
+                std::mem::size_of_val(&FULL_SCREEN_TRIANGLE),
+            )
+        };
+        gl.buffer_data_with_u8_array(GL::ARRAY_BUFFER, u8_slice, GL::STATIC_DRAW);
+
+        Ok(Self {
+            scene_target: RenderTarget::new(gl.clone(), width, height),
+            ping_pong: [
+                RenderTarget::new(gl.clone(), width, height),
+                RenderTarget::new(gl.clone(), width, height),
+            ],
+            gl,
+            passes,
+            quad_buffer,
+            width,
+            height,
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.scene_target.resize(width, height);
+        self.ping_pong[0].resize(width, height);
+        self.ping_pong[1].resize(width, height);
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Redirects scene rendering into the offscreen scene target
+    pub fn begin(&self) {
+        self.scene_target.bind();
+    }
+
+    /// Runs all passes, the last one writes into the default framebuffer
+    pub fn apply(&self) -> Result<(), String> {
+        let gl = &self.gl;
+        gl.disable(GL::DEPTH_TEST);
+
+        let mut input = &self.scene_target;
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let output = if i + 1 == self.passes.len() {
+                None
+            } else {
+                Some(&self.ping_pong[i % 2])
+            };
+
+            match output {
+                Some(target) => target.bind(),
+                None => RenderTarget::bind_default(gl, self.width, self.height),
+            }
+
+            pass.program.bind();
+            input.bind_color(GL::TEXTURE0);
+            pass.program.set_optional_uniform("tex_input", UniformValue::Sampler(0))?;
+            pass.program.set_optional_uniform("resolution", UniformValue::Vec2([self.width as f32, self.height as f32]))?;
+
+            for (name, value) in pass.params.iter() {
+                pass.program.set_optional_uniform(name, UniformValue::Float(*value))?;
+            }
+
+            gl.bind_buffer(GL::ARRAY_BUFFER, self.quad_buffer.as_ref());
+            gl.vertex_attrib_pointer_with_i32(pass.vert_pos_loc as u32, 2, GL::FLOAT, false, 0, 0);
+            gl.enable_vertex_attrib_array(pass.vert_pos_loc as u32);
+
+            gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+            gl.disable_vertex_attrib_array(pass.vert_pos_loc as u32);
+
+            if let Some(target) = output {
+                input = target;
+            }
+        }
+
+        gl.enable(GL::DEPTH_TEST);
+
+        Ok(())
+    }
+}
+
+impl Drop for PostProcessChain {
+    fn drop(&mut self) {
+        self.gl.delete_buffer(self.quad_buffer.as_ref());
+    }
+}
@@ -0,0 +1,636 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use crate::model::hdr::HdrEncoding;
+
+/// Chunks shipped with the crate, available to every shader
+const BUILTIN_CHUNKS: &[(&str, &str)] = &[
+    ("matrix", include_str!("../shader/chunks/matrix.glsl")),
+    ("tangent_space", include_str!("../shader/chunks/tangent_space.glsl")),
+    ("brdf", include_str!("../shader/chunks/brdf.glsl")),
+    ("environment", include_str!("../shader/chunks/environment.glsl")),
+    ("ibl", include_str!("../shader/chunks/ibl.glsl")),
+    ("frame", include_str!("../shader/chunks/frame.glsl")),
+];
+
+/// Named GLSL snippets resolvable through `#include "name"`
+pub struct ShaderChunks {
+    chunks: HashMap<String, String>,
+}
+
+impl ShaderChunks {
+    /// Library holding the built-in chunks
+    pub fn new() -> Self {
+        let mut chunks = Self { chunks: HashMap::new() };
+        for (name, source) in BUILTIN_CHUNKS.iter() {
+            chunks.register(name, source);
+        }
+
+        chunks
+    }
+
+    /// Adds or replaces a chunk
+    pub fn register(&mut self, name: &str, source: &str) {
+        self.chunks.insert(name.to_string(), source.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.chunks.get(name).map(|source| source.as_str())
+    }
+}
+
+impl Default for ShaderChunks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Renderer features that change shader code
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderFeatures {
+    pub normal_map: bool,
+    pub base_color_map: bool,
+    pub metallic_roughness_map: bool,
+    pub occlusion_map: bool,
+    pub emissive_map: bool,
+    /// Image-based ambient lighting
+    pub ibl: bool,
+    /// `EXT_shader_texture_lod` is available for explicit cube map levels
+    pub texture_lod: bool,
+    /// GPU storage of an HDR environment, `None` for 8-bit sRGB
+    pub env_encoding: Option<HdrEncoding>,
+    pub shadows: bool,
+    pub instancing: bool,
+    /// Frame uniforms come from a WebGL2 uniform buffer
+    pub uniform_buffers: bool,
+    pub light_count: u32,
+}
+
+impl ShaderFeatures {
+    pub fn to_defines(&self) -> BTreeMap<String, String> {
+        let mut defines = BTreeMap::new();
+        if self.normal_map {
+            defines.insert("NORMAL_MAP".to_string(), "1".to_string());
+        }
+        let maps = [
+            (self.base_color_map, "BASE_COLOR_MAP"),
+            (self.metallic_roughness_map, "METALLIC_ROUGHNESS_MAP"),
+            (self.occlusion_map, "OCCLUSION_MAP"),
+            (self.emissive_map, "EMISSIVE_MAP"),
+            (self.ibl, "IBL"),
+            (self.texture_lod, "TEXTURE_LOD"),
+        ];
+        for (enabled, define) in maps.iter() {
+            if *enabled {
+                defines.insert(define.to_string(), "1".to_string());
+            }
+        }
+        match self.env_encoding {
+            Some(HdrEncoding::Rgbe) => {
+                defines.insert("ENV_RGBE".to_string(), "1".to_string());
+            }
+            Some(_) => {
+                defines.insert("ENV_LINEAR".to_string(), "1".to_string());
+            }
+            None => {}
+        }
+        if self.shadows {
+            defines.insert("SHADOWS".to_string(), "1".to_string());
+        }
+        if self.instancing {
+            defines.insert("INSTANCING".to_string(), "1".to_string());
+        }
+        if self.uniform_buffers {
+            defines.insert("UNIFORM_BUFFERS".to_string(), "1".to_string());
+        }
+        defines.insert("LIGHT_COUNT".to_string(), self.light_count.to_string());
+
+        defines
+    }
+}
+
+impl Default for ShaderFeatures {
+    fn default() -> Self {
+        Self {
+            normal_map: false,
+            base_color_map: false,
+            metallic_roughness_map: false,
+            occlusion_map: false,
+            emissive_map: false,
+            ibl: false,
+            texture_lod: false,
+            env_encoding: None,
+            shadows: false,
+            instancing: false,
+            uniform_buffers: false,
+            light_count: 1,
+        }
+    }
+}
+
+/// Stable string identifying a define set, used to key program variants
+pub fn defines_key(defines: &BTreeMap<String, String>) -> String {
+    defines
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Origin of an output line
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl From<PreprocessError> for String {
+    fn from(err: PreprocessError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Shader source with includes resolved and defines injected
+#[derive(Clone, Debug)]
+pub struct PreprocessedShader {
+    pub source: String,
+    /// Origin of every output line, index 0 is output line 1
+    pub line_map: Vec<SourceLine>,
+}
+
+impl PreprocessedShader {
+    pub fn source_line(&self, line: u32) -> Option<&SourceLine> {
+        if line == 0 {
+            return None;
+        }
+
+        self.line_map.get(line as usize - 1)
+    }
+
+    /// Rewrites `ERROR: 0:<line>:` locations of a GL info log to `<file>:<line>:`
+    pub fn map_error_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| self.map_error_line(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn map_error_line(&self, line: &str) -> String {
+        for prefix in ["ERROR: ", "WARNING: "].iter() {
+            if let Some(rest) = line.strip_prefix(prefix) {
+                let mut parts = rest.splitn(3, ':');
+                let (string_index, line_number, message) = (parts.next(), parts.next(), parts.next());
+
+                if let (Some(_), Some(line_number), Some(message)) = (string_index, line_number, message) {
+                    if let Some(source) = line_number.trim().parse().ok().and_then(|n| self.source_line(n)) {
+                        return format!("{}{}:{}:{}", prefix, source.file, source.line, message);
+                    }
+                }
+            }
+        }
+
+        line.to_string()
+    }
+}
+
+/// Resolves `#include "name"` directives and injects `#define`s after `#version`
+///
+/// `#if` branches are left to the GLSL compiler, so includes inside switched-off branches still
+/// have to resolve. Chunks included inside a conditional are wrapped in an `#ifndef` guard, which
+/// keeps include-once right whichever branches end up compiled. Includes inside comments are ignored.
+pub fn preprocess(
+    name: &str,
+    source: &str,
+    chunks: &ShaderChunks,
+    defines: &BTreeMap<String, String>,
+) -> Result<PreprocessedShader, PreprocessError> {
+    let mut output = PreprocessedShader { source: String::new(), line_map: vec![] };
+    let mut lines = source.lines().enumerate().peekable();
+
+    // `#version` has to stay the very first line
+    if let Some((_, first)) = lines.peek() {
+        if first.trim_start().starts_with("#version") {
+            push_line(&mut output, first, name, 1);
+            lines.next();
+        }
+    }
+
+    for (define, value) in defines.iter() {
+        push_line(&mut output, &format!("#define {} {}", define, value), "<defines>", 0);
+    }
+
+    let mut includes = Includes { emitted: HashMap::new(), stack: vec![name.to_string()], depth: 0 };
+    expand(&mut output, name, lines, chunks, &mut includes)?;
+
+    Ok(output)
+}
+
+/// Include bookkeeping shared by all files of one shader
+struct Includes {
+    /// Chunks emitted so far, true once one was emitted outside of any conditional
+    emitted: HashMap<String, bool>,
+    /// Files being expanded, to catch circular includes
+    stack: Vec<String>,
+    /// Nesting of `#if`/`#ifdef`/`#ifndef` blocks
+    depth: u32,
+}
+
+fn expand<'a, I: Iterator<Item = (usize, &'a str)>>(
+    output: &mut PreprocessedShader,
+    file: &str,
+    lines: I,
+    chunks: &ShaderChunks,
+    includes: &mut Includes,
+) -> Result<(), PreprocessError> {
+    let mut in_comment = false;
+
+    for (i, line) in lines {
+        let line_number = i as u32 + 1;
+        let trimmed = line.trim();
+        let directive = if in_comment { "" } else { trimmed };
+        in_comment = ends_in_comment(line, in_comment);
+
+        if directive.starts_with("#if") {
+            includes.depth += 1;
+        } else if directive.starts_with("#endif") {
+            includes.depth = includes.depth.saturating_sub(1);
+        }
+
+        if !directive.starts_with("#include") {
+            push_line(output, line, file, line_number);
+            continue;
+        }
+
+        let error = |message: String| PreprocessError { file: file.to_string(), line: line_number, message };
+
+        let chunk_name = parse_include(trimmed)
+            .ok_or_else(|| error(format!("Malformed include directive: {}", trimmed)))?;
+
+        if includes.stack.iter().any(|name| name == chunk_name) {
+            return Err(error(format!("Circular include of \"{}\"", chunk_name)));
+        }
+
+        // Every chunk is included once, like `#pragma once`
+        let unconditional = includes.depth == 0;
+        let guarded = match includes.emitted.get(chunk_name) {
+            Some(true) => continue,
+            Some(false) => true,
+            None => !unconditional,
+        };
+        includes.emitted.insert(chunk_name.to_string(), unconditional);
+
+        let chunk = chunks
+            .get(chunk_name)
+            .ok_or_else(|| error(format!("Unknown shader chunk \"{}\"", chunk_name)))?;
+
+        let guard = include_guard(chunk_name);
+        if guarded {
+            push_line(output, &format!("#ifndef {}", guard), chunk_name, 0);
+            push_line(output, &format!("#define {}", guard), chunk_name, 0);
+            includes.depth += 1;
+        }
+
+        includes.stack.push(chunk_name.to_string());
+        expand(output, chunk_name, chunk.lines().enumerate(), chunks, includes)?;
+        includes.stack.pop();
+
+        if guarded {
+            push_line(output, "#endif", chunk_name, 0);
+            includes.depth -= 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a `/* */` comment is still open at the end of `line`
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut rest = line;
+
+    loop {
+        if in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    in_comment = false;
+                }
+                None => return true,
+            }
+        } else {
+            let block = rest.find("/*");
+            let line_comment = rest.find("//");
+            match (block, line_comment) {
+                (Some(start), Some(line_start)) if line_start < start => return false,
+                (Some(start), _) => {
+                    rest = &rest[start + 2..];
+                    in_comment = true;
+                }
+                (None, _) => return false,
+            }
+        }
+    }
+}
+
+/// Macro defined by a guarded chunk, e.g. `CHUNK_TANGENT_SPACE` for `tangent_space`
+fn include_guard(chunk_name: &str) -> String {
+    let name: String = chunk_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+
+    format!("CHUNK_{}", name)
+}
+
+fn parse_include(directive: &str) -> Option<&str> {
+    let rest = directive.strip_prefix("#include")?.trim();
+
+    if rest.len() >= 2 && ((rest.starts_with('"') && rest.ends_with('"')) || (rest.starts_with('<') && rest.ends_with('>'))) {
+        Some(&rest[1..rest.len() - 1])
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+/// Extensions of GLSL ES 1.00 that are core in 3.00
+const CORE_EXTENSIONS: &[&str] = &["GL_EXT_shader_texture_lod", "GL_OES_standard_derivatives", "GL_EXT_frag_depth"];
+
+/// Translates a preprocessed GLSL ES 1.00 shader to 3.00, sources with a `#version` are returned as is
+///
+/// Storage qualifiers, texture lookups and `gl_FragColor` are renamed, line mapping is kept.
+pub fn to_glsl_es300(shader: &PreprocessedShader, stage: ShaderStage) -> PreprocessedShader {
+    if shader.source.trim_start().starts_with("#version") {
+        return shader.clone();
+    }
+
+    let mut output = PreprocessedShader { source: String::new(), line_map: vec![] };
+    push_line(&mut output, "#version 300 es", "<version>", 0);
+    if stage == ShaderStage::Fragment && shader.source.contains("gl_FragColor") {
+        push_line(&mut output, "out highp vec4 frag_color;", "<version>", 0);
+    }
+
+    for (line, origin) in shader.source.lines().zip(shader.line_map.iter()) {
+        let trimmed = line.trim_start();
+        let core_extension = trimmed.starts_with("#extension")
+            && CORE_EXTENSIONS.iter().any(|extension| trimmed.contains(extension));
+
+        let line = if core_extension { format!("// {}", trimmed) } else { rename_identifiers(line, stage) };
+        push_line(&mut output, &line, &origin.file, origin.line);
+    }
+
+    output
+}
+
+fn rename_identifier(identifier: &str, stage: ShaderStage) -> &str {
+    match (identifier, stage) {
+        ("attribute", ShaderStage::Vertex) => "in",
+        ("varying", ShaderStage::Vertex) => "out",
+        ("varying", ShaderStage::Fragment) => "in",
+        ("gl_FragColor", ShaderStage::Fragment) => "frag_color",
+        ("texture2D", _) | ("textureCube", _) => "texture",
+        ("texture2DProj", _) => "textureProj",
+        ("texture2DLod", _) | ("textureCubeLod", _) | ("texture2DLodEXT", _) | ("textureCubeLodEXT", _) => "textureLod",
+        ("texture2DGradEXT", _) | ("textureCubeGradEXT", _) => "textureGrad",
+        _ => identifier,
+    }
+}
+
+fn rename_identifiers(line: &str, stage: ShaderStage) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut identifier = String::new();
+
+    for c in line.chars() {
+        // Digits only continue identifiers, so numbers like `2D` inside literals stay untouched
+        if c == '_' || c.is_ascii_alphabetic() || (c.is_ascii_digit() && !identifier.is_empty()) {
+            identifier.push(c);
+            continue;
+        }
+
+        output.push_str(rename_identifier(&identifier, stage));
+        identifier.clear();
+        output.push(c);
+    }
+    output.push_str(rename_identifier(&identifier, stage));
+
+    output
+}
+
+fn push_line(output: &mut PreprocessedShader, line: &str, file: &str, line_number: u32) {
+    output.source.push_str(line);
+    output.source.push('\n');
+    output.line_map.push(SourceLine { file: file.to_string(), line: line_number });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::default_pipeline::{PBR_FRAG, PBR_VERT};
+
+    fn chunks() -> ShaderChunks {
+        let mut chunks = ShaderChunks::new();
+        chunks.register("a", "float a() { return 1.0; }\n#include \"b\"");
+        chunks.register("b", "float b() {\n    return 2.0;\n}");
+        chunks.register("loop", "#include \"loop\"");
+        chunks
+    }
+
+    fn defines(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn includes_are_expanded_recursively() {
+        let out = preprocess("main", "#include \"a\"\nvoid main() {}", &chunks(), &BTreeMap::new()).unwrap();
+
+        assert_eq!(out.source, "float a() { return 1.0; }\nfloat b() {\n    return 2.0;\n}\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn chunks_are_included_once() {
+        let out = preprocess("main", "#include \"b\"\n#include <b>\n#include \"a\"", &chunks(), &BTreeMap::new()).unwrap();
+
+        assert_eq!(out.source.matches("float b()").count(), 1);
+    }
+
+    #[test]
+    fn defines_follow_the_version_directive() {
+        let out = preprocess(
+            "main",
+            "#version 300 es\nvoid main() {}",
+            &chunks(),
+            &defines(&[("LIGHT_COUNT", "4"), ("NORMAL_MAP", "1")]),
+        ).unwrap();
+
+        assert_eq!(out.source, "#version 300 es\n#define LIGHT_COUNT 4\n#define NORMAL_MAP 1\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn output_lines_map_back_to_chunks() {
+        let out = preprocess("main", "#include \"a\"\nvoid main() {}", &chunks(), &defines(&[("X", "1")])).unwrap();
+
+        assert_eq!(out.source_line(1), Some(&SourceLine { file: "<defines>".to_string(), line: 0 }));
+        assert_eq!(out.source_line(2), Some(&SourceLine { file: "a".to_string(), line: 1 }));
+        assert_eq!(out.source_line(4), Some(&SourceLine { file: "b".to_string(), line: 2 }));
+        assert_eq!(out.source_line(6), Some(&SourceLine { file: "main".to_string(), line: 2 }));
+    }
+
+    #[test]
+    fn error_logs_point_at_original_files() {
+        let out = preprocess("obj.vert", "#include \"a\"\nvoid main() { x; }", &chunks(), &BTreeMap::new()).unwrap();
+
+        let log = "ERROR: 0:3: 'return' : syntax error\nERROR: 0:5: 'x' : undeclared identifier\n1 compilation errors";
+        assert_eq!(
+            out.map_error_log(log),
+            "ERROR: b:2: 'return' : syntax error\nERROR: obj.vert:2: 'x' : undeclared identifier\n1 compilation errors"
+        );
+    }
+
+    #[test]
+    fn unknown_and_circular_includes_are_errors() {
+        let err = preprocess("main", "void f();\n#include \"missing\"", &chunks(), &BTreeMap::new()).unwrap_err();
+        assert_eq!((err.file.as_str(), err.line), ("main", 2));
+
+        let err = preprocess("main", "#include \"loop\"", &chunks(), &BTreeMap::new()).unwrap_err();
+        assert!(err.message.contains("Circular"));
+        assert_eq!(err.file, "loop");
+    }
+
+    #[test]
+    fn commented_includes_are_ignored() {
+        let source = "// #include \"missing\"\n/* block\n#include \"missing\"\n*/ #include \"b\"\nvoid main() {}";
+        let out = preprocess("main", source, &chunks(), &BTreeMap::new()).unwrap();
+
+        assert_eq!(out.source, format!("{}\n", source));
+    }
+
+    #[test]
+    fn conditional_includes_are_guarded() {
+        let source = "#ifdef FOO\n#include \"b\"\n#endif\n#include \"b\"\n#include \"b\"";
+        let out = preprocess("main", source, &chunks(), &BTreeMap::new()).unwrap();
+
+        // A switched-off first include doesn't drop the second one, the third one is redundant
+        let guarded = "#ifndef CHUNK_B\n#define CHUNK_B\nfloat b() {\n    return 2.0;\n}\n#endif\n";
+        assert_eq!(out.source, format!("#ifdef FOO\n{}#endif\n{}", guarded, guarded));
+        assert_eq!(out.source_line(4), Some(&SourceLine { file: "b".to_string(), line: 1 }));
+    }
+
+    #[test]
+    fn includes_in_switched_off_branches_still_resolve() {
+        let err = preprocess("main", "#if 0\n#include \"missing\"\n#endif", &chunks(), &BTreeMap::new()).unwrap_err();
+
+        assert_eq!((err.file.as_str(), err.line), ("main", 2));
+    }
+
+    #[test]
+    fn features_produce_a_stable_key() {
+        let features = ShaderFeatures { normal_map: true, light_count: 2, ..ShaderFeatures::default() };
+
+        assert_eq!(defines_key(&features.to_defines()), "LIGHT_COUNT=2;NORMAL_MAP=1");
+    }
+
+    #[test]
+    fn builtin_matrix_chunk_is_available() {
+        let out = preprocess("main", "#include \"tangent_space\"", &ShaderChunks::new(), &BTreeMap::new()).unwrap();
+
+        assert!(out.source.contains("mat3 inverse(mat3 matrix)"));
+        assert!(out.source.contains("mat3 tangent_space("));
+    }
+
+    #[test]
+    fn benchmark_shader_uses_the_matrix_chunk() {
+        let source = include_str!("../../../main/assets/resources/shader/obj.vert.glsl");
+        let out = preprocess("obj.vert", source, &ShaderChunks::new(), &BTreeMap::new()).unwrap();
+
+        assert_eq!(out.source.matches("mat3 inverse(mat3 matrix)").count(), 1);
+    }
+
+    #[test]
+    fn builtin_pbr_shaders_resolve_their_chunks() {
+        let features = ShaderFeatures { normal_map: true, base_color_map: true, ibl: true, light_count: 2, ..ShaderFeatures::default() };
+        let defines = features.to_defines();
+
+        let vert = preprocess("pbr.vert", PBR_VERT, &ShaderChunks::new(), &defines).unwrap();
+        let frag = preprocess("pbr.frag", PBR_FRAG, &ShaderChunks::new(), &defines).unwrap();
+
+        assert!(vert.source.contains("mat3 inverse(mat3 matrix)"));
+        assert!(frag.source.contains("#define BASE_COLOR_MAP 1\n#define IBL 1\n#define LIGHT_COUNT 2\n#define NORMAL_MAP 1"));
+        assert!(frag.source.contains("vec3 irradiance_sh(vec3 n)"));
+        assert_eq!(frag.source.matches("float distribution_ggx(").count(), 1);
+    }
+
+    #[test]
+    fn es100_shaders_translate_to_es300() {
+        let vert = preprocess("v", "attribute vec3 vert_pos;\nvarying vec2 uv2;\nvoid main() {}", &chunks(), &BTreeMap::new()).unwrap();
+        let vert = to_glsl_es300(&vert, ShaderStage::Vertex);
+        assert_eq!(vert.source, "#version 300 es\nin vec3 vert_pos;\nout vec2 uv2;\nvoid main() {}\n");
+
+        let source = "#extension GL_EXT_shader_texture_lod : enable\nvarying vec2 uv;\nuniform sampler2D my_texture2D;\n\
+            void main() { gl_FragColor = texture2D(my_texture2D, uv) + textureCubeLodEXT(env, dir, 1.0); }";
+        let frag = preprocess("f", source, &chunks(), &BTreeMap::new()).unwrap();
+        let frag = to_glsl_es300(&frag, ShaderStage::Fragment);
+
+        assert_eq!(
+            frag.source,
+            "#version 300 es\nout highp vec4 frag_color;\n// #extension GL_EXT_shader_texture_lod : enable\nin vec2 uv;\n\
+            uniform sampler2D my_texture2D;\n\
+            void main() { frag_color = texture(my_texture2D, uv) + textureLod(env, dir, 1.0); }\n"
+        );
+        // Errors still point at the original lines
+        assert_eq!(frag.source_line(4), Some(&SourceLine { file: "f".to_string(), line: 2 }));
+    }
+
+    #[test]
+    fn es300_shaders_are_left_alone() {
+        let shader = preprocess("v", "#version 300 es\nin vec3 varying_pos;", &chunks(), &BTreeMap::new()).unwrap();
+
+        assert_eq!(to_glsl_es300(&shader, ShaderStage::Vertex).source, shader.source);
+    }
+
+    #[test]
+    fn builtin_pbr_shaders_translate() {
+        let features = ShaderFeatures { ibl: true, texture_lod: true, instancing: true, uniform_buffers: true, ..ShaderFeatures::default() };
+        let defines = features.to_defines();
+
+        let frag = preprocess("pbr.frag", PBR_FRAG, &ShaderChunks::new(), &defines).unwrap();
+        let frag = to_glsl_es300(&frag, ShaderStage::Fragment);
+        for legacy in ["texture2D", "textureCube", "gl_FragColor", "varying", "#extension"].iter() {
+            assert!(!frag.source.lines().any(|line| line.contains(legacy) && !line.starts_with("//")), "{} left", legacy);
+        }
+
+        let vert = preprocess("pbr.vert", PBR_VERT, &ShaderChunks::new(), &defines).unwrap();
+        let vert = to_glsl_es300(&vert, ShaderStage::Vertex);
+        assert!(vert.source.contains("in mat4 instance_model;"));
+        assert!(vert.source.contains("layout(std140) uniform Frame"));
+    }
+
+    #[test]
+    fn hdr_environments_pick_their_decoding() {
+        let defines = |encoding| ShaderFeatures { env_encoding: encoding, ..ShaderFeatures::default() }.to_defines();
+
+        assert!(defines(Some(HdrEncoding::Rgbe)).contains_key("ENV_RGBE"));
+        assert!(defines(Some(HdrEncoding::HalfFloat)).contains_key("ENV_LINEAR"));
+        assert!(!defines(None).keys().any(|name| name.starts_with("ENV_")));
+
+        let skybox = include_str!("../shader/skybox/skybox.frag.glsl");
+        let frag = preprocess("skybox.frag", skybox, &ShaderChunks::new(), &defines(Some(HdrEncoding::Float))).unwrap();
+        assert!(frag.source.contains("#define ENV_LINEAR 1"));
+        assert!(frag.source.contains("vec3 decode_environment(vec4 texel)"));
+    }
+}
@@ -1,86 +1,133 @@
-use web_sys::WebGlBuffer;
+use std::cell::RefCell;
 use crate::model::Geometry;
-use web_sys::WebGlRenderingContext as GL;
+use crate::model::backend::IndexType;
+use crate::model::device::{DrawCall, GraphicsDevice};
+use crate::model::gl as GL;
 
 /// GPU-side primitive geometry
-pub struct Primitive {
-    pub gl: GL,
+pub struct Primitive<D: GraphicsDevice> {
+    pub device: D,
     // pub vertex_buffer: Option<WebGlBuffer>,
-    pub position_buffer: Option<WebGlBuffer>,
-    pub uv_buffer: Option<WebGlBuffer>,
-    pub tang_buffer: Option<WebGlBuffer>,
-    pub bitang_buffer: Option<WebGlBuffer>,
-    pub vertex_count: i32
+    pub position_buffer: Option<D::Buffer>,
+    pub uv_buffer: Option<D::Buffer>,
+    pub tang_buffer: Option<D::Buffer>,
+    pub bitang_buffer: Option<D::Buffer>,
+    /// Element buffer with its type and length, `None` draws the vertices in order
+    pub index_buffer: Option<(D::Buffer, IndexType, i32)>,
+    pub vertex_count: i32,
+    /// Vertex array objects by the id of the pipeline whose layout they hold
+    vertex_arrays: RefCell<Vec<(u32, D::VertexArray)>>,
 }
 
-impl Primitive {
-    pub fn from_raw(gl: GL, vec_vertices: &Vec<f32>, vec_uvs: &Vec<f32>, vec_tang: &Vec<f32>, vec_bitang: &Vec<f32>, vertex_count: i32) -> Self {
+/// Uploads a slice as a static buffer
+fn create_buffer<D: GraphicsDevice, T>(device: &D, target: u32, data: &[T]) -> Option<D::Buffer> {
+    let buffer = device.create_buffer();
+    device.bind_buffer(target, buffer.as_ref());
+    let u8_slice = unsafe {
+        std::slice::from_raw_parts(
+            data.as_ptr() as *const u8,
+            std::mem::size_of_val(data),
+        )
+    };
+    device.buffer_data(target, u8_slice, GL::STATIC_DRAW);
 
-        let position_buffer = gl.create_buffer();
-        gl.bind_buffer(GL::ARRAY_BUFFER, position_buffer.as_ref());
-        let u8_slice = unsafe {
-            std::slice::from_raw_parts(
-                vec_vertices.as_ptr() as *const u8,
-                vec_vertices.len() * std::mem::size_of::<f32>(),
-            )
-        };
-        gl.buffer_data_with_u8_array(GL::ARRAY_BUFFER, u8_slice, GL::STATIC_DRAW);
-
-        let uv_buffer = gl.create_buffer();
-        gl.bind_buffer(GL::ARRAY_BUFFER, uv_buffer.as_ref());
-        let u8_slice = unsafe {
-            std::slice::from_raw_parts(
-                vec_uvs.as_ptr() as *const u8,
-                vec_uvs.len() * std::mem::size_of::<f32>(),
-            )
-        };
-        gl.buffer_data_with_u8_array(GL::ARRAY_BUFFER, u8_slice, GL::STATIC_DRAW);
-
-        let tang_buffer = gl.create_buffer();
-        gl.bind_buffer(GL::ARRAY_BUFFER, tang_buffer.as_ref());
-        let u8_slice = unsafe {
-            std::slice::from_raw_parts(
-                vec_tang.as_ptr() as *const u8,
-                vec_tang.len() * std::mem::size_of::<f32>(),
-            )
-        };
-        gl.buffer_data_with_u8_array(GL::ARRAY_BUFFER, u8_slice, GL::STATIC_DRAW);
-
-        let bitang_buffer = gl.create_buffer();
-        gl.bind_buffer(GL::ARRAY_BUFFER, bitang_buffer.as_ref());
-        let u8_slice = unsafe {
-            std::slice::from_raw_parts(
-                vec_bitang.as_ptr() as *const u8,
-                vec_bitang.len() * std::mem::size_of::<f32>(),
-            )
-        };
-        gl.buffer_data_with_u8_array(GL::ARRAY_BUFFER, u8_slice, GL::STATIC_DRAW);
+    buffer
+}
+
+impl<D: GraphicsDevice> Primitive<D> {
+    pub fn from_raw(device: D, vec_vertices: &[f32], vec_uvs: &[f32], vec_tang: &[f32], vec_bitang: &[f32], vertex_count: i32) -> Self {
+        let position_buffer = create_buffer(&device, GL::ARRAY_BUFFER, vec_vertices);
+        let uv_buffer = create_buffer(&device, GL::ARRAY_BUFFER, vec_uvs);
+        let tang_buffer = create_buffer(&device, GL::ARRAY_BUFFER, vec_tang);
+        let bitang_buffer = create_buffer(&device, GL::ARRAY_BUFFER, vec_bitang);
 
         Self {
-            gl,
+            device,
             position_buffer,
             uv_buffer,
             tang_buffer,
             bitang_buffer,
-            vertex_count
+            index_buffer: None,
+            vertex_count,
+            vertex_arrays: RefCell::new(vec![]),
+        }
+    }
+
+    /// Uploads the geometry with shared vertices merged, it stays unindexed when the merged vertices don't fit
+    /// 16-bit indices and the device lacks 32-bit ones
+    pub fn new(device: D, geometry: &Geometry) -> Self {
+        let indexed = geometry.indexed();
+
+        let index_type = match IndexType::pick(indexed.vertices.len() / 3, device.features().uint_indices) {
+            Some(index_type) => index_type,
+            None => return Self::from_raw(device, &geometry.vertices, &geometry.uvs, &geometry.tangents, &geometry.bitangents, geometry.vertex_count),
+        };
+
+        let mut primitive = Self::from_raw(device, &indexed.vertices, &indexed.uvs, &indexed.tangents, &indexed.bitangents, indexed.vertex_count);
+        // No vertex array is bound while primitives are created, so this doesn't rewire one
+        let buffer = match index_type {
+            IndexType::U16 => {
+                let indices: Vec<u16> = indexed.indices.iter().map(|&index| index as u16).collect();
+                create_buffer(&primitive.device, GL::ELEMENT_ARRAY_BUFFER, &indices)
+            }
+            IndexType::U32 => create_buffer(&primitive.device, GL::ELEMENT_ARRAY_BUFFER, &indexed.indices),
+        };
+        primitive.index_buffer = buffer.map(|buffer| (buffer, index_type, indexed.indices.len() as i32));
+
+        primitive
+    }
+
+    /// Binds the element buffer to the current vertex array, or globally without vertex arrays
+    pub fn bind_indices(&self) {
+        if let Some((buffer, _, _)) = self.index_buffer.as_ref() {
+            self.device.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(buffer));
         }
     }
 
-    pub fn new(gl: GL, geometry: &Geometry) -> Self {
-        Self::from_raw(gl, &geometry.vertices, &geometry.uvs, &geometry.tangents, &geometry.bitangents, geometry.vertex_count)
+    /// Vertex array holding the attribute layout of a pipeline, `setup` records it on first use
+    ///
+    /// `None` on devices without vertex arrays.
+    pub fn vertex_array(&self, pipeline: u32, setup: impl FnOnce(&D::VertexArray)) -> Option<D::VertexArray> {
+        let mut vertex_arrays = self.vertex_arrays.borrow_mut();
+        if let Some((_, vertex_array)) = vertex_arrays.iter().find(|(id, _)| *id == pipeline) {
+            return Some(vertex_array.clone());
+        }
+
+        let vertex_array = self.device.create_vertex_array()?;
+        setup(&vertex_array);
+        vertex_arrays.push((pipeline, vertex_array.clone()));
+
+        Some(vertex_array)
+    }
+
+    fn draw_call(&self, instances: Option<i32>) -> DrawCall {
+        match self.index_buffer.as_ref() {
+            Some((_, index_type, count)) => DrawCall { count: *count, index_type: Some(*index_type), instances },
+            None => DrawCall { count: self.vertex_count, index_type: None, instances },
+        }
     }
 
     pub fn draw(&self) {
-        self.gl
-            .draw_arrays(GL::TRIANGLES, 0, self.vertex_count);
+        self.device.draw(&self.draw_call(None));
+    }
+
+    /// Draws `instances` copies, the device has to support instancing
+    pub fn draw_instanced(&self, instances: i32) {
+        self.device.draw(&self.draw_call(Some(instances)));
     }
 }
 
-impl Drop for Primitive {
+impl<D: GraphicsDevice> Drop for Primitive<D> {
     fn drop(&mut self) {
-        self.gl.delete_buffer(self.position_buffer.as_ref());
-        self.gl.delete_buffer(self.uv_buffer.as_ref());
-        self.gl.delete_buffer(self.tang_buffer.as_ref());
-        self.gl.delete_buffer(self.bitang_buffer.as_ref());
+        for (_, vertex_array) in self.vertex_arrays.borrow().iter() {
+            self.device.delete_vertex_array(vertex_array);
+        }
+        let buffers = [&self.position_buffer, &self.uv_buffer, &self.tang_buffer, &self.bitang_buffer];
+        for buffer in buffers.iter().filter_map(|buffer| buffer.as_ref()) {
+            self.device.delete_buffer(buffer);
+        }
+        if let Some((buffer, _, _)) = self.index_buffer.as_ref() {
+            self.device.delete_buffer(buffer);
+        }
     }
-}
\ No newline at end of file
+}
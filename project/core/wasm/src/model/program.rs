@@ -1,80 +1,140 @@
-use web_sys::{WebGlRenderingContext as GL, WebGlProgram, WebGlUniformLocation, WebGlShader};
+use crate::model::gl as GL;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use crate::model::{PreprocessedShader, ProgramReflection, UniformValue};
+use crate::model::common::now_ms;
+use crate::model::device::GraphicsDevice;
+
+static NEXT_PROGRAM_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Time spent building a program, in milliseconds
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct ProgramTimings {
+    pub compile_ms: f64,
+    pub link_ms: f64,
+}
 
-pub struct Program {
-    pub gl: GL,
-    pub program: WebGlProgram,
+pub struct Program<D: GraphicsDevice> {
+    pub device: D,
+    pub handle: D::Program,
+    /// Unique id used for state sorting and caching
+    pub id: u32,
+    /// Active uniforms and attributes enumerated after linking
+    pub reflection: ProgramReflection,
+    pub timings: ProgramTimings,
+    /// Compiled from GLSL ES 3.00 sources
+    pub es300: bool,
+    uniform_locations: HashMap<String, D::UniformLocation>,
+    /// Last value written to each uniform
+    uniform_values: RefCell<HashMap<String, UniformValue>>,
 }
 
-impl Program {
-    pub fn new(gl: GL, vert_src: &str, frag_src: &str) -> Self {
-        let vert_shader = compile_shader(&gl, GL::VERTEX_SHADER, vert_src);
-        let frag_shader = compile_shader(&gl, GL::FRAGMENT_SHADER, frag_src);
+impl<D: GraphicsDevice> Program<D> {
+    /// Compiles and links built-in sources, panics on failure
+    pub fn new(device: D, vert_src: &str, frag_src: &str) -> Self {
+        Self::try_new(device, vert_src, frag_src).unwrap_or_else(|msg| panic!("{}", msg))
+    }
+
+    pub fn try_new(device: D, vert_src: &str, frag_src: &str) -> Result<Self, String> {
+        let start = now_ms();
+        let vert_shader = device.compile_shader(GL::VERTEX_SHADER, vert_src)?;
+        let frag_shader = device.compile_shader(GL::FRAGMENT_SHADER, frag_src).inspect_err(|_| {
+            device.delete_shader(&vert_shader);
+        })?;
+
+        let compiled = now_ms();
+        let handle = device.link_program(vert_shader, frag_shader)?;
+        let timings = ProgramTimings { compile_ms: compiled - start, link_ms: now_ms() - compiled };
+
+        let id = NEXT_PROGRAM_ID.fetch_add(1, Ordering::Relaxed);
+        let (reflection, uniform_locations) = device.reflect_program(&handle);
 
-        let program = link_program(&gl, vert_shader, frag_shader);
+        let es300 = vert_src.trim_start().starts_with("#version 300 es");
 
-        Self { gl, program }
+        Ok(Self { device, handle, id, reflection, timings, es300, uniform_locations, uniform_values: RefCell::new(HashMap::new()) })
+    }
+
+    /// Builds a program from preprocessed sources, error locations point at the original chunks
+    pub fn from_preprocessed(device: D, vert: &PreprocessedShader, frag: &PreprocessedShader) -> Result<Self, String> {
+        Self::try_new(device, &vert.source, &frag.source).map_err(|msg| {
+            if msg.starts_with(VERT_ERROR) {
+                vert.map_error_log(&msg)
+            } else if msg.starts_with(FRAG_ERROR) {
+                frag.map_error_log(&msg)
+            } else {
+                msg
+            }
+        })
     }
 
     pub fn bind(&self) {
-        self.gl.use_program(Some(&self.program));
+        self.device.use_program(Some(&self.handle));
     }
 
+    /// Location of an active attribute, -1 when the shader does not use it
     pub fn get_attrib_loc(&self, name: &str) -> i32 {
-        self.gl.get_attrib_location(&self.program, name)
+        self.reflection.attributes.get(name).map_or(-1, |attribute| attribute.location)
     }
 
-    pub fn get_uniform_loc(&self, name: &str) -> Option<WebGlUniformLocation> {
-        self.gl.get_uniform_location(&self.program, name)
+    /// Validates a value against the reflected uniform type without touching GL
+    pub fn check_uniform(&self, name: &str, value: &UniformValue) -> Result<(), String> {
+        let uniform = self.reflection.uniforms
+            .get(name)
+            .ok_or_else(|| format!("Uniform '{}' is not active in the program", name))?;
+
+        if !value.accepts(uniform.gl_type) {
+            return Err(format!(
+                "Uniform '{}' is declared as {}, got a {} value",
+                name, uniform.type_name, value.type_name()
+            ));
+        }
+
+        if let Some(len) = value.array_len() {
+            if len > uniform.size as usize {
+                return Err(format!(
+                    "Uniform '{}' has {} elements, got {} values",
+                    name, uniform.size, len
+                ));
+            }
+        }
+
+        Ok(())
     }
-}
 
-impl Drop for Program {
-    fn drop(&mut self) {
-        self.gl.delete_program(Some(&self.program));
-    }
-}
+    /// Sets a uniform of this program, skipping the GL call when the value is unchanged
+    ///
+    /// The program must be in use.
+    pub fn set_uniform(&self, name: &str, value: UniformValue) -> Result<(), String> {
+        self.check_uniform(name, &value)?;
+
+        let mut values = self.uniform_values.borrow_mut();
+        if values.get(name) == Some(&value) {
+            return Ok(());
+        }
+
+        self.device.set_uniform(&self.uniform_locations[name], &value);
+        values.insert(name.to_string(), value);
 
-/// Compiles source code into a shader object
-fn compile_shader(gl: &GL, shader_type: u32, source: &str) -> WebGlShader {
-    let shader = gl.create_shader(shader_type).unwrap();
-    gl.shader_source(&shader, source);
-    gl.compile_shader(&shader);
-
-    if !gl
-        .get_shader_parameter(&shader, GL::COMPILE_STATUS)
-        .as_bool()
-        .unwrap()
-    {
-        let msg = gl
-            .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error"));
-        panic!("Failed to compile shader: {}", msg);
+        Ok(())
     }
 
-    shader
+    /// Sets a uniform only if the shader declares it, for optional built-in uniforms
+    pub fn set_optional_uniform(&self, name: &str, value: UniformValue) -> Result<(), String> {
+        if self.reflection.uniforms.contains_key(name) {
+            self.set_uniform(name, value)
+        } else {
+            Ok(())
+        }
+    }
 }
 
-/// Links vertex and fragment shader into a shader program
-fn link_program(gl: &GL, vert: WebGlShader, frag: WebGlShader) -> WebGlProgram {
-    let program = gl.create_program().unwrap();
-
-    gl.attach_shader(&program, &vert);
-    gl.attach_shader(&program, &frag);
-    gl.link_program(&program);
-
-    if !gl
-        .get_program_parameter(&program, GL::LINK_STATUS)
-        .as_bool()
-        .unwrap()
-    {
-        let msg = gl
-            .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error"));
-        panic!("Failed to link program: {}", msg);
+/// Runs once the last pipeline sharing the program lets go of it
+impl<D: GraphicsDevice> Drop for Program<D> {
+    fn drop(&mut self) {
+        self.device.delete_program(&self.handle);
     }
+}
 
-    gl.delete_shader(Some(&vert));
-    gl.delete_shader(Some(&frag));
-
-    program
-}
\ No newline at end of file
+pub const VERT_ERROR: &str = "Failed to compile vertex shader";
+pub const FRAG_ERROR: &str = "Failed to compile fragment shader";
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+use web_sys::WebGlRenderingContext as GL;
+use crate::model::Program;
+use crate::model::preprocessor::defines_key;
+use crate::model::device::WebGlDevice;
+
+thread_local! {
+    /// Programs alive per GL context, shared by every pipeline drawing into it
+    static PROGRAMS: RefCell<Vec<(GL, WeakCache<Program<WebGlDevice>>)>> = const { RefCell::new(vec![]) };
+}
+
+/// Hash identifying a program by its final sources and define set
+pub fn program_key(vert_src: &str, frag_src: &str, defines: &BTreeMap<String, String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    vert_src.hash(&mut hasher);
+    frag_src.hash(&mut hasher);
+    defines_key(defines).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Map of weak references, entries disappear once the last owner drops its value
+pub struct WeakCache<T> {
+    entries: HashMap<u64, Weak<T>>,
+}
+
+impl<T> WeakCache<T> {
+    pub fn new() -> Self {
+        Self { entries: HashMap::new() }
+    }
+
+    pub fn get(&self, key: u64) -> Option<Rc<T>> {
+        self.entries.get(&key).and_then(|entry| entry.upgrade())
+    }
+
+    pub fn insert(&mut self, key: u64, value: &Rc<T>) {
+        self.entries.insert(key, Rc::downgrade(value));
+    }
+
+    /// Forgets entries whose value has been dropped
+    pub fn prune(&mut self) {
+        self.entries.retain(|_, entry| entry.strong_count() > 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T> Default for WeakCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the live program for the key, `None` when it has to be compiled
+pub fn find_program(gl: &GL, key: u64) -> Option<Rc<Program<WebGlDevice>>> {
+    PROGRAMS.with(|programs| {
+        programs
+            .borrow()
+            .iter()
+            .find(|(context, _)| context == gl)
+            .and_then(|(_, cache)| cache.get(key))
+    })
+}
+
+/// Makes a compiled program available to other pipelines on the same GL context
+pub fn share_program(gl: &GL, key: u64, program: &Rc<Program<WebGlDevice>>) {
+    PROGRAMS.with(|programs| {
+        let mut programs = programs.borrow_mut();
+
+        for (_, cache) in programs.iter_mut() {
+            cache.prune();
+        }
+        programs.retain(|(context, cache)| context == gl || !cache.is_empty());
+
+        match programs.iter_mut().find(|(context, _)| context == gl) {
+            Some((_, cache)) => cache.insert(key, program),
+            None => {
+                let mut cache = WeakCache::new();
+                cache.insert(key, program);
+                programs.push((gl.clone(), cache));
+            }
+        }
+    });
+}
+
+/// Drops the programs of a GL context, needed once it was lost since its programs died with it
+pub fn forget_context(gl: &GL) {
+    PROGRAMS.with(|programs| programs.borrow_mut().retain(|(context, _)| context != gl));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defines(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn key_depends_on_sources_and_defines() {
+        let key = program_key("vert", "frag", &defines(&[("A", "1")]));
+
+        assert_eq!(key, program_key("vert", "frag", &defines(&[("A", "1")])));
+        assert_ne!(key, program_key("vert", "frag", &defines(&[("A", "2")])));
+        assert_ne!(key, program_key("frag", "vert", &defines(&[("A", "1")])));
+        assert_ne!(key, program_key("vert2", "frag", &defines(&[("A", "1")])));
+    }
+
+    #[test]
+    fn values_are_shared_while_alive() {
+        let mut cache = WeakCache::new();
+        let value = Rc::new("program".to_string());
+        cache.insert(1, &value);
+
+        let shared = cache.get(1).unwrap();
+        assert!(Rc::ptr_eq(&value, &shared));
+        assert_eq!(Rc::strong_count(&value), 2);
+        assert!(cache.get(2).is_none());
+    }
+
+    #[test]
+    fn dropped_values_are_not_returned_and_pruned() {
+        let mut cache = WeakCache::new();
+        let value = Rc::new(1);
+        cache.insert(1, &value);
+        drop(value);
+
+        assert!(cache.get(1).is_none());
+        assert!(!cache.is_empty());
+        cache.prune();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,475 @@
+//! CPU rasterizer of `obj.vert.glsl` and `obj.frag.glsl`, renders frames into an `Image`
+//!
+//! Follows the WebGL draw path closely enough to compare frames against golden images without a
+//! GPU: clipping against the view volume, back-face culling, perspective-correct varyings, a
+//! `LEQUAL` depth test and bilinear, edge-clamped texture lookups. Rows are stored top-down, as
+//! the canvas shows them.
+
+use nalgebra as na;
+use crate::model::{Geometry, Image, Node};
+use crate::model::scene;
+
+/// `light_pos` of the vertex shader, in view space
+const LIGHT_POS: [f32; 3] = [1.0, 2.0, 0.0];
+
+/// `frag_uv`, `ts_light_pos` and `ts_frag_pos`, `ts_view_pos` only feeds the unused `view_dir`
+const VARYINGS: usize = 8;
+
+/// View volume as `dot(plane, position) >= 0` in clip space
+const CLIP_PLANES: [[f32; 4]; 6] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [-1.0, 0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+    [0.0, -1.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 1.0],
+    [0.0, 0.0, -1.0, 1.0],
+];
+
+/// Textures and uniforms of `obj.frag.glsl`
+pub struct SceneTextures<'a> {
+    pub diffuse: &'a Image,
+    pub norm: &'a Image,
+    /// Fragments with a lower diffuse alpha are discarded
+    pub alpha_cutoff: f32,
+}
+
+/// Vertex shader output
+#[derive(Clone, Copy)]
+struct ClipVertex {
+    position: na::Vector4<f32>,
+    varyings: [f32; VARYINGS],
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut varyings = self.varyings;
+        for (varying, target) in varyings.iter_mut().zip(other.varyings.iter()) {
+            *varying += (target - *varying) * t;
+        }
+
+        Self { position: self.position + (other.position - self.position) * t, varyings }
+    }
+}
+
+/// Vertex in pixel coordinates, varyings are divided by w for perspective-correct interpolation
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    /// Window depth in [0, 1]
+    z: f32,
+    inv_w: f32,
+    varyings: [f32; VARYINGS],
+}
+
+/// Color and depth buffer drawn on the CPU
+pub struct Rasterizer {
+    pub width: u32,
+    pub height: u32,
+    /// RGB, 8 bits per channel
+    color: Vec<u8>,
+    depth: Vec<f32>,
+}
+
+impl Rasterizer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = (width * height) as usize;
+
+        Self { width, height, color: vec![0; pixels * 3], depth: vec![1.0; pixels] }
+    }
+
+    /// Clears to opaque black and the far plane, as `Context::draw` does
+    pub fn clear(&mut self) {
+        self.color.iter_mut().for_each(|value| *value = 0);
+        self.depth.iter_mut().for_each(|depth| *depth = 1.0);
+    }
+
+    /// Rotates the nodes for time `t` and draws those `Scene::draw_nodes` would, returns how many
+    pub fn draw_nodes(&mut self, meshes: &[Geometry], nodes: &mut [Node], perspective: &na::Matrix4<f32>, t: f32, textures: &SceneTextures) -> usize {
+        let visible = scene::visible_nodes(nodes, perspective, t);
+        for &i in visible.iter() {
+            self.draw(&meshes[nodes[i].mesh], &nodes[i].model, perspective, textures);
+        }
+
+        visible.len()
+    }
+
+    /// Draws the triangles of `geometry`, `model_view` places them in front of the camera at the origin
+    pub fn draw(&mut self, geometry: &Geometry, model_view: &[f32; 16], perspective: &na::Matrix4<f32>, textures: &SceneTextures) {
+        let model_view = na::Matrix4::from_column_slice(model_view);
+        let linear = model_view.fixed_slice::<na::U3, na::U3>(0, 0).into_owned();
+        let norm_mtx = linear.try_inverse().unwrap_or_else(na::Matrix3::identity).transpose();
+
+        let vertex = |i: usize| {
+            let attribute = |data: &[f32]| na::Vector3::new(data[i * 3], data[i * 3 + 1], data[i * 3 + 2]);
+            let uv = [geometry.uvs[i * 2], geometry.uvs[i * 2 + 1]];
+            shade_vertex(&model_view, &norm_mtx, perspective, attribute(&geometry.vertices), uv, attribute(&geometry.tangents), attribute(&geometry.bitangents))
+        };
+
+        let count = if geometry.indices.is_empty() { geometry.vertex_count as usize } else { geometry.indices.len() };
+        let index = |i: usize| if geometry.indices.is_empty() { i } else { geometry.indices[i] as usize };
+
+        for first in (0..count - count % 3).step_by(3) {
+            let polygon = clip_triangle([vertex(index(first)), vertex(index(first + 1)), vertex(index(first + 2))]);
+            if polygon.len() >= 3 {
+                self.fill_polygon(&polygon, textures);
+            }
+        }
+    }
+
+    /// The frame as an RGB image
+    pub fn image(&self) -> Image {
+        Image::from_vec(self.color.clone(), self.width, self.height)
+    }
+
+    fn to_screen(&self, vertex: &ClipVertex) -> ScreenVertex {
+        let inv_w = 1.0 / vertex.position.w;
+        let mut varyings = vertex.varyings;
+        varyings.iter_mut().for_each(|varying| *varying *= inv_w);
+
+        ScreenVertex {
+            x: (vertex.position.x * inv_w * 0.5 + 0.5) * self.width as f32,
+            // Clip space y points up, rows go down
+            y: (0.5 - vertex.position.y * inv_w * 0.5) * self.height as f32,
+            z: vertex.position.z * inv_w * 0.5 + 0.5,
+            inv_w,
+            varyings,
+        }
+    }
+
+    /// Fills a convex clipped polygon as a triangle fan unless it faces away
+    fn fill_polygon(&mut self, polygon: &[ClipVertex], textures: &SceneTextures) {
+        let screen: Vec<ScreenVertex> = polygon.iter().map(|vertex| self.to_screen(vertex)).collect();
+
+        // Rows go down, so counter-clockwise front faces have a negative area
+        let area: f32 = (0..screen.len())
+            .map(|i| {
+                let (a, b) = (&screen[i], &screen[(i + 1) % screen.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum();
+        if area >= 0.0 {
+            return;
+        }
+
+        for i in 1..screen.len() - 1 {
+            self.fill_triangle([&screen[0], &screen[i], &screen[i + 1]], textures);
+        }
+    }
+
+    /// Pixels on an edge shared by two triangles are shaded by both, the depth test keeps the later one
+    fn fill_triangle(&mut self, [a, b, c]: [&ScreenVertex; 3], textures: &SceneTextures) {
+        let area = edge(a, b, c.x, c.y);
+        if area == 0.0 {
+            return;
+        }
+
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+        let max_x = (a.x.max(b.x).max(c.x).ceil() as u32).min(self.width);
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+        let max_y = (a.y.max(b.y).max(c.y).ceil() as u32).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                // Sampled at pixel centers
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [edge(b, c, px, py) / area, edge(c, a, px, py) / area, edge(a, b, px, py) / area];
+                if weights.iter().any(|weight| *weight < 0.0) {
+                    continue;
+                }
+
+                let pixel = (y * self.width + x) as usize;
+                let z = weights[0] * a.z + weights[1] * b.z + weights[2] * c.z;
+                if z > self.depth[pixel] {
+                    continue;
+                }
+
+                let inv_w = weights[0] * a.inv_w + weights[1] * b.inv_w + weights[2] * c.inv_w;
+                let mut varyings = [0.0; VARYINGS];
+                for (i, varying) in varyings.iter_mut().enumerate() {
+                    *varying = (weights[0] * a.varyings[i] + weights[1] * b.varyings[i] + weights[2] * c.varyings[i]) / inv_w;
+                }
+
+                if let Some(color) = shade_fragment(&varyings, textures) {
+                    self.depth[pixel] = z;
+                    for (channel, value) in color.iter().enumerate() {
+                        self.color[pixel * 3 + channel] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Twice the signed area of `a`, `b` and the point
+fn edge(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// `obj.vert.glsl`, lighting inputs move into tangent space
+fn shade_vertex(
+    model_view: &na::Matrix4<f32>,
+    norm_mtx: &na::Matrix3<f32>,
+    perspective: &na::Matrix4<f32>,
+    pos: na::Vector3<f32>,
+    uv: [f32; 2],
+    tang: na::Vector3<f32>,
+    bitang: na::Vector3<f32>,
+) -> ClipVertex {
+    let view_pos = model_view * pos.push(1.0);
+    let norm = bitang.cross(&tang);
+
+    let t = (norm_mtx * tang).normalize();
+    let b = (norm_mtx * bitang).normalize();
+    let n = (norm_mtx * norm).normalize();
+    let tbn = na::Matrix3::from_columns(&[t, b, n]).transpose();
+
+    let ts_light_pos = tbn * na::Vector3::new(LIGHT_POS[0], LIGHT_POS[1], LIGHT_POS[2]);
+    let ts_frag_pos = tbn * view_pos.xyz();
+
+    let mut varyings = [0.0; VARYINGS];
+    varyings[..2].copy_from_slice(&uv);
+    varyings[2..5].copy_from_slice(ts_light_pos.as_slice());
+    varyings[5..].copy_from_slice(ts_frag_pos.as_slice());
+
+    ClipVertex { position: perspective * view_pos, varyings }
+}
+
+/// `obj.frag.glsl`, `None` where the fragment is discarded
+fn shade_fragment(varyings: &[f32; VARYINGS], textures: &SceneTextures) -> Option<[f32; 3]> {
+    let uv = [varyings[0], varyings[1]];
+    let ts_light_pos = na::Vector3::new(varyings[2], varyings[3], varyings[4]);
+    let ts_frag_pos = na::Vector3::new(varyings[5], varyings[6], varyings[7]);
+    let light_dir = (ts_light_pos - ts_frag_pos).normalize();
+
+    let diffuse_color = sample(textures.diffuse, uv);
+    if diffuse_color[3] < textures.alpha_cutoff {
+        return None;
+    }
+
+    let albedo = na::Vector3::new(diffuse_color[0], diffuse_color[1], diffuse_color[2]);
+    let ambient = albedo * 0.7;
+
+    // Normal mapping
+    let texel = sample(textures.norm, uv);
+    let norm = (na::Vector3::new(texel[0], texel[1], texel[2]) * 2.0 - na::Vector3::repeat(1.0)).normalize();
+    let diffuse = light_dir.dot(&norm).max(0.0);
+
+    let color = albedo * diffuse + ambient;
+    Some([color.x, color.y, color.z])
+}
+
+/// Bilinear RGBA lookup with `CLAMP_TO_EDGE`, how the scene textures are sampled
+fn sample(image: &Image, uv: [f32; 2]) -> [f32; 4] {
+    let x = uv[0] * image.width as f32 - 0.5;
+    let y = uv[1] * image.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).clamp(0, image.width as i64 - 1) as usize;
+        let y = (y as i64).clamp(0, image.height as i64 - 1) as usize;
+        let channels = image.channels as usize;
+        let start = (y * image.width as usize + x) * channels;
+
+        let mut rgba = [1.0; 4];
+        for (value, byte) in rgba.iter_mut().zip(image.data[start..start + channels].iter()) {
+            *value = *byte as f32 / 255.0;
+        }
+        rgba
+    };
+
+    let (top_left, top_right) = (texel(x0, y0), texel(x0 + 1.0, y0));
+    let (bottom_left, bottom_right) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+    let mut rgba = [0.0; 4];
+    for (i, value) in rgba.iter_mut().enumerate() {
+        let top = top_left[i] + (top_right[i] - top_left[i]) * fx;
+        let bottom = bottom_left[i] + (bottom_right[i] - bottom_left[i]) * fx;
+        *value = top + (bottom - top) * fy;
+    }
+    rgba
+}
+
+/// Sutherland-Hodgman clipping against the view volume, returns a convex polygon
+fn clip_triangle(triangle: [ClipVertex; 3]) -> Vec<ClipVertex> {
+    let mut polygon = triangle.to_vec();
+
+    for plane in CLIP_PLANES.iter() {
+        let plane = na::Vector4::from_row_slice(plane);
+        let input = std::mem::take(&mut polygon);
+
+        for (i, current) in input.iter().enumerate() {
+            let next = &input[(i + 1) % input.len()];
+            let (current_distance, next_distance) = (plane.dot(&current.position), plane.dot(&next.position));
+
+            if current_distance >= 0.0 {
+                polygon.push(*current);
+            }
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                polygon.push(current.lerp(next, current_distance / (current_distance - next_distance)));
+            }
+        }
+
+        if polygon.is_empty() {
+            break;
+        }
+    }
+
+    polygon
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use crate::model::common::obj_file_data_to_vertex_vector_data;
+
+    const CUBE_OBJ: &str = include_str!("../../../main/assets/resources/obj/cube.obj");
+    const CUBE_DIFFUSE: &[u8] = include_bytes!("../../../main/assets/resources/images/cube-diffuse.png");
+    const CUBE_NORMAL: &[u8] = include_bytes!("../../../main/assets/resources/images/cube-normal.png");
+
+    fn perspective(width: u32, height: u32) -> na::Matrix4<f32> {
+        na::Perspective3::new(width as f32 / height as f32, 45.0f32.to_radians(), 0.1, 100.0).to_homogeneous()
+    }
+
+    fn identity() -> [f32; 16] {
+        let mut identity = [0.0; 16];
+        identity.copy_from_slice(na::Matrix4::<f32>::identity().as_slice());
+        identity
+    }
+
+    fn white() -> Image {
+        Image::from_vec(vec![255; 3], 1, 1)
+    }
+
+    fn flat_normal() -> Image {
+        Image::from_vec(vec![128, 128, 255], 1, 1)
+    }
+
+    /// Textures without alpha testing
+    fn textures<'a>(diffuse: &'a Image, norm: &'a Image) -> SceneTextures<'a> {
+        SceneTextures { diffuse, norm, alpha_cutoff: 0.0 }
+    }
+
+    /// Triangle list with one tangent frame for every vertex
+    fn geometry(vertices: Vec<f32>, uvs: Vec<f32>) -> Geometry {
+        let count = vertices.len() / 3;
+        let tangents = [1.0, 0.0, 0.0].repeat(count);
+        let bitangents = [0.0, 1.0, 0.0].repeat(count);
+
+        Geometry::new(vertices, uvs, tangents, bitangents, count as i32 / 3, count as i32)
+    }
+
+    fn pixel(image: &Image, x: u32, y: u32) -> [u8; 3] {
+        let start = ((y * image.width + x) * 3) as usize;
+        [image.data[start], image.data[start + 1], image.data[start + 2]]
+    }
+
+    #[test]
+    fn back_faces_are_culled() {
+        let (white, norm) = (white(), flat_normal());
+        let textures = textures(&white, &norm);
+
+        let front = geometry(vec![-1.0, -1.0, -3.0, 1.0, -1.0, -3.0, 0.0, 1.0, -3.0], vec![0.0; 6]);
+        let back = geometry(vec![-1.0, -1.0, -3.0, 0.0, 1.0, -3.0, 1.0, -1.0, -3.0], vec![0.0; 6]);
+
+        let mut rasterizer = Rasterizer::new(32, 32);
+        rasterizer.draw(&back, &identity(), &perspective(32, 32), &textures);
+        assert!(rasterizer.image().data.iter().all(|value| *value == 0));
+
+        rasterizer.draw(&front, &identity(), &perspective(32, 32), &textures);
+        // White is at least the ambient term
+        assert!(pixel(&rasterizer.image(), 16, 16)[0] >= 178);
+        assert_eq!(pixel(&rasterizer.image(), 0, 0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn triangles_through_the_near_plane_are_clipped() {
+        let (white, norm) = (white(), flat_normal());
+        let textures = textures(&white, &norm);
+
+        // Floor reaching from behind the camera into the distance
+        let floor = geometry(vec![-1.0, -1.0, 2.0, 1.0, -1.0, 2.0, 0.0, -1.0, -20.0], vec![0.0; 6]);
+        let mut rasterizer = Rasterizer::new(32, 32);
+        rasterizer.draw(&floor, &identity(), &perspective(32, 32), &textures);
+
+        let image = rasterizer.image();
+        assert!(pixel(&image, 16, 31)[0] > 0);
+        assert_eq!(pixel(&image, 16, 0), [0, 0, 0]);
+        assert!(rasterizer.depth.iter().all(|depth| (0.0..=1.0).contains(depth)));
+    }
+
+    #[test]
+    fn varyings_are_perspective_correct() {
+        // Black left half, white right half, u crosses 0.5 at the quad's middle
+        let diffuse = Image::from_vec(vec![0, 0, 0, 255, 255, 255], 2, 1);
+        let norm = flat_normal();
+        let textures = textures(&diffuse, &norm);
+
+        // Wall receding to the right, from x = -0.4 at z = -1 to x = 1.6 at z = -5
+        let (near_bottom, far_bottom, far_top, near_top) = ([-0.4, -1.0, -1.0], [1.6, -1.0, -5.0], [1.6, 1.0, -5.0], [-0.4, 1.0, -1.0]);
+        let vertices = [near_bottom, far_bottom, far_top, near_bottom, far_top, near_top].concat();
+        let uvs = vec![0.0, 0.5, 1.0, 0.5, 1.0, 0.5, 0.0, 0.5, 1.0, 0.5, 0.0, 0.5];
+        let mut rasterizer = Rasterizer::new(64, 64);
+        rasterizer.draw(&geometry(vertices, uvs), &identity(), &perspective(64, 64), &textures);
+        let image = rasterizer.image();
+
+        // The middle (0.6, 0, -3) lands on column 47, halfway between the projected ends is column 29
+        assert_eq!(pixel(&image, 29, 32), [0, 0, 0]);
+        assert!(pixel(&image, 44, 32)[0] < pixel(&image, 47, 32)[0]);
+        assert!(pixel(&image, 47, 32)[0] < pixel(&image, 50, 32)[0]);
+    }
+
+    /// Compares with `tests/golden/<name>.png`, `UPDATE_GOLDEN=1` writes the frame there instead
+    fn assert_golden(name: &str, frame: &Image) {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("tests/golden").join(format!("{}.png", name));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, frame.encode_png().unwrap()).unwrap();
+            return;
+        }
+
+        let data = fs::read(&path).unwrap_or_else(|_| panic!("Missing {}, run the tests with UPDATE_GOLDEN=1 to create it", path.display()));
+        let golden = Image::decode(&data).unwrap();
+        assert_eq!((golden.width, golden.height, golden.channels), (frame.width, frame.height, frame.channels));
+
+        // Off by a step or two is float rounding, not a changed frame
+        let mismatched = golden.data
+            .chunks_exact(3)
+            .zip(frame.data.chunks_exact(3))
+            .filter(|(expected, actual)| expected.iter().zip(actual.iter()).any(|(e, a)| (*e as i32 - *a as i32).abs() > 2))
+            .count();
+        if mismatched > (frame.width * frame.height / 1000) as usize {
+            let actual = manifest_dir.join("target/golden").join(format!("{}.png", name));
+            fs::create_dir_all(actual.parent().unwrap()).unwrap();
+            fs::write(&actual, frame.encode_png().unwrap()).unwrap();
+            panic!("{} pixels differ from {}, the frame was written to {}", mismatched, path.display(), actual.display());
+        }
+    }
+
+    #[test]
+    fn cube_matches_golden_images() {
+        let obj_data = obj_file_data_to_vertex_vector_data(CUBE_OBJ);
+        let cube = Geometry::new(obj_data.0, obj_data.1, obj_data.2, obj_data.3, obj_data.4, obj_data.5);
+        let diffuse = Image::decode(CUBE_DIFFUSE).unwrap();
+        let norm = Image::decode(CUBE_NORMAL).unwrap();
+        let textures = textures(&diffuse, &norm);
+
+        let mut node = Node::with_bounds(0, cube.bounds);
+        node.set_x_y_z(0.0, 0.0, -6.0);
+        let mut nodes = vec![node];
+        let meshes = vec![cube];
+
+        let mut rasterizer = Rasterizer::new(128, 128);
+        for (name, t) in [("cube_t0", 0.0), ("cube_t08", 0.8)].iter() {
+            rasterizer.clear();
+            let drawn = rasterizer.draw_nodes(&meshes, &mut nodes, &perspective(128, 128), *t, &textures);
+            assert_eq!(drawn, 1);
+
+            assert_golden(name, &rasterizer.image());
+        }
+    }
+}
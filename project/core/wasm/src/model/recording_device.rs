@@ -0,0 +1,332 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::model::gl as GL;
+use crate::model::{ProgramReflection, UniformValue};
+use crate::model::device::{DeviceFeatures, DrawCall, GraphicsDevice};
+use crate::model::reflection::{gl_type_name, AttributeInfo, UniformInfo};
+
+/// Call made on a `RecordingDevice`, handles are the ids it handed out
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceCall {
+    CreateBuffer(u32),
+    BindBuffer(u32, Option<u32>),
+    /// Target and byte length
+    BufferData(u32, usize),
+    DeleteBuffer(u32),
+    CreateTexture(u32),
+    ActiveTexture(u32),
+    BindTexture(u32, Option<u32>),
+    TexParameter(u32, u32, i32),
+    PixelStore(u32, i32),
+    /// Target, level and size
+    TexImage(u32, i32, (u32, u32)),
+    /// Target, level and region
+    TexSubImage(u32, i32, (u32, u32, u32, u32)),
+    DeleteTexture(u32),
+    LinkProgram(u32),
+    UseProgram(Option<u32>),
+    SetUniform(String, UniformValue),
+    BindUniformBlock(u32, String, u32),
+    DeleteProgram(u32),
+    SetCapability(u32, bool),
+    DepthMask(bool),
+    CullFace(u32),
+    /// Location, size, stride and offset
+    VertexAttribPointer(u32, i32, i32, i32),
+    SetAttribArray(u32, bool),
+    VertexAttribDivisor(u32, u32),
+    CreateVertexArray(u32),
+    BindVertexArray(Option<u32>),
+    DeleteVertexArray(u32),
+    Draw(DrawCall),
+}
+
+/// Linked program, reflected from the declarations in its sources
+#[derive(Debug)]
+pub struct RecordedProgram {
+    pub id: u32,
+    vert_src: String,
+    frag_src: String,
+}
+
+/// Device that draws nothing and records every call, clones share the log
+#[derive(Clone)]
+pub struct RecordingDevice {
+    calls: Rc<RefCell<Vec<DeviceCall>>>,
+    next_handle: Rc<Cell<u32>>,
+    features: DeviceFeatures,
+}
+
+impl RecordingDevice {
+    pub fn new(features: DeviceFeatures) -> Self {
+        Self { calls: Rc::new(RefCell::new(vec![])), next_handle: Rc::new(Cell::new(1)), features }
+    }
+
+    pub fn calls(&self) -> Vec<DeviceCall> {
+        self.calls.borrow().clone()
+    }
+
+    pub fn draws(&self) -> Vec<DrawCall> {
+        self.calls.borrow().iter().filter_map(|call| match call {
+            DeviceCall::Draw(draw) => Some(*draw),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn clear(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    fn record(&self, call: DeviceCall) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn handle(&self) -> u32 {
+        let handle = self.next_handle.get();
+        self.next_handle.set(handle + 1);
+
+        handle
+    }
+}
+
+/// GL enum of a GLSL type name
+fn gl_type(type_name: &str) -> Option<u32> {
+    let types = [
+        GL::FLOAT, GL::FLOAT_VEC2, GL::FLOAT_VEC3, GL::FLOAT_VEC4,
+        GL::INT, GL::INT_VEC2, GL::INT_VEC3, GL::INT_VEC4,
+        GL::BOOL, GL::BOOL_VEC2, GL::BOOL_VEC3, GL::BOOL_VEC4,
+        GL::FLOAT_MAT2, GL::FLOAT_MAT3, GL::FLOAT_MAT4,
+        GL::SAMPLER_2D, GL::SAMPLER_CUBE,
+    ];
+
+    types.iter().copied().find(|gl_type| gl_type_name(*gl_type) == type_name)
+}
+
+/// Type, name and array size of `<qualifier> [precision] <type> <name>[N];` lines
+fn declarations<'a>(source: &'a str, qualifiers: &[&str]) -> Vec<(&'a str, &'a str, i32)> {
+    source.lines().filter_map(|line| {
+        let line = line.trim().strip_suffix(';')?;
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() < 3 || !qualifiers.contains(&words[0]) {
+            return None;
+        }
+        words.retain(|word| !["highp", "mediump", "lowp"].contains(word));
+
+        let (name, size) = match words[2].find('[') {
+            Some(start) => (&words[2][..start], words[2][start + 1..words[2].len() - 1].parse().ok()?),
+            None => (words[2], 1),
+        };
+
+        Some((words[1], name, size))
+    }).collect()
+}
+
+impl GraphicsDevice for RecordingDevice {
+    type Buffer = u32;
+    type Texture = u32;
+    /// Type and source
+    type Shader = (u32, String);
+    type Program = RecordedProgram;
+    /// Uniform name
+    type UniformLocation = String;
+    type VertexArray = u32;
+
+    fn features(&self) -> DeviceFeatures {
+        self.features
+    }
+
+    fn create_buffer(&self) -> Option<u32> {
+        let buffer = self.handle();
+        self.record(DeviceCall::CreateBuffer(buffer));
+
+        Some(buffer)
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<&u32>) {
+        self.record(DeviceCall::BindBuffer(target, buffer.copied()));
+    }
+
+    fn buffer_data(&self, target: u32, data: &[u8], _usage: u32) {
+        self.record(DeviceCall::BufferData(target, data.len()));
+    }
+
+    fn delete_buffer(&self, buffer: &u32) {
+        self.record(DeviceCall::DeleteBuffer(*buffer));
+    }
+
+    fn create_texture(&self) -> Option<u32> {
+        let texture = self.handle();
+        self.record(DeviceCall::CreateTexture(texture));
+
+        Some(texture)
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.record(DeviceCall::ActiveTexture(unit));
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<&u32>) {
+        self.record(DeviceCall::BindTexture(target, texture.copied()));
+    }
+
+    fn tex_parameter(&self, target: u32, name: u32, value: i32) {
+        self.record(DeviceCall::TexParameter(target, name, value));
+    }
+
+    fn pixel_store(&self, name: u32, value: i32) {
+        self.record(DeviceCall::PixelStore(name, value));
+    }
+
+    fn tex_image_2d(&self, target: u32, level: i32, _format: u32, size: (u32, u32), _data_type: u32, _data: Option<&[u8]>) -> Result<(), String> {
+        self.record(DeviceCall::TexImage(target, level, size));
+
+        Ok(())
+    }
+
+    fn tex_sub_image_2d(&self, target: u32, level: i32, region: (u32, u32, u32, u32), _format: u32, _data_type: u32, _data: &[u8]) -> Result<(), String> {
+        self.record(DeviceCall::TexSubImage(target, level, region));
+
+        Ok(())
+    }
+
+    fn delete_texture(&self, texture: &u32) {
+        self.record(DeviceCall::DeleteTexture(*texture));
+    }
+
+    fn compile_shader(&self, shader_type: u32, source: &str) -> Result<(u32, String), String> {
+        Ok((shader_type, source.to_string()))
+    }
+
+    fn delete_shader(&self, _shader: &(u32, String)) {}
+
+    fn link_program(&self, vert: (u32, String), frag: (u32, String)) -> Result<RecordedProgram, String> {
+        let id = self.handle();
+        self.record(DeviceCall::LinkProgram(id));
+
+        Ok(RecordedProgram { id, vert_src: vert.1, frag_src: frag.1 })
+    }
+
+    /// Every declaration counts as active, attributes get consecutive locations
+    fn reflect_program(&self, program: &RecordedProgram) -> (ProgramReflection, HashMap<String, String>) {
+        let mut reflection = ProgramReflection::default();
+        let mut locations = HashMap::new();
+
+        for source in [&program.vert_src, &program.frag_src].iter() {
+            for (type_name, name, size) in declarations(source, &["uniform"]) {
+                let gl_type = gl_type(type_name).unwrap_or_else(|| panic!("Unknown uniform type {}", type_name));
+                locations.insert(name.to_string(), name.to_string());
+                reflection.uniforms.insert(name.to_string(), UniformInfo {
+                    name: name.to_string(),
+                    gl_type,
+                    type_name: gl_type_name(gl_type),
+                    size,
+                });
+            }
+        }
+
+        let mut location = 0;
+        for (type_name, name, size) in declarations(&program.vert_src, &["attribute", "in"]) {
+            let gl_type = gl_type(type_name).unwrap_or_else(|| panic!("Unknown attribute type {}", type_name));
+            reflection.attributes.insert(name.to_string(), AttributeInfo {
+                name: name.to_string(),
+                gl_type,
+                type_name: gl_type_name(gl_type),
+                size,
+                location,
+            });
+            // Matrices take a location per column
+            location += match gl_type {
+                GL::FLOAT_MAT2 => 2,
+                GL::FLOAT_MAT3 => 3,
+                GL::FLOAT_MAT4 => 4,
+                _ => 1,
+            } * size;
+        }
+
+        (reflection, locations)
+    }
+
+    fn use_program(&self, program: Option<&RecordedProgram>) {
+        self.record(DeviceCall::UseProgram(program.map(|program| program.id)));
+    }
+
+    fn set_uniform(&self, location: &String, value: &UniformValue) {
+        self.record(DeviceCall::SetUniform(location.clone(), value.clone()));
+    }
+
+    fn bind_uniform_block(&self, program: &RecordedProgram, name: &str, binding: u32) -> bool {
+        self.record(DeviceCall::BindUniformBlock(program.id, name.to_string(), binding));
+
+        self.features.uniform_buffers
+    }
+
+    fn delete_program(&self, program: &RecordedProgram) {
+        self.record(DeviceCall::DeleteProgram(program.id));
+    }
+
+    fn set_capability(&self, capability: u32, enabled: bool) {
+        self.record(DeviceCall::SetCapability(capability, enabled));
+    }
+
+    fn depth_mask(&self, enabled: bool) {
+        self.record(DeviceCall::DepthMask(enabled));
+    }
+
+    fn cull_face(&self, mode: u32) {
+        self.record(DeviceCall::CullFace(mode));
+    }
+
+    fn vertex_attrib_pointer(&self, loc: u32, size: i32, stride: i32, offset: i32) {
+        self.record(DeviceCall::VertexAttribPointer(loc, size, stride, offset));
+    }
+
+    fn set_attrib_array(&self, loc: u32, enabled: bool) {
+        self.record(DeviceCall::SetAttribArray(loc, enabled));
+    }
+
+    fn vertex_attrib_divisor(&self, loc: u32, divisor: u32) {
+        self.record(DeviceCall::VertexAttribDivisor(loc, divisor));
+    }
+
+    fn create_vertex_array(&self) -> Option<u32> {
+        if !self.features.vertex_arrays {
+            return None;
+        }
+        let vertex_array = self.handle();
+        self.record(DeviceCall::CreateVertexArray(vertex_array));
+
+        Some(vertex_array)
+    }
+
+    fn bind_vertex_array(&self, vertex_array: Option<&u32>) {
+        self.record(DeviceCall::BindVertexArray(vertex_array.copied()));
+    }
+
+    fn delete_vertex_array(&self, vertex_array: &u32) {
+        self.record(DeviceCall::DeleteVertexArray(*vertex_array));
+    }
+
+    fn draw(&self, call: &DrawCall) {
+        self.record(DeviceCall::Draw(*call));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declarations_are_reflected() {
+        let device = RecordingDevice::new(DeviceFeatures { instancing: false, vertex_arrays: false, uint_indices: false, uniform_buffers: false });
+        let vert = "attribute vec3 vert_pos;\nattribute mat4 instance_model;\nattribute vec2 vert_uv;\nuniform highp vec4 lights[4];\n";
+        let program = device.link_program((GL::VERTEX_SHADER, vert.to_string()), (GL::FRAGMENT_SHADER, "uniform sampler2D tex;".to_string())).unwrap();
+
+        let (reflection, locations) = device.reflect_program(&program);
+        assert_eq!(reflection.attributes["vert_uv"].location, 5);
+        assert_eq!(reflection.attributes["instance_model"].type_name, "mat4");
+        assert_eq!((reflection.uniforms["lights"].gl_type, reflection.uniforms["lights"].size), (GL::FLOAT_VEC4, 4));
+        assert_eq!(reflection.uniforms["tex"].gl_type, GL::SAMPLER_2D);
+        assert_eq!(locations["tex"], "tex");
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+#[cfg(feature = "web")]
+use web_sys::{WebGlRenderingContext as WebGl, WebGlProgram, WebGlUniformLocation};
+use crate::model::gl as GL;
+
+/// Active uniform of a linked program
+#[derive(Clone, Debug, Serialize)]
+pub struct UniformInfo {
+    /// Name without the `[0]` suffix of arrays
+    pub name: String,
+    pub gl_type: u32,
+    pub type_name: &'static str,
+    /// Number of array elements, 1 for plain uniforms
+    pub size: i32,
+}
+
+/// Active vertex attribute of a linked program
+#[derive(Clone, Debug, Serialize)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub gl_type: u32,
+    pub type_name: &'static str,
+    pub size: i32,
+    pub location: i32,
+}
+
+/// Uniforms and attributes reported by GL after linking
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ProgramReflection {
+    pub uniforms: HashMap<String, UniformInfo>,
+    pub attributes: HashMap<String, AttributeInfo>,
+}
+
+impl ProgramReflection {
+    /// Enumerates `ACTIVE_UNIFORMS` and `ACTIVE_ATTRIBUTES` of a linked program, also returns the uniform locations
+    #[cfg(feature = "web")]
+    pub fn from_gl(gl: &WebGl, program: &WebGlProgram) -> (Self, HashMap<String, WebGlUniformLocation>) {
+        let mut reflection = Self::default();
+        let mut locations = HashMap::new();
+
+        let uniform_count = gl
+            .get_program_parameter(program, GL::ACTIVE_UNIFORMS)
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+
+        for i in 0..uniform_count {
+            if let Some(info) = gl.get_active_uniform(program, i) {
+                let name = strip_array_suffix(&info.name()).to_string();
+                // Members of WebGL2 uniform blocks have no location, buffers feed them
+                let location = match gl.get_uniform_location(program, &info.name()) {
+                    Some(location) => location,
+                    None => continue,
+                };
+                locations.insert(name.clone(), location);
+
+                reflection.uniforms.insert(name.clone(), UniformInfo {
+                    name,
+                    gl_type: info.type_(),
+                    type_name: gl_type_name(info.type_()),
+                    size: info.size(),
+                });
+            }
+        }
+
+        let attribute_count = gl
+            .get_program_parameter(program, GL::ACTIVE_ATTRIBUTES)
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+
+        for i in 0..attribute_count {
+            if let Some(info) = gl.get_active_attrib(program, i) {
+                let name = info.name();
+                let location = gl.get_attrib_location(program, &name);
+
+                reflection.attributes.insert(name.clone(), AttributeInfo {
+                    name,
+                    gl_type: info.type_(),
+                    type_name: gl_type_name(info.type_()),
+                    size: info.size(),
+                    location,
+                });
+            }
+        }
+
+        (reflection, locations)
+    }
+
+    /// Checks that every texture key names a sampler uniform
+    ///
+    /// A key naming a non-sampler uniform is an error, textures without a sampler and
+    /// samplers without a texture are returned as warnings.
+    pub fn validate_samplers(&self, texture_keys: &[&str]) -> Result<Vec<String>, String> {
+        let mut warnings = vec![];
+
+        for key in texture_keys.iter() {
+            match self.uniforms.get(*key) {
+                Some(uniform) if !is_sampler(uniform.gl_type) => {
+                    return Err(format!(
+                        "Texture '{}' is bound to uniform '{}' of type {}, expected a sampler",
+                        key, uniform.name, uniform.type_name
+                    ));
+                }
+                Some(_) => {}
+                None => warnings.push(format!(
+                    "Texture '{}' has no matching sampler uniform in the shader and will not be used",
+                    key
+                )),
+            }
+        }
+
+        let mut samplers: Vec<&UniformInfo> = self.uniforms
+            .values()
+            .filter(|uniform| is_sampler(uniform.gl_type) && !texture_keys.contains(&uniform.name.as_str()))
+            .collect();
+        samplers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for uniform in samplers {
+            warnings.push(format!("Sampler uniform '{}' has no texture bound", uniform.name));
+        }
+
+        Ok(warnings)
+    }
+}
+
+pub fn is_sampler(gl_type: u32) -> bool {
+    gl_type == GL::SAMPLER_2D || gl_type == GL::SAMPLER_CUBE
+}
+
+/// GLSL name of a GL type enum
+pub fn gl_type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        GL::FLOAT => "float",
+        GL::FLOAT_VEC2 => "vec2",
+        GL::FLOAT_VEC3 => "vec3",
+        GL::FLOAT_VEC4 => "vec4",
+        GL::INT => "int",
+        GL::INT_VEC2 => "ivec2",
+        GL::INT_VEC3 => "ivec3",
+        GL::INT_VEC4 => "ivec4",
+        GL::BOOL => "bool",
+        GL::BOOL_VEC2 => "bvec2",
+        GL::BOOL_VEC3 => "bvec3",
+        GL::BOOL_VEC4 => "bvec4",
+        GL::FLOAT_MAT2 => "mat2",
+        GL::FLOAT_MAT3 => "mat3",
+        GL::FLOAT_MAT4 => "mat4",
+        GL::SAMPLER_2D => "sampler2D",
+        GL::SAMPLER_CUBE => "samplerCube",
+        _ => "unknown",
+    }
+}
+
+#[cfg(any(feature = "web", test))]
+fn strip_array_suffix(name: &str) -> &str {
+    name.strip_suffix("[0]").unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform(name: &str, gl_type: u32) -> (String, UniformInfo) {
+        (name.to_string(), UniformInfo {
+            name: name.to_string(),
+            gl_type,
+            type_name: gl_type_name(gl_type),
+            size: 1,
+        })
+    }
+
+    fn reflection() -> ProgramReflection {
+        ProgramReflection {
+            uniforms: vec![
+                uniform("tex_diffuse", GL::SAMPLER_2D),
+                uniform("tex_norm", GL::SAMPLER_2D),
+                uniform("perspective", GL::FLOAT_MAT4),
+            ].into_iter().collect(),
+            attributes: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn array_suffix_is_stripped() {
+        assert_eq!(strip_array_suffix("lights[0]"), "lights");
+        assert_eq!(strip_array_suffix("perspective"), "perspective");
+    }
+
+    #[test]
+    fn matching_textures_produce_no_warnings() {
+        assert_eq!(reflection().validate_samplers(&["tex_diffuse", "tex_norm"]), Ok(vec![]));
+    }
+
+    #[test]
+    fn unknown_texture_and_unbound_sampler_are_warnings() {
+        let warnings = reflection().validate_samplers(&["tex_diffuse", "tex_normal"]).unwrap();
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("'tex_normal'"));
+        assert!(warnings[1].contains("'tex_norm'"));
+    }
+
+    #[test]
+    fn texture_bound_to_non_sampler_is_an_error() {
+        let err = reflection().validate_samplers(&["perspective"]).unwrap_err();
+        assert!(err.contains("mat4"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::ops::Range;
+use crate::model::AlphaMode;
+
+/// Passes in submission order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderPass {
+    Opaque = 0,
+    AlphaTest = 1,
+    Blend = 2,
+}
+
+impl From<AlphaMode> for RenderPass {
+    fn from(alpha_mode: AlphaMode) -> Self {
+        match alpha_mode {
+            AlphaMode::Opaque => RenderPass::Opaque,
+            AlphaMode::Mask(_) => RenderPass::AlphaTest,
+            AlphaMode::Blend => RenderPass::Blend,
+        }
+    }
+}
+
+const PASS_SHIFT: u64 = 62;
+const PROGRAM_BITS: u64 = 12;
+const MATERIAL_BITS: u64 = 16;
+const PROGRAM_MASK: u64 = (1 << PROGRAM_BITS) - 1;
+const MATERIAL_MASK: u64 = (1 << MATERIAL_BITS) - 1;
+
+/// Maps a float to an integer with the same ordering
+fn sortable_depth(depth: f32) -> u32 {
+    let bits = depth.to_bits();
+    if bits & 0x8000_0000 != 0 {
+        !bits
+    } else {
+        bits | 0x8000_0000
+    }
+}
+
+/// Packs a 64-bit sort key
+///
+/// Opaque and alpha-tested items: `pass:2 | program:12 | material:16 | unused:2 | depth:32`,
+/// grouped by state and front-to-back inside a group.
+/// Blended items: `pass:2 | inverted depth:32 | program:12 | material:16 | unused:2`,
+/// strictly back-to-front since ordering matters more than state changes.
+pub fn sort_key(pass: RenderPass, program: u32, material: u32, depth: f32) -> u64 {
+    let pass_bits = (pass as u64) << PASS_SHIFT;
+    let program = program as u64 & PROGRAM_MASK;
+    let material = material as u64 & MATERIAL_MASK;
+    let depth = sortable_depth(depth) as u64;
+
+    match pass {
+        RenderPass::Blend => {
+            pass_bits | ((!depth & 0xFFFF_FFFF) << 30) | (program << 18) | (material << 2)
+        }
+        _ => pass_bits | (program << 50) | (material << 34) | depth,
+    }
+}
+
+/// Single draw submission
+#[derive(Clone, Debug)]
+pub struct DrawItem {
+    pub key: u64,
+    /// Index of the node providing the mesh
+    pub node: usize,
+    pub model: [f32; 16],
+}
+
+impl DrawItem {
+    pub fn new(pass: RenderPass, program: u32, material: u32, depth: f32, node: usize, model: [f32; 16]) -> Self {
+        Self {
+            key: sort_key(pass, program, material, depth),
+            node,
+            model,
+        }
+    }
+}
+
+/// Draw items collected for a frame and sorted before submission
+#[derive(Default)]
+pub struct RenderQueue {
+    pub items: Vec<DrawItem>,
+}
+
+impl RenderQueue {
+    pub fn new() -> Self {
+        Self { items: vec![] }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn push(&mut self, item: DrawItem) {
+        self.items.push(item);
+    }
+
+    pub fn sort(&mut self) {
+        self.items.sort_unstable_by_key(|item| item.key);
+    }
+
+    /// Splits the sorted items into runs of neighbours that `same_batch` can draw together
+    ///
+    /// Only neighbours are merged, so the submission order is kept.
+    pub fn batches(&self, same_batch: impl Fn(&DrawItem, &DrawItem) -> bool) -> Vec<Range<usize>> {
+        let mut batches: Vec<Range<usize>> = vec![];
+
+        for (i, item) in self.items.iter().enumerate() {
+            match batches.last_mut() {
+                Some(batch) if same_batch(&self.items[batch.start], item) => batch.end = i + 1,
+                _ => batches.push(i..i + 1),
+            }
+        }
+
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(items: Vec<DrawItem>) -> Vec<usize> {
+        let mut queue = RenderQueue::new();
+        for item in items {
+            queue.push(item);
+        }
+        queue.sort();
+        queue.items.iter().map(|item| item.node).collect()
+    }
+
+    #[test]
+    fn sortable_depth_keeps_float_order() {
+        let values = [-10.0, -0.5, 0.0, 0.25, 1.0, 50.0, 1000.0];
+        for pair in values.windows(2) {
+            assert!(sortable_depth(pair[0]) < sortable_depth(pair[1]));
+        }
+    }
+
+    #[test]
+    fn passes_are_submitted_in_order() {
+        let order = sorted(vec![
+            DrawItem::new(RenderPass::Blend, 0, 0, 1.0, 0, [0.0; 16]),
+            DrawItem::new(RenderPass::AlphaTest, 0, 0, 1.0, 1, [0.0; 16]),
+            DrawItem::new(RenderPass::Opaque, 5, 9, 90.0, 2, [0.0; 16]),
+        ]);
+        assert_eq!(order, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn opaque_items_group_by_program_then_material_then_depth() {
+        let order = sorted(vec![
+            DrawItem::new(RenderPass::Opaque, 2, 0, 1.0, 0, [0.0; 16]),
+            DrawItem::new(RenderPass::Opaque, 1, 1, 1.0, 1, [0.0; 16]),
+            DrawItem::new(RenderPass::Opaque, 1, 0, 20.0, 2, [0.0; 16]),
+            DrawItem::new(RenderPass::Opaque, 1, 0, 5.0, 3, [0.0; 16]),
+        ]);
+        assert_eq!(order, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn batches_merge_neighbours_only() {
+        let mut queue = RenderQueue::new();
+        for (material, node) in [(0, 0), (0, 1), (1, 2), (0, 3), (0, 4), (0, 5)].iter() {
+            queue.push(DrawItem::new(RenderPass::Blend, 0, *material, 1.0, *node, [0.0; 16]));
+        }
+
+        let batches = queue.batches(|a, b| a.key == b.key);
+        assert_eq!(batches, vec![0..2, 2..3, 3..6]);
+        assert!(RenderQueue::new().batches(|_, _| true).is_empty());
+    }
+
+    #[test]
+    fn blended_items_sort_back_to_front_across_programs() {
+        let order = sorted(vec![
+            DrawItem::new(RenderPass::Blend, 1, 0, 5.0, 0, [0.0; 16]),
+            DrawItem::new(RenderPass::Blend, 2, 0, 50.0, 1, [0.0; 16]),
+            DrawItem::new(RenderPass::Blend, 1, 3, 20.0, 2, [0.0; 16]),
+        ]);
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,108 @@
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::{WebGlFramebuffer, WebGlRenderbuffer, WebGlTexture};
+
+/// Offscreen framebuffer with a colour texture and a depth renderbuffer
+pub struct RenderTarget {
+    pub gl: GL,
+    pub framebuffer: WebGlFramebuffer,
+    pub color: WebGlTexture,
+    pub depth: WebGlRenderbuffer,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(gl: GL, width: u32, height: u32) -> Self {
+        let framebuffer = gl.create_framebuffer().expect("Failed to create framebuffer");
+        let color = gl.create_texture().expect("Failed to create texture");
+        let depth = gl.create_renderbuffer().expect("Failed to create renderbuffer");
+
+        gl.bind_texture(GL::TEXTURE_2D, Some(&color));
+        // NPOT textures in WebGL1 are only complete without mipmaps and with clamped wrapping
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+        let mut target = Self {
+            gl,
+            framebuffer,
+            color,
+            depth,
+            width: 0,
+            height: 0,
+        };
+
+        target.allocate(width, height);
+
+        target
+    }
+
+    /// Reallocates colour and depth storage when the size differs from the current one
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // Compared with the clamped size `allocate` stores, so empty canvases don't reallocate every frame
+        let width = width.max(1);
+        let height = height.max(1);
+        if self.width != width || self.height != height {
+            self.allocate(width, height);
+        }
+    }
+
+    /// Makes this target the destination of draw calls
+    pub fn bind(&self) {
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        self.gl.viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    /// Restores the default (canvas) framebuffer
+    pub fn bind_default(gl: &GL, width: u32, height: u32) {
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        gl.viewport(0, 0, width as i32, height as i32);
+    }
+
+    /// Binds the colour attachment for sampling
+    pub fn bind_color(&self, texture_num: u32) {
+        self.gl.active_texture(texture_num);
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&self.color));
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) {
+        // Zero sized attachments make the framebuffer incomplete
+        let width = width.max(1);
+        let height = height.max(1);
+
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&self.color));
+        self.gl
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                GL::TEXTURE_2D,
+                0,
+                GL::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                None,
+            )
+            .expect("Failed to allocate render target texture");
+
+        self.gl.bind_renderbuffer(GL::RENDERBUFFER, Some(&self.depth));
+        self.gl.renderbuffer_storage(GL::RENDERBUFFER, GL::DEPTH_COMPONENT16, width as i32, height as i32);
+
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        self.gl.framebuffer_texture_2d(GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0, GL::TEXTURE_2D, Some(&self.color), 0);
+        self.gl.framebuffer_renderbuffer(GL::FRAMEBUFFER, GL::DEPTH_ATTACHMENT, GL::RENDERBUFFER, Some(&self.depth));
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        self.width = width;
+        self.height = height;
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        self.gl.delete_framebuffer(Some(&self.framebuffer));
+        self.gl.delete_texture(Some(&self.color));
+        self.gl.delete_renderbuffer(Some(&self.depth));
+    }
+}
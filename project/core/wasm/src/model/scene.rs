@@ -0,0 +1,232 @@
+use std::rc::Rc;
+use nalgebra as na;
+use crate::model::{DefaultPipeline, DrawItem, Frustum, GlState, Material, Node, Primitive, RenderPass, RenderQueue, Texture};
+use crate::model::device::GraphicsDevice;
+use crate::model::gl as GL;
+
+/// Nodes and everything they are drawn with, on any graphics device
+pub struct Scene<D: GraphicsDevice> {
+    /// Pipelines referenced by materials
+    pub pipelines: Vec<DefaultPipeline<D>>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture<D>>,
+    /// Meshes indexed by `Node::mesh`, entries may share a primitive
+    pub primitives: Vec<Rc<Primitive<D>>>,
+    pub nodes: Vec<Node>,
+    pub queue: RenderQueue,
+    pub state: GlState<D>,
+    /// Nodes skipped by frustum culling in the last frame
+    pub culled: i32,
+    /// Nodes drawn in the last frame
+    pub drawn: i32,
+    pub draw_calls: i32,
+}
+
+impl<D: GraphicsDevice> Scene<D> {
+    pub fn new(state: GlState<D>, pipelines: Vec<DefaultPipeline<D>>, materials: Vec<Material>, textures: Vec<Texture<D>>, primitives: Vec<Rc<Primitive<D>>>) -> Self {
+        Self {
+            pipelines,
+            materials,
+            textures,
+            primitives,
+            nodes: vec![],
+            queue: RenderQueue::new(),
+            state,
+            culled: 0,
+            drawn: 0,
+            draw_calls: 0,
+        }
+    }
+
+    /// Draws the nodes left by `visible_nodes` in render queue order
+    ///
+    /// Frame uniforms have to be set already.
+    pub fn draw_nodes(&mut self, perspective: &na::Matrix4<f32>, t: f32) -> Result<(), String> {
+        let visible = visible_nodes(&mut self.nodes, perspective, t);
+        self.drawn = visible.len() as i32;
+        self.culled = (self.nodes.len() - visible.len()) as i32;
+        self.queue.clear();
+
+        // Collect draw items of visible nodes
+        for i in visible {
+            let node = &self.nodes[i];
+            let material = &self.materials[node.material];
+            self.queue.push(DrawItem::new(
+                RenderPass::from(material.render_state.alpha_mode),
+                self.pipelines[material.pipeline].program.id,
+                node.material as u32,
+                node.view_depth(),
+                i,
+                node.model,
+            ));
+        }
+
+        // Opaque front-to-back grouped by state, blended back-to-front
+        self.queue.sort();
+
+        // Neighbours sharing material and mesh go into one instanced draw where the pipeline supports it
+        let (nodes, primitives) = (&self.nodes, &self.primitives);
+        let batches = self.queue.batches(|a, b| {
+            let (a, b) = (&nodes[a.node], &nodes[b.node]);
+            a.material == b.material && Rc::ptr_eq(&primitives[a.mesh], &primitives[b.mesh])
+        });
+        self.draw_calls = 0;
+
+        for batch in batches {
+            let items = &self.queue.items[batch];
+            let node = &self.nodes[items[0].node];
+            let material = &self.materials[node.material];
+            let pipeline = &self.pipelines[material.pipeline];
+            let primitive = &self.primitives[node.mesh];
+
+            // Redundant binds are dropped by the state cache, items are sorted by material
+            material.bind(pipeline, &self.textures, &mut self.state)?;
+            if pipeline.supports_instancing() {
+                let models: Vec<[f32; 16]> = items.iter().map(|item| item.model).collect();
+                pipeline.draw_instanced(primitive, &models, &mut self.state)?;
+                self.draw_calls += 1;
+            } else {
+                for item in items.iter() {
+                    pipeline.draw(primitive, &item.model, &mut self.state)?;
+                }
+                self.draw_calls += items.len() as i32;
+            }
+        }
+
+        self.state.set_capability(GL::BLEND, false);
+        self.state.set_depth_mask(true);
+        for pipeline in self.pipelines.iter() {
+            pipeline.unbind(&mut self.state);
+        }
+
+        Ok(())
+    }
+}
+
+/// Rotates the nodes for time `t` and returns the indices of those inside the view of `perspective`
+///
+/// The camera sits at the origin, so model space goes straight into clip space.
+pub fn visible_nodes(nodes: &mut [Node], perspective: &na::Matrix4<f32>, t: f32) -> Vec<usize> {
+    let c = t.cos();
+    let s = t.sin();
+    let frustum = Frustum::from_matrix(perspective);
+
+    let mut visible = vec![];
+    for (i, node) in nodes.iter_mut().enumerate() {
+        node.rotate_model(c, s);
+
+        if let Some(bounds) = node.bounds.as_ref() {
+            let world_bounds = bounds.transform(&na::Matrix4::from_column_slice(&node.model));
+            if !frustum.is_visible(&world_bounds) {
+                continue;
+            }
+        }
+        visible.push(i);
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Geometry, Primitive, Program, UniformValue};
+    use crate::model::backend::IndexType;
+    use crate::model::device::{DeviceFeatures, DrawCall};
+    use crate::model::recording_device::{DeviceCall, RecordingDevice};
+
+    const VERT: &str = "attribute vec3 vert_pos;
+attribute vec2 vert_uv;
+uniform mat4 perspective;
+uniform mat4 model_view;
+void main(void) { gl_Position = perspective * model_view * vec4(vert_pos, 1.0); }";
+
+    const INSTANCED_VERT: &str = "attribute vec3 vert_pos;
+attribute vec2 vert_uv;
+attribute mat4 instance_model;
+uniform mat4 perspective;
+void main(void) { gl_Position = perspective * instance_model * vec4(vert_pos, 1.0); }";
+
+    const FRAG: &str = "precision highp float;
+uniform vec4 color;
+void main(void) { gl_FragColor = color; }";
+
+    fn features(webgl2: bool) -> DeviceFeatures {
+        DeviceFeatures { instancing: webgl2, vertex_arrays: webgl2, uint_indices: webgl2, uniform_buffers: webgl2 }
+    }
+
+    /// Two nodes in front of the camera and one behind it, sharing a triangle
+    fn scene(device: &RecordingDevice, vert: &str) -> Scene<RecordingDevice> {
+        let frame = [1.0, 0.0, 0.0].repeat(3);
+        let geometry = Geometry::new(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], vec![0.0; 6], frame.clone(), frame, 1, 3);
+        let primitive = Rc::new(Primitive::new(device.clone(), &geometry));
+
+        let pipeline = DefaultPipeline::from_program(Rc::new(Program::new(device.clone(), vert, FRAG)));
+        let mut material = Material::new("test", 0, &[]);
+        material.uniforms.push(("color".to_string(), UniformValue::Vec4([1.0; 4])));
+
+        let mut scene = Scene::new(GlState::new(device.clone()), vec![pipeline], vec![material], vec![], vec![primitive]);
+        for z in [-5.0, -8.0, 5.0].iter() {
+            let mut node = Node::with_bounds(0, geometry.bounds);
+            node.set_x_y_z(0.0, 0.0, *z);
+            scene.nodes.push(node);
+        }
+
+        scene
+    }
+
+    fn perspective() -> na::Matrix4<f32> {
+        na::Perspective3::new(1.0, 45.0f32.to_radians(), 0.1, 100.0).to_homogeneous()
+    }
+
+    #[test]
+    fn visible_nodes_are_drawn_indexed() {
+        let device = RecordingDevice::new(features(false));
+        let mut scene = scene(&device, VERT);
+        device.clear();
+
+        scene.draw_nodes(&perspective(), 0.0).unwrap();
+
+        let draw = DrawCall { count: 3, index_type: Some(IndexType::U16), instances: None };
+        assert_eq!(device.draws(), vec![draw, draw]);
+        assert_eq!((scene.drawn, scene.culled, scene.draw_calls), (2, 1, 2));
+
+        // The material is bound once for both nodes
+        let calls = device.calls();
+        let color_sets = calls.iter().filter(|call| matches!(call, DeviceCall::SetUniform(name, _) if name == "color")).count();
+        assert_eq!(color_sets, 1);
+        assert_eq!(calls.iter().filter(|call| matches!(call, DeviceCall::SetUniform(name, _) if name == "model_view")).count(), 2);
+    }
+
+    #[test]
+    fn attributes_are_enabled_again_on_the_next_frame() {
+        let device = RecordingDevice::new(features(false));
+        let mut scene = scene(&device, VERT);
+        scene.draw_nodes(&perspective(), 0.0).unwrap();
+        device.clear();
+
+        scene.draw_nodes(&perspective(), 0.0).unwrap();
+
+        // The first frame ended by disabling the arrays, so the second one binds them again before drawing
+        let calls = device.calls();
+        let first_draw = calls.iter().position(|call| matches!(call, DeviceCall::Draw(_))).unwrap();
+        assert!(calls[..first_draw].iter().any(|call| matches!(call, DeviceCall::VertexAttribPointer(..))));
+        assert!(calls[..first_draw].iter().any(|call| matches!(call, DeviceCall::SetAttribArray(_, true))));
+        assert_eq!(device.draws().len(), 2);
+    }
+
+    #[test]
+    fn shared_primitives_are_drawn_instanced() {
+        let device = RecordingDevice::new(features(true));
+        let mut scene = scene(&device, INSTANCED_VERT);
+        assert!(scene.pipelines[0].supports_instancing());
+        device.clear();
+
+        scene.draw_nodes(&perspective(), 0.0).unwrap();
+
+        assert_eq!(device.draws(), vec![DrawCall { count: 3, index_type: Some(IndexType::U16), instances: Some(2) }]);
+        assert_eq!(scene.draw_calls, 1);
+        // Two model matrices went into the instance buffer
+        assert!(device.calls().contains(&DeviceCall::BufferData(GL::ARRAY_BUFFER, 2 * 64)));
+    }
+}
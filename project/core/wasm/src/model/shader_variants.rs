@@ -0,0 +1,107 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use crate::model::Program;
+use crate::model::program_cache::{find_program, program_key, share_program};
+use crate::model::preprocessor::{defines_key, preprocess, to_glsl_es300, ShaderChunks, ShaderStage};
+use crate::model::device::WebGlDevice;
+
+/// Named shader source
+pub struct ShaderSource<'a> {
+    pub name: &'a str,
+    pub source: &'a str,
+}
+
+/// Build cost of a program variant
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgramTiming {
+    /// `<vertex>+<fragment>` followed by the define set
+    pub name: String,
+    pub id: u32,
+    pub compile_ms: f64,
+    pub link_ms: f64,
+    /// Taken from another pipeline on the same GL context instead of compiled
+    pub shared: bool,
+    /// Runs as GLSL ES 3.00, false for ES 1.00 sources
+    pub es300: bool,
+}
+
+/// Compiled program variants keyed by source and define set
+pub struct ShaderVariants {
+    pub gl: WebGlDevice,
+    pub chunks: ShaderChunks,
+    /// WebGL2 contexts get sources translated to GLSL ES 3.00
+    es300: bool,
+    programs: HashMap<String, Rc<Program<WebGlDevice>>>,
+    /// One entry per variant requested by this context, in request order
+    pub timings: Vec<ProgramTiming>,
+}
+
+impl ShaderVariants {
+    pub fn new(gl: WebGlDevice) -> Self {
+        let es300 = gl.webgl2().is_some();
+        Self { gl, chunks: ShaderChunks::new(), es300, programs: HashMap::new(), timings: vec![] }
+    }
+
+    /// Returns the variant for the define set, preprocessing and compiling it on first use
+    ///
+    /// Programs with identical final sources are shared with other contexts on the same GL context.
+    pub fn get(&mut self, vert: &ShaderSource, frag: &ShaderSource, defines: &BTreeMap<String, String>) -> Result<Rc<Program<WebGlDevice>>, String> {
+        let mut hasher = DefaultHasher::new();
+        vert.source.hash(&mut hasher);
+        frag.source.hash(&mut hasher);
+        let key = format!("{}|{}|{:x}|{}", vert.name, frag.name, hasher.finish(), defines_key(defines));
+
+        if let Some(program) = self.programs.get(&key) {
+            return Ok(program.clone());
+        }
+
+        let name = format!("{}+{}|{}", vert.name, frag.name, defines_key(defines));
+        let vert = preprocess(vert.name, vert.source, &self.chunks, defines)?;
+        let frag = preprocess(frag.name, frag.source, &self.chunks, defines)?;
+
+        // Sources that don't survive the translation, e.g. by redefining 3.00 built-ins, stay at 1.00
+        let mut candidates = vec![];
+        if self.es300 {
+            candidates.push((to_glsl_es300(&vert, ShaderStage::Vertex), to_glsl_es300(&frag, ShaderStage::Fragment)));
+        }
+        candidates.push((vert, frag));
+
+        let mut result = Err(String::new());
+        for (vert, frag) in candidates.iter() {
+            let shared_key = program_key(&vert.source, &frag.source, defines);
+            result = match find_program(&self.gl, shared_key) {
+                Some(program) => Ok((program, true)),
+                None => Program::from_preprocessed(self.gl.clone(), vert, frag).map(|program| {
+                    let program = Rc::new(program);
+                    share_program(&self.gl, shared_key, &program);
+                    (program, false)
+                }),
+            };
+
+            if result.is_ok() {
+                break;
+            }
+        }
+        let (program, shared) = result?;
+        let es300 = program.es300;
+
+        self.timings.push(ProgramTiming {
+            name,
+            id: program.id,
+            compile_ms: if shared { 0.0 } else { program.timings.compile_ms },
+            link_ms: if shared { 0.0 } else { program.timings.link_ms },
+            shared,
+            es300,
+        });
+        self.programs.insert(key, program.clone());
+
+        Ok(program)
+    }
+
+    /// Total compile and link time spent by this context, in milliseconds
+    pub fn build_time_ms(&self) -> f64 {
+        self.timings.iter().map(|timing| timing.compile_ms + timing.link_ms).sum()
+    }
+}
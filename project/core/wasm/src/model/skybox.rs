@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use web_sys::{WebGlBuffer, WebGlRenderingContext as GL};
+use crate::model::{GlState, Program, ShaderSource, ShaderVariants, Texture, UniformValue};
+use crate::model::post_process::FULL_SCREEN_TRIANGLE;
+use crate::model::device::WebGlDevice;
+
+const SKYBOX_VERT: &str = include_str!("../shader/skybox/skybox.vert.glsl");
+const SKYBOX_FRAG: &str = include_str!("../shader/skybox/skybox.frag.glsl");
+
+/// Full-screen pass drawing the environment behind the scene
+pub struct Skybox {
+    pub program: Rc<Program<WebGlDevice>>,
+    pub vert_pos_loc: i32,
+    pub buffer: Option<WebGlBuffer>,
+}
+
+impl Skybox {
+    /// Builds the pass with the scene defines, so it decodes and tone maps the environment the same way
+    pub fn new(shader_variants: &mut ShaderVariants, defines: &BTreeMap<String, String>) -> Result<Self, String> {
+        let program = shader_variants.get(
+            &ShaderSource { name: "skybox.vert", source: SKYBOX_VERT },
+            &ShaderSource { name: "skybox.frag", source: SKYBOX_FRAG },
+            defines,
+        )?;
+
+        let gl = &shader_variants.gl;
+
+        let buffer = gl.create_buffer();
+        gl.bind_buffer(GL::ARRAY_BUFFER, buffer.as_ref());
+        let u8_slice = unsafe {
+            std::slice::from_raw_parts(
+                FULL_SCREEN_TRIANGLE.as_ptr() as *const u8,
+                std::mem::size_of_val(&FULL_SCREEN_TRIANGLE),
+            )
+        };
+        gl.buffer_data_with_u8_array(GL::ARRAY_BUFFER, u8_slice, GL::STATIC_DRAW);
+
+        Ok(Self { vert_pos_loc: program.get_attrib_loc("vert_pos"), program, buffer })
+    }
+
+    /// Draws the environment without touching the depth buffer, before any scene geometry
+    pub fn draw(&self, environment: &Texture<WebGlDevice>, texture_num: u32, inv_perspective: [f32; 16], state: &mut GlState<WebGlDevice>) -> Result<(), String> {
+        state.use_program(&self.program);
+        state.bind_texture(texture_num, environment.target, &environment.handle);
+        self.program.set_uniform("env_specular", UniformValue::Sampler((texture_num - GL::TEXTURE0) as i32))?;
+        self.program.set_uniform("inv_perspective", UniformValue::Mat4(inv_perspective))?;
+
+        state.set_capability(GL::DEPTH_TEST, false);
+        state.set_capability(GL::CULL_FACE, false);
+        state.set_capability(GL::BLEND, false);
+        state.set_depth_mask(false);
+
+        state.bind_array_buffer(self.buffer.as_ref());
+        self.program.device.vertex_attrib_pointer_with_i32(self.vert_pos_loc as u32, 2, GL::FLOAT, false, 0, 0);
+        state.set_attrib_array(self.vert_pos_loc as u32, true);
+
+        self.program.device.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        state.set_attrib_array(self.vert_pos_loc as u32, false);
+        // Scene pipelines rebind their attributes after this
+        state.clear_vertex_source();
+
+        Ok(())
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        self.program.device.delete_buffer(self.buffer.as_ref());
+    }
+}
@@ -1,87 +1,450 @@
-use web_sys::WebGlRenderingContext as GL;
+#[cfg(feature = "web")]
+use wasm_bindgen::{JsCast, JsValue};
+#[cfg(feature = "web")]
+use web_sys::WebGlRenderingContext as WebGl;
+#[cfg(feature = "web")]
 use web_sys::*;
 use crate::model::Image;
+#[cfg(feature = "web")]
+use crate::model::cubemap::{CubeImage, FACE_COUNT};
+#[cfg(feature = "web")]
+use crate::model::hdr::{encode_f16, encode_rgbe8, HdrEncoding, HdrImage};
+#[cfg(feature = "web")]
+use crate::model::ktx::{CompressionFamily, CompressionSupport, KtxTexture};
+use crate::model::device::GraphicsDevice;
+#[cfg(feature = "web")]
+use crate::model::device::WebGlDevice;
+use crate::model::gl as GL;
 
-pub struct Texture {
-    pub gl: GL,
-    pub handle: WebGlTexture,
+/// `OES_texture_half_float.HALF_FLOAT_OES`
+#[cfg(feature = "web")]
+const HALF_FLOAT_OES: u32 = 0x8D61;
+/// WebGL2 half float type and sized float formats
+#[cfg(feature = "web")]
+const HALF_FLOAT: u32 = 0x140B;
+#[cfg(feature = "web")]
+const RGB16F: u32 = 0x881B;
+#[cfg(feature = "web")]
+const RGB32F: u32 = 0x8815;
+
+/// HDR storage picked from the available extensions
+#[cfg(feature = "web")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HdrFormat {
+    pub encoding: HdrEncoding,
+    /// Whether the hardware can filter the texels linearly
+    pub filterable: bool,
+    /// Whether float textures take sized internal formats, as in WebGL2
+    pub sized: bool,
+}
+
+#[cfg(feature = "web")]
+impl HdrFormat {
+    /// Prefers full floats, then half floats, then the RGBE fallback
+    ///
+    /// WebGL2 has float textures in core and always filters half floats, so full floats are only
+    /// picked there when `OES_texture_float_linear` filters them too.
+    pub fn detect(gl: &WebGlDevice) -> Self {
+        let has = |name: &str| matches!(gl.get_extension(name), Ok(Some(_)));
+
+        if gl.webgl2().is_some() {
+            if has("OES_texture_float_linear") {
+                Self { encoding: HdrEncoding::Float, filterable: true, sized: true }
+            } else {
+                Self { encoding: HdrEncoding::HalfFloat, filterable: true, sized: true }
+            }
+        } else if has("OES_texture_float") {
+            Self { encoding: HdrEncoding::Float, filterable: has("OES_texture_float_linear"), sized: false }
+        } else if has("OES_texture_half_float") {
+            Self { encoding: HdrEncoding::HalfFloat, filterable: has("OES_texture_half_float_linear"), sized: false }
+        } else {
+            Self { encoding: HdrEncoding::Rgbe, filterable: false, sized: false }
+        }
+    }
+
+    fn bytes_per_texel(&self) -> usize {
+        match self.encoding {
+            HdrEncoding::Float => 12,
+            HdrEncoding::HalfFloat => 6,
+            HdrEncoding::Rgbe => 4,
+        }
+    }
+
+    fn filter(&self, mipmaps: bool) -> (u32, u32) {
+        match (self.filterable, mipmaps) {
+            (true, true) => (GL::LINEAR_MIPMAP_LINEAR, GL::LINEAR),
+            (true, false) => (GL::LINEAR, GL::LINEAR),
+            (false, true) => (GL::NEAREST_MIPMAP_NEAREST, GL::NEAREST),
+            (false, false) => (GL::NEAREST, GL::NEAREST),
+        }
+    }
+
+    /// Uploads one level of linear RGB data to a bound texture
+    fn upload(&self, gl: &WebGl, target: u32, level: i32, size: (u32, u32), rgb: &[f32]) -> Result<(), String> {
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        let result = match self.encoding {
+            HdrEncoding::Float => {
+                let internal_format = if self.sized { RGB32F } else { GL::RGB };
+                let data = js_sys::Float32Array::from(rgb);
+                gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                    target, level, internal_format as i32, width, height, 0, GL::RGB, GL::FLOAT, Some(&data),
+                )
+            }
+            HdrEncoding::HalfFloat => {
+                let (internal_format, half_float) = if self.sized { (RGB16F, HALF_FLOAT) } else { (GL::RGB, HALF_FLOAT_OES) };
+                let data = js_sys::Uint16Array::from(&encode_f16(rgb)[..]);
+                gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                    target, level, internal_format as i32, width, height, 0, GL::RGB, half_float, Some(&data),
+                )
+            }
+            HdrEncoding::Rgbe => {
+                gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                    target, level, GL::RGBA as i32, width, height, 0, GL::RGBA, GL::UNSIGNED_BYTE, Some(&encode_rgbe8(rgb)),
+                )
+            }
+        };
+
+        result.map_err(|_| format!("Failed to upload {:?} texture data", self.encoding))
+    }
+}
+
+#[cfg(feature = "web")]
+impl CompressionSupport {
+    /// Queries the compressed texture extensions, enabling the ones found
+    pub fn detect(gl: &WebGl) -> Self {
+        let families = [
+            CompressionFamily::Astc,
+            CompressionFamily::S3tc,
+            CompressionFamily::S3tcSrgb,
+            CompressionFamily::Etc,
+            CompressionFamily::Etc1,
+        ];
+
+        Self {
+            families: families
+                .iter()
+                .copied()
+                .filter(|family| matches!(gl.get_extension(family.extension()), Ok(Some(_))))
+                .collect(),
+        }
+    }
+}
+
+/// Image the browser decodes itself, uploaded without going through the wasm decoders
+#[cfg(feature = "web")]
+pub enum DomImageSource {
+    Bitmap(ImageBitmap),
+    Image(HtmlImageElement),
+    Canvas(HtmlCanvasElement),
+    /// Uploaded with its current frame, `VideoTexture` keeps it streaming
+    Video(HtmlVideoElement),
+}
+
+#[cfg(feature = "web")]
+impl DomImageSource {
+    pub fn from_js(value: &JsValue) -> Option<Self> {
+        if let Some(bitmap) = value.dyn_ref::<ImageBitmap>() {
+            Some(DomImageSource::Bitmap(bitmap.clone()))
+        } else if let Some(image) = value.dyn_ref::<HtmlImageElement>() {
+            Some(DomImageSource::Image(image.clone()))
+        } else if let Some(canvas) = value.dyn_ref::<HtmlCanvasElement>() {
+            Some(DomImageSource::Canvas(canvas.clone()))
+        } else {
+            value.dyn_ref::<HtmlVideoElement>().map(|video| DomImageSource::Video(video.clone()))
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            DomImageSource::Bitmap(bitmap) => (bitmap.width(), bitmap.height()),
+            DomImageSource::Image(image) => (image.natural_width(), image.natural_height()),
+            DomImageSource::Canvas(canvas) => (canvas.width(), canvas.height()),
+            DomImageSource::Video(video) => (video.video_width(), video.video_height()),
+        }
+    }
+}
+
+pub struct Texture<D: GraphicsDevice> {
+    pub device: D,
+    pub handle: D::Texture,
+    /// `TEXTURE_2D` or `TEXTURE_CUBE_MAP`
+    pub target: u32,
     pub width: u32,
     pub height: u32,
-    pub texture_num: u32,
-    pub uniform_key: String
+    /// Estimated GPU memory of all levels and faces, as uploaded
+    pub gpu_bytes: usize,
+    /// Unsized format of 8-bit 2D textures, `None` when the texels can't be updated from bytes
+    pub byte_format: Option<u32>
 }
 
-pub const TEXTURE_IDS: &'static [u32] = &[GL::TEXTURE0, GL::TEXTURE1];
-
-impl Texture {
+impl<D: GraphicsDevice> Texture<D> {
     /// Returns a new texture uploading data from the specified image
-    pub fn from_image(gl: GL, image: &Image, texture_num: u32, uniform_key: &str) -> Self {
-        let handle = gl.create_texture().expect("Failed to create texture");
+    pub fn from_image(device: D, image: &Image, texture_num: u32) -> Self {
+        let handle = device.create_texture().expect("Failed to create texture");
 
         let mut texture = Self {
-            gl,
+            device,
             handle,
+            target: GL::TEXTURE_2D,
             width: 0,
             height: 0,
-            texture_num,
-            uniform_key: uniform_key.to_string()
+            gpu_bytes: 0,
+            byte_format: None
         };
 
         texture.bind(texture_num);
 
         texture
-            .gl
-            .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+            .device
+            .tex_parameter(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
         texture
-            .gl
-            .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+            .device
+            .tex_parameter(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
 
         //    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MIN_FILTER, gl.LINEAR);
         texture
-            .gl
-            .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+            .device
+            .tex_parameter(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
         texture
-            .gl
-            .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+            .device
+            .tex_parameter(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
 
-        texture.upload(Some(&image.data), image.width, image.height);
+        texture.upload(Some(&image.data), image.width, image.height, format_for_channels(image.channels));
 
         texture
     }
 
-    pub fn bind(&self, texture_num: u32) {
-        self.gl.active_texture(texture_num);
-        self.gl.bind_texture(GL::TEXTURE_2D, Some(&self.handle));
+    /// Sets filtering and edge clamping of the bound texture
+    #[cfg(feature = "web")]
+    fn set_sampling(&self, min_filter: u32, mag_filter: u32) {
+        self.device.tex_parameter(self.target, GL::TEXTURE_MIN_FILTER, min_filter as i32);
+        self.device.tex_parameter(self.target, GL::TEXTURE_MAG_FILTER, mag_filter as i32);
+        self.device.tex_parameter(self.target, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        self.device.tex_parameter(self.target, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
     }
 
-    pub fn bind_self(&self) {
-        self.gl.active_texture(self.texture_num);
-        self.gl.bind_texture(GL::TEXTURE_2D, Some(&self.handle));
+    pub fn bind(&self, texture_num: u32) {
+        self.device.active_texture(texture_num);
+        self.device.bind_texture(self.target, Some(&self.handle));
     }
 
     /// Uploads pixels data to the texture memory in the GPU
-    pub fn upload(&mut self, pixels: Option<&[u8]>, width: u32, height: u32) {
-        self.gl
-            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+    pub fn upload(&mut self, pixels: Option<&[u8]>, width: u32, height: u32, format: u32) {
+        self.device
+            .tex_image_2d(GL::TEXTURE_2D, 0, format, (width, height), GL::UNSIGNED_BYTE, pixels)
+            .expect("Failed to upload texture data");
+
+        self.width = width;
+        self.height = height;
+        self.gpu_bytes = (width * height) as usize * bytes_per_pixel(format);
+        self.byte_format = Some(format);
+    }
+
+    /// Replaces a rectangle of level 0 with tightly packed pixels in the texture's own format
+    ///
+    /// The texture has to be bound, as for `upload`. Returns the number of bytes sent.
+    pub fn update_region(&self, x: u32, y: u32, width: u32, height: u32, data: &[u8]) -> Result<usize, String> {
+        let format = self.byte_format.ok_or("Only 8-bit RGB and RGBA textures can be updated")?;
+        check_region((self.width, self.height), (x, y, width, height), bytes_per_pixel(format), data.len())?;
+
+        // Rows of RGB data are rarely 4-byte aligned
+        self.device.pixel_store(GL::UNPACK_ALIGNMENT, 1);
+        let result = self.device.tex_sub_image_2d(GL::TEXTURE_2D, 0, (x, y, width, height), format, GL::UNSIGNED_BYTE, data);
+        self.device.pixel_store(GL::UNPACK_ALIGNMENT, 4);
+
+        result?;
+        Ok(data.len())
+    }
+}
+
+/// Sources only WebGL can upload
+#[cfg(feature = "web")]
+impl Texture<WebGlDevice> {
+    /// Returns a texture uploaded straight from a browser image source, always as RGBA
+    pub fn from_dom(gl: WebGlDevice, source: &DomImageSource, texture_num: u32) -> Result<Self, String> {
+        match source {
+            DomImageSource::Image(image) if !image.complete() => {
+                return Err("Image element has not finished loading".to_string());
+            }
+            DomImageSource::Video(video) if video.ready_state() < HtmlMediaElement::HAVE_CURRENT_DATA => {
+                return Err("Video element has no frame to show yet".to_string());
+            }
+            _ => {}
+        }
+
+        let handle = gl.create_texture().expect("Failed to create texture");
+        let (width, height) = source.size();
+
+        let gpu_bytes = (width * height * 4) as usize;
+        let texture = Self { device: gl, handle, target: GL::TEXTURE_2D, width, height, gpu_bytes, byte_format: Some(GL::RGBA) };
+        texture.bind(texture_num);
+        texture.set_sampling(GL::LINEAR, GL::LINEAR);
+
+        let gl = &texture.device;
+        let (format, data_type) = (GL::RGBA, GL::UNSIGNED_BYTE);
+        match source {
+            DomImageSource::Bitmap(bitmap) => {
+                gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(GL::TEXTURE_2D, 0, format as i32, format, data_type, bitmap)
+            }
+            DomImageSource::Image(image) => {
+                gl.tex_image_2d_with_u32_and_u32_and_image(GL::TEXTURE_2D, 0, format as i32, format, data_type, image)
+            }
+            DomImageSource::Canvas(canvas) => {
+                gl.tex_image_2d_with_u32_and_u32_and_canvas(GL::TEXTURE_2D, 0, format as i32, format, data_type, canvas)
+            }
+            DomImageSource::Video(video) => {
+                gl.tex_image_2d_with_u32_and_u32_and_video(GL::TEXTURE_2D, 0, format as i32, format, data_type, video)
+            }
+        }.map_err(|_| "Failed to upload browser image".to_string())?;
+
+        Ok(texture)
+    }
+
+    /// Returns a texture holding compressed levels, the device has to support their format
+    pub fn from_compressed(gl: WebGlDevice, ktx: &KtxTexture, texture_num: u32) -> Result<Self, String> {
+        let handle = gl.create_texture().expect("Failed to create texture");
+
+        let texture = Self { device: gl, handle, target: GL::TEXTURE_2D, width: ktx.width, height: ktx.height, gpu_bytes: ktx.byte_size(), byte_format: None };
+        texture.bind(texture_num);
+
+        // A partial chain would leave the texture incomplete once mipmapping is on
+        let min_filter = if ktx.levels.len() > 1 && ktx.has_full_mip_chain() { GL::LINEAR_MIPMAP_LINEAR } else { GL::LINEAR };
+        texture.set_sampling(min_filter, GL::LINEAR);
+
+        for (level, data) in ktx.levels.iter().enumerate() {
+            let (width, height) = ktx.level_size(level);
+            texture.device.compressed_tex_image_2d_with_u8_array(
                 GL::TEXTURE_2D,
-                0,
-                GL::RGB as i32,
+                level as i32,
+                ktx.format.gl_format,
                 width as i32,
                 height as i32,
                 0,
-                GL::RGB,
-                GL::UNSIGNED_BYTE,
-                pixels,
-            )
-            .expect("Failed to upload texture data");
+                data,
+            );
+        }
 
-        self.width = width;
-        self.height = height;
+        match texture.device.get_error() {
+            GL::NO_ERROR => Ok(texture),
+            error => Err(format!("Failed to upload compressed texture, GL error 0x{:x}", error)),
+        }
+    }
+
+    /// Returns a linear float texture, or an RGBE-encoded one when float textures aren't available
+    pub fn from_hdr(gl: WebGlDevice, image: &HdrImage, texture_num: u32, format: HdrFormat) -> Result<Self, String> {
+        let handle = gl.create_texture().expect("Failed to create texture");
+
+        let gpu_bytes = (image.width * image.height) as usize * format.bytes_per_texel();
+        let texture = Self { device: gl, handle, target: GL::TEXTURE_2D, width: image.width, height: image.height, gpu_bytes, byte_format: None };
+        texture.bind(texture_num);
+
+        let (min_filter, mag_filter) = format.filter(false);
+        texture.set_sampling(min_filter, mag_filter);
+        format.upload(&texture.device, GL::TEXTURE_2D, 0, (image.width, image.height), &image.data)?;
+
+        Ok(texture)
+    }
+
+    /// Returns a cube texture with one mip level per cube, level 0 first
+    ///
+    /// Without an HDR format the levels are stored as 8-bit sRGB.
+    pub fn from_cube_levels(gl: WebGlDevice, levels: &[CubeImage], texture_num: u32, format: Option<HdrFormat>) -> Result<Self, String> {
+        let handle = gl.create_texture().expect("Failed to create texture");
+        let size = levels[0].size;
+
+        let bytes_per_texel = format.map_or(3, |format| format.bytes_per_texel());
+        let gpu_bytes = levels.iter().map(|cube| (cube.size * cube.size) as usize * bytes_per_texel * FACE_COUNT).sum();
+
+        let texture = Self { device: gl, handle, target: GL::TEXTURE_CUBE_MAP, width: size, height: size, gpu_bytes, byte_format: None };
+        texture.bind(texture_num);
+
+        let mipmaps = levels.len() > 1;
+        let (min_filter, mag_filter) = match format {
+            Some(format) => format.filter(mipmaps),
+            None if mipmaps => (GL::LINEAR_MIPMAP_LINEAR, GL::LINEAR),
+            None => (GL::LINEAR, GL::LINEAR),
+        };
+        texture.set_sampling(min_filter, mag_filter);
+
+        let gl = &texture.device;
+        for (level, cube) in levels.iter().enumerate() {
+            for face in 0..FACE_COUNT {
+                let target = GL::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32;
+                match format {
+                    Some(format) => format.upload(gl, target, level as i32, (cube.size, cube.size), &cube.face_rgb(face))?,
+                    None => gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                        target,
+                        level as i32,
+                        GL::RGB as i32,
+                        cube.size as i32,
+                        cube.size as i32,
+                        0,
+                        GL::RGB,
+                        GL::UNSIGNED_BYTE,
+                        Some(&cube.face_rgb8(face)),
+                    ).map_err(|_| "Failed to upload cube map face".to_string())?,
+                }
+            }
+        }
+
+        Ok(texture)
     }
 }
 
-impl Drop for Texture {
+/// Bytes per pixel of an unsized 8-bit format
+pub fn bytes_per_pixel(format: u32) -> usize {
+    if format == GL::RGBA { 4 } else { 3 }
+}
+
+/// Checks that a region lies inside a texture of `size` and that `len` bytes cover it exactly
+fn check_region(size: (u32, u32), region: (u32, u32, u32, u32), bytes_per_pixel: usize, len: usize) -> Result<(), String> {
+    let (x, y, width, height) = region;
+    if x as u64 + width as u64 > size.0 as u64 || y as u64 + height as u64 > size.1 as u64 {
+        return Err(format!(
+            "Region {}x{} at ({}, {}) exceeds the {}x{} texture",
+            width, height, x, y, size.0, size.1
+        ));
+    }
+
+    let expected = width as usize * height as usize * bytes_per_pixel;
+    if len != expected {
+        return Err(format!("Region {}x{} needs {} bytes, got {}", width, height, expected, len));
+    }
+
+    Ok(())
+}
+
+/// Unsized GL format matching the channel count of an `Image`
+pub fn format_for_channels(channels: u32) -> u32 {
+    match channels {
+        4 => GL::RGBA,
+        _ => GL::RGB,
+    }
+}
+
+impl<D: GraphicsDevice> Drop for Texture<D> {
     fn drop(&mut self) {
-        self.gl.delete_texture(Some(&self.handle))
+        self.device.delete_texture(&self.handle)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_have_to_fit_the_texture() {
+        assert!(check_region((8, 4), (0, 0, 8, 4), 4, 128).is_ok());
+        assert!(check_region((8, 4), (6, 3, 2, 1), 3, 6).is_ok());
+
+        assert!(check_region((8, 4), (7, 0, 2, 1), 4, 8).is_err());
+        assert!(check_region((8, 4), (0, 4, 1, 1), 4, 4).is_err());
+        assert!(check_region((8, 4), (u32::MAX, 0, 2, 1), 4, 8).is_err());
+    }
+
+    #[test]
+    fn region_data_has_to_match_its_size() {
+        assert!(check_region((8, 4), (0, 0, 3, 2), 3, 17).is_err());
+        assert!(check_region((8, 4), (0, 0, 3, 2), 3, 19).is_err());
+        assert!(check_region((8, 4), (0, 0, 3, 2), 4, 18).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(feature = "web")]
+use web_sys::{WebGlRenderingContext as WebGl, WebGlUniformLocation};
+use crate::model::gl as GL;
+
+/// Typed uniform value, from JS as `{type: "vec3", value: [1, 0, 0]}`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat3([f32; 9]),
+    Mat4([f32; 16]),
+    Int(i32),
+    /// Texture unit index
+    Sampler(i32),
+    /// Arrays start at element 0 and may be shorter than the declared size
+    FloatArray(Vec<f32>),
+    Vec3Array(Vec<[f32; 3]>),
+    Vec4Array(Vec<[f32; 4]>),
+}
+
+impl UniformValue {
+    /// Whether the value can be assigned to a uniform of the given GL type
+    pub fn accepts(&self, gl_type: u32) -> bool {
+        match self {
+            UniformValue::Float(_) => gl_type == GL::FLOAT,
+            UniformValue::Vec2(_) => gl_type == GL::FLOAT_VEC2,
+            UniformValue::Vec3(_) => gl_type == GL::FLOAT_VEC3,
+            UniformValue::Vec4(_) => gl_type == GL::FLOAT_VEC4,
+            UniformValue::Mat3(_) => gl_type == GL::FLOAT_MAT3,
+            UniformValue::Mat4(_) => gl_type == GL::FLOAT_MAT4,
+            UniformValue::Int(_) => gl_type == GL::INT || gl_type == GL::BOOL,
+            UniformValue::Sampler(_) => gl_type == GL::SAMPLER_2D || gl_type == GL::SAMPLER_CUBE,
+            UniformValue::FloatArray(_) => gl_type == GL::FLOAT,
+            UniformValue::Vec3Array(_) => gl_type == GL::FLOAT_VEC3,
+            UniformValue::Vec4Array(_) => gl_type == GL::FLOAT_VEC4,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            UniformValue::Float(_) => "float",
+            UniformValue::Vec2(_) => "vec2",
+            UniformValue::Vec3(_) => "vec3",
+            UniformValue::Vec4(_) => "vec4",
+            UniformValue::Mat3(_) => "mat3",
+            UniformValue::Mat4(_) => "mat4",
+            UniformValue::Int(_) => "int",
+            UniformValue::Sampler(_) => "sampler",
+            UniformValue::FloatArray(_) => "float[]",
+            UniformValue::Vec3Array(_) => "vec3[]",
+            UniformValue::Vec4Array(_) => "vec4[]",
+        }
+    }
+
+    /// Number of array elements written, `None` for plain values
+    pub fn array_len(&self) -> Option<usize> {
+        match self {
+            UniformValue::FloatArray(v) => Some(v.len()),
+            UniformValue::Vec3Array(v) => Some(v.len()),
+            UniformValue::Vec4Array(v) => Some(v.len()),
+            _ => None,
+        }
+    }
+
+    /// Issues the matching `uniform*` call, the owning program must be in use
+    #[cfg(feature = "web")]
+    pub fn apply(&self, gl: &WebGl, loc: Option<&WebGlUniformLocation>) {
+        match self {
+            UniformValue::Float(v) => gl.uniform1f(loc, *v),
+            UniformValue::Vec2(v) => gl.uniform2fv_with_f32_array(loc, v),
+            UniformValue::Vec3(v) => gl.uniform3fv_with_f32_array(loc, v),
+            UniformValue::Vec4(v) => gl.uniform4fv_with_f32_array(loc, v),
+            UniformValue::Mat3(v) => gl.uniform_matrix3fv_with_f32_array(loc, false, v),
+            UniformValue::Mat4(v) => gl.uniform_matrix4fv_with_f32_array(loc, false, v),
+            UniformValue::Int(v) | UniformValue::Sampler(v) => gl.uniform1i(loc, *v),
+            UniformValue::FloatArray(v) => gl.uniform1fv_with_f32_array(loc, v),
+            UniformValue::Vec3Array(v) => gl.uniform3fv_with_f32_array(loc, &flatten(v)),
+            UniformValue::Vec4Array(v) => gl.uniform4fv_with_f32_array(loc, &flatten(v)),
+        }
+    }
+}
+
+#[cfg(any(feature = "web", test))]
+fn flatten<const N: usize>(values: &[[f32; N]]) -> Vec<f32> {
+    values.iter().flat_map(|value| value.iter().copied()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_accept_only_matching_types() {
+        assert!(UniformValue::Float(1.0).accepts(GL::FLOAT));
+        assert!(!UniformValue::Float(1.0).accepts(GL::FLOAT_VEC2));
+        assert!(UniformValue::Mat4([0.0; 16]).accepts(GL::FLOAT_MAT4));
+        assert!(!UniformValue::Mat3([0.0; 9]).accepts(GL::FLOAT_MAT4));
+        assert!(UniformValue::Int(1).accepts(GL::BOOL));
+        assert!(UniformValue::Sampler(0).accepts(GL::SAMPLER_CUBE));
+        assert!(!UniformValue::Int(0).accepts(GL::SAMPLER_2D));
+    }
+
+    #[test]
+    fn values_deserialize_from_tagged_json() {
+        let value: UniformValue = serde_json::from_str(r#"{"type": "vec3", "value": [1.0, 0.5, 0.0]}"#).unwrap();
+        assert_eq!(value, UniformValue::Vec3([1.0, 0.5, 0.0]));
+
+        let value: UniformValue = serde_json::from_str(r#"{"type": "float", "value": 2.5}"#).unwrap();
+        assert_eq!(value, UniformValue::Float(2.5));
+
+        let value: UniformValue = serde_json::from_str(r#"{"type": "vec3_array", "value": [[1, 0, 0], [0, 1, 0]]}"#).unwrap();
+        assert_eq!(value.array_len(), Some(2));
+    }
+
+    #[test]
+    fn arrays_are_flattened_in_element_order() {
+        assert_eq!(flatten(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert!(UniformValue::Vec4Array(vec![]).accepts(GL::FLOAT_VEC4));
+        assert!(!UniformValue::Vec4Array(vec![]).accepts(GL::FLOAT_VEC3));
+    }
+}
@@ -0,0 +1,106 @@
+//! Textures streamed from `<video>` elements
+
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::{HtmlMediaElement, HtmlVideoElement};
+use crate::model::{GlState, Texture};
+use crate::model::device::WebGlDevice;
+
+/// What a new video frame needs on the GPU side
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameUpload {
+    /// The uploaded frame is still current
+    Skip,
+    /// First frame or new video size, storage is reallocated
+    Allocate,
+    /// Same size, the texels are replaced in place
+    Update,
+}
+
+impl FrameUpload {
+    /// Compares the uploaded frame, as (media time, size), with the one the video shows now
+    pub fn decide(uploaded: Option<(f64, (u32, u32))>, time: f64, size: (u32, u32)) -> Self {
+        match uploaded {
+            None => FrameUpload::Allocate,
+            Some((_, uploaded_size)) if uploaded_size != size => FrameUpload::Allocate,
+            // Paused or stalled videos keep their media time
+            Some((uploaded_time, _)) if uploaded_time == time => FrameUpload::Skip,
+            Some(_) => FrameUpload::Update,
+        }
+    }
+}
+
+/// Re-uploads a video into one of the scene textures whenever it shows a new frame
+pub struct VideoTexture {
+    pub video: HtmlVideoElement,
+    /// Index of the texture it streams into
+    pub texture: usize,
+    /// Media time and size of the uploaded frame
+    uploaded: Option<(f64, (u32, u32))>,
+    pub frames_uploaded: u32,
+    pub bytes_uploaded: usize,
+}
+
+impl VideoTexture {
+    /// `texture` already holds the current frame when `uploaded` is set
+    pub fn new(video: HtmlVideoElement, texture: usize, uploaded: bool) -> Self {
+        let state = if uploaded { Some((video.current_time(), (video.video_width(), video.video_height()))) } else { None };
+
+        Self { video, texture, uploaded: state, frames_uploaded: 0, bytes_uploaded: 0 }
+    }
+
+    /// Uploads the current frame as RGBA if it changed, returns the bytes sent
+    pub fn update(&mut self, texture: &mut Texture<WebGlDevice>, state: &mut GlState<WebGlDevice>) -> Result<usize, String> {
+        let video = &self.video;
+        let media: &HtmlMediaElement = video.as_ref();
+        if media.ready_state() < HtmlMediaElement::HAVE_CURRENT_DATA {
+            return Ok(0);
+        }
+
+        let time = media.current_time();
+        let size = (video.video_width(), video.video_height());
+        let upload = FrameUpload::decide(self.uploaded, time, size);
+        if upload == FrameUpload::Skip {
+            return Ok(0);
+        }
+
+        state.bind_texture(GL::TEXTURE0, texture.target, &texture.handle);
+        let gl = &texture.device;
+        let (format, data_type) = (GL::RGBA, GL::UNSIGNED_BYTE);
+        match upload {
+            FrameUpload::Allocate => gl.tex_image_2d_with_u32_and_u32_and_video(GL::TEXTURE_2D, 0, format as i32, format, data_type, video),
+            _ => gl.tex_sub_image_2d_with_u32_and_u32_and_video(GL::TEXTURE_2D, 0, 0, 0, format, data_type, video),
+        }.map_err(|_| "Failed to upload video frame".to_string())?;
+
+        let bytes = (size.0 * size.1) as usize * 4;
+        texture.width = size.0;
+        texture.height = size.1;
+        texture.gpu_bytes = bytes;
+        texture.byte_format = Some(format);
+
+        self.uploaded = Some((time, size));
+        self.frames_uploaded += 1;
+        self.bytes_uploaded += bytes;
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_upload_only_when_they_change() {
+        assert_eq!(FrameUpload::decide(None, 0.0, (4, 4)), FrameUpload::Allocate);
+        assert_eq!(FrameUpload::decide(Some((0.5, (4, 4))), 0.5, (4, 4)), FrameUpload::Skip);
+        assert_eq!(FrameUpload::decide(Some((0.5, (4, 4))), 0.54, (4, 4)), FrameUpload::Update);
+        // Looping videos jump back in time
+        assert_eq!(FrameUpload::decide(Some((9.9, (4, 4))), 0.0, (4, 4)), FrameUpload::Update);
+    }
+
+    #[test]
+    fn new_sizes_reallocate() {
+        assert_eq!(FrameUpload::decide(Some((0.5, (4, 4))), 0.5, (8, 4)), FrameUpload::Allocate);
+        assert_eq!(FrameUpload::decide(Some((0.5, (4, 4))), 0.6, (4, 8)), FrameUpload::Allocate);
+    }
+}
@@ -0,0 +1,415 @@
+//! WebGPU renderer for the shared scene
+//!
+//! Geometry, decoded images and nodes come from the same code as the WebGL path, only the GPU
+//! objects differ. Node transforms go into one uniform buffer, read at a dynamic offset per draw.
+
+use js_sys::{JsNullable, Number};
+use nalgebra as na;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{gpu_buffer_usage, gpu_shader_stage, gpu_texture_usage, HtmlCanvasElement};
+use web_sys::{GpuAddressMode, GpuCompareFunction, GpuCullMode, GpuFilterMode, GpuIndexFormat, GpuLoadOp, GpuPrimitiveTopology};
+use web_sys::{GpuBindGroup, GpuBindGroupDescriptor, GpuBindGroupEntry, GpuBindGroupLayout, GpuBindGroupLayoutDescriptor, GpuBindGroupLayoutEntry};
+use web_sys::{GpuBuffer, GpuBufferBinding, GpuBufferBindingLayout, GpuBufferBindingType, GpuBufferDescriptor};
+use web_sys::{GpuCanvasConfiguration, GpuCanvasContext, GpuDevice, GpuStoreOp, GpuTextureFormat};
+use web_sys::{GpuColorTargetState, GpuDepthStencilState, GpuFragmentState, GpuPipelineLayoutDescriptor, GpuPrimitiveState};
+use web_sys::{GpuRenderPassColorAttachment, GpuRenderPassDepthStencilAttachment, GpuRenderPassDescriptor};
+use web_sys::{GpuRenderPipeline, GpuRenderPipelineDescriptor, GpuShaderModuleDescriptor};
+use web_sys::{GpuSamplerBindingLayout, GpuSamplerDescriptor, GpuTexelCopyBufferLayout, GpuTexelCopyTextureInfo};
+use web_sys::{GpuTexture, GpuTextureBindingLayout, GpuTextureDescriptor, GpuTextureSampleType, GpuTextureView};
+use web_sys::{GpuVertexAttribute, GpuVertexBufferLayout, GpuVertexFormat, GpuVertexState};
+use crate::model::{Geometry, Image, Node};
+use crate::model::backend::IndexType;
+
+/// WGSL port of the default scene shaders, samples `tex_diffuse` and `tex_norm`
+pub const SCENE_WGSL: &str = include_str!("../shader/webgpu/scene.wgsl");
+
+/// `model_view` and `norm_mtx` of a node
+const NODE_TRANSFORM_SIZE: usize = 128;
+/// Dynamic uniform offsets are multiples of `minUniformBufferOffsetAlignment`, which is at most 256
+pub const NODE_TRANSFORM_STRIDE: usize = 256;
+
+const DEPTH_FORMAT: GpuTextureFormat = GpuTextureFormat::Depth24plus;
+
+/// Vertex and index buffers of an uploaded geometry
+pub struct GpuMesh {
+    /// Position, uv, tangent and bitangent buffers, in shader location order
+    vertex_buffers: Vec<GpuBuffer>,
+    index_buffer: GpuBuffer,
+    index_format: GpuIndexFormat,
+    index_count: u32,
+}
+
+pub struct WebGpuRenderer {
+    device: GpuDevice,
+    context: GpuCanvasContext,
+    pipeline: GpuRenderPipeline,
+    /// Holds the perspective matrix
+    frame_buffer: GpuBuffer,
+    frame_group: GpuBindGroup,
+    node_layout: GpuBindGroupLayout,
+    /// Transforms of the visible nodes, grown with the scene
+    node_buffer: GpuBuffer,
+    node_group: GpuBindGroup,
+    node_capacity: usize,
+    /// Depth texture view and the size it was created for
+    depth: Option<(GpuTextureView, u32, u32)>,
+    pub meshes: Vec<GpuMesh>,
+}
+
+impl WebGpuRenderer {
+    /// Requests a device and configures the canvas, the textures stay bound for every draw
+    pub async fn new(canvas: &HtmlCanvasElement, diffuse: &Image, norm: &Image) -> Result<Self, JsValue> {
+        let gpu = web_sys::window().ok_or("No window")?.navigator().gpu();
+        // `navigator.gpu` is missing where WebGPU is not supported
+        if gpu.is_undefined() {
+            return Err("WebGPU is not supported".into());
+        }
+        let adapter = gpu.request_adapter().await?.into_option().ok_or("No WebGPU adapter available")?;
+        let device = adapter.request_device().await?;
+
+        let context = canvas
+            .get_context("webgpu")?
+            .ok_or("Failed to get a WebGPU canvas context")?
+            .unchecked_into::<GpuCanvasContext>();
+        let format = gpu.get_preferred_canvas_format();
+        context.configure(&GpuCanvasConfiguration::new(&device, format))?;
+
+        let frame_layout = device.create_bind_group_layout(&GpuBindGroupLayoutDescriptor::new(&[
+            uniform_layout_entry(0, false),
+            {
+                let entry = GpuBindGroupLayoutEntry::new(1, gpu_shader_stage::FRAGMENT);
+                entry.set_sampler(&GpuSamplerBindingLayout::new());
+                entry
+            },
+            texture_layout_entry(2),
+            texture_layout_entry(3),
+        ]))?;
+        let node_layout = device.create_bind_group_layout(&GpuBindGroupLayoutDescriptor::new(&[uniform_layout_entry(0, true)]))?;
+
+        let pipeline = create_pipeline(&device, format, &[frame_layout.clone(), node_layout.clone()])?;
+
+        let sampler_descriptor = GpuSamplerDescriptor::new();
+        sampler_descriptor.set_min_filter(GpuFilterMode::Linear);
+        sampler_descriptor.set_mag_filter(GpuFilterMode::Linear);
+        sampler_descriptor.set_address_mode_u(GpuAddressMode::ClampToEdge);
+        sampler_descriptor.set_address_mode_v(GpuAddressMode::ClampToEdge);
+        let sampler = device.create_sampler_with_descriptor(&sampler_descriptor);
+
+        let frame_buffer = create_buffer(&device, gpu_buffer_usage::UNIFORM, &[0; 64])?;
+        let frame_group = device.create_bind_group(&GpuBindGroupDescriptor::new(&[
+            GpuBindGroupEntry::new_with_gpu_buffer(0, &frame_buffer),
+            GpuBindGroupEntry::new(1, &sampler),
+            GpuBindGroupEntry::new_with_gpu_texture_view(2, &create_texture(&device, diffuse)?),
+            GpuBindGroupEntry::new_with_gpu_texture_view(3, &create_texture(&device, norm)?),
+        ], &frame_layout));
+
+        let node_capacity = 64;
+        let (node_buffer, node_group) = create_node_buffer(&device, &node_layout, node_capacity)?;
+
+        Ok(Self {
+            device,
+            context,
+            pipeline,
+            frame_buffer,
+            frame_group,
+            node_layout,
+            node_buffer,
+            node_group,
+            node_capacity,
+            depth: None,
+            meshes: vec![],
+        })
+    }
+
+    /// Uploads the geometry with shared vertices merged, returns its mesh index for `Node::mesh`
+    pub fn upload_mesh(&mut self, geometry: &Geometry) -> Result<usize, JsValue> {
+        let indexed = geometry.indexed();
+
+        let vertex_buffers = [&indexed.vertices, &indexed.uvs, &indexed.tangents, &indexed.bitangents]
+            .iter()
+            .map(|data| create_buffer(&self.device, gpu_buffer_usage::VERTEX, as_bytes(data)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // 32-bit indices are core in WebGPU
+        let (index_buffer, index_format) = match IndexType::pick(indexed.vertices.len() / 3, true) {
+            Some(IndexType::U16) => {
+                let indices: Vec<u16> = indexed.indices.iter().map(|&index| index as u16).collect();
+                (create_buffer(&self.device, gpu_buffer_usage::INDEX, as_bytes(&indices))?, GpuIndexFormat::Uint16)
+            }
+            _ => (create_buffer(&self.device, gpu_buffer_usage::INDEX, as_bytes(&indexed.indices))?, GpuIndexFormat::Uint32),
+        };
+
+        self.meshes.push(GpuMesh { vertex_buffers, index_buffer, index_format, index_count: indexed.indices.len() as u32 });
+
+        Ok(self.meshes.len() - 1)
+    }
+
+    /// Draws the `visible` nodes into the canvas, returns the number of draw calls
+    ///
+    /// `perspective` is a GL projection, it is remapped to WebGPU clip space here.
+    pub fn draw(&mut self, nodes: &[Node], visible: &[usize], perspective: &[f32; 16], width: u32, height: u32) -> Result<i32, JsValue> {
+        let depth_view = self.depth_view(width, height)?;
+        let queue = self.device.queue();
+
+        let perspective = gpu_clip_space(&na::Matrix4::from_column_slice(perspective));
+        queue.write_buffer_with_u32_and_u8_slice(&self.frame_buffer, 0, as_bytes(perspective.as_slice()))?;
+
+        let models: Vec<[f32; 16]> = visible.iter().map(|&i| nodes[i].model).collect();
+        self.reserve_nodes(models.len())?;
+        if !models.is_empty() {
+            queue.write_buffer_with_u32_and_u8_slice(&self.node_buffer, 0, &pack_node_transforms(&models))?;
+        }
+
+        let target = self.context.get_current_texture()?.create_view()?;
+        let color = GpuRenderPassColorAttachment::new_with_gpu_texture_view(GpuLoadOp::Clear, GpuStoreOp::Store, &target);
+        color.set_clear_value(&[Number::from(0.0), Number::from(0.0), Number::from(0.0), Number::from(1.0)]);
+        let depth = GpuRenderPassDepthStencilAttachment::new_with_gpu_texture_view(&depth_view);
+        depth.set_depth_clear_value(1.0);
+        depth.set_depth_load_op(GpuLoadOp::Clear);
+        depth.set_depth_store_op(GpuStoreOp::Discard);
+        let pass_descriptor = GpuRenderPassDescriptor::new(&[JsNullable::wrap(color)]);
+        pass_descriptor.set_depth_stencil_attachment(&depth);
+
+        let encoder = self.device.create_command_encoder();
+        let pass = encoder.begin_render_pass(&pass_descriptor)?;
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, Some(&self.frame_group));
+
+        let mut bound_mesh = None;
+        for (slot, &i) in visible.iter().enumerate() {
+            let mesh = &self.meshes[nodes[i].mesh];
+            if bound_mesh != Some(nodes[i].mesh) {
+                for (location, buffer) in mesh.vertex_buffers.iter().enumerate() {
+                    pass.set_vertex_buffer(location as u32, Some(buffer));
+                }
+                pass.set_index_buffer(&mesh.index_buffer, mesh.index_format);
+                bound_mesh = Some(nodes[i].mesh);
+            }
+
+            let offset = Number::from((slot * NODE_TRANSFORM_STRIDE) as u32);
+            pass.set_bind_group_with_u32_sequence(1, Some(&self.node_group), &[offset]);
+            pass.draw_indexed(mesh.index_count);
+        }
+        pass.end();
+
+        queue.submit(&[encoder.finish()]);
+
+        Ok(visible.len() as i32)
+    }
+
+    pub fn context(&self) -> &GpuCanvasContext {
+        &self.context
+    }
+
+    /// Depth buffer matching the canvas, created again when the canvas was resized
+    fn depth_view(&mut self, width: u32, height: u32) -> Result<GpuTextureView, JsValue> {
+        if let Some((view, depth_width, depth_height)) = self.depth.as_ref() {
+            if (*depth_width, *depth_height) == (width, height) {
+                return Ok(view.clone());
+            }
+        }
+
+        let size = [Number::from(width), Number::from(height)];
+        let texture = self.device.create_texture(&GpuTextureDescriptor::new(DEPTH_FORMAT, &size, gpu_texture_usage::RENDER_ATTACHMENT))?;
+        let view = texture.create_view()?;
+        self.depth = Some((view.clone(), width, height));
+
+        Ok(view)
+    }
+
+    /// Grows the node buffer to hold `count` transforms, doubling to keep reallocations rare
+    fn reserve_nodes(&mut self, count: usize) -> Result<(), JsValue> {
+        if count <= self.node_capacity {
+            return Ok(());
+        }
+
+        let capacity = count.next_power_of_two();
+        let (buffer, group) = create_node_buffer(&self.device, &self.node_layout, capacity)?;
+        self.node_buffer.destroy();
+        self.node_buffer = buffer;
+        self.node_group = group;
+        self.node_capacity = capacity;
+
+        Ok(())
+    }
+}
+
+/// Remaps the clip z of a GL `projection` from [-w, w] to the [0, w] range of WebGPU
+pub fn gpu_clip_space(projection: &na::Matrix4<f32>) -> na::Matrix4<f32> {
+    // z' = 0.5 * z + 0.5 * w
+    let remap = na::Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.5, 0.5,
+        0.0, 0.0, 0.0, 1.0,
+    );
+
+    remap * projection
+}
+
+/// Packs model and normal matrices of the nodes `NODE_TRANSFORM_STRIDE` apart
+///
+/// Normal matrices are inverted here since WGSL has no `inverse`.
+pub fn pack_node_transforms(models: &[[f32; 16]]) -> Vec<u8> {
+    let mut data = vec![0; models.len() * NODE_TRANSFORM_STRIDE];
+
+    for (model, slot) in models.iter().zip(data.chunks_exact_mut(NODE_TRANSFORM_STRIDE)) {
+        let linear = na::Matrix4::from_column_slice(model).fixed_slice::<na::U3, na::U3>(0, 0).into_owned();
+        // Singular (zero scale) models have nothing to light
+        let norm_mtx = linear.try_inverse().unwrap_or_else(na::Matrix3::identity).transpose().to_homogeneous();
+
+        for (value, bytes) in model.iter().chain(norm_mtx.as_slice().iter()).zip(slot.chunks_exact_mut(4)) {
+            bytes.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    data
+}
+
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            data.as_ptr() as *const u8,
+            std::mem::size_of_val(data),
+        )
+    }
+}
+
+/// Creates a buffer holding `data`, padded since WebGPU copies whole 4-byte words
+fn create_buffer(device: &GpuDevice, usage: u32, data: &[u8]) -> Result<GpuBuffer, JsValue> {
+    let mut padded = data.to_vec();
+    padded.resize(data.len().next_multiple_of(4), 0);
+
+    let buffer = device.create_buffer(&GpuBufferDescriptor::new(padded.len() as u32, usage | gpu_buffer_usage::COPY_DST))?;
+    device.queue().write_buffer_with_u32_and_u8_slice(&buffer, 0, &padded)?;
+
+    Ok(buffer)
+}
+
+/// Uploads the image as sRGB-unaware RGBA8, like the WebGL textures
+fn create_texture(device: &GpuDevice, image: &Image) -> Result<GpuTextureView, JsValue> {
+    let size = [Number::from(image.width), Number::from(image.height)];
+    let texture: GpuTexture = device.create_texture(&GpuTextureDescriptor::new(
+        GpuTextureFormat::Rgba8unorm,
+        &size,
+        gpu_texture_usage::TEXTURE_BINDING | gpu_texture_usage::COPY_DST,
+    ))?;
+
+    let layout = GpuTexelCopyBufferLayout::new();
+    layout.set_bytes_per_row(image.width * 4);
+    device.queue().write_texture_with_u8_slice_and_u32_sequence(&GpuTexelCopyTextureInfo::new(&texture), &image.rgba_data(), &layout, &size)?;
+
+    texture.create_view()
+}
+
+fn create_node_buffer(device: &GpuDevice, layout: &GpuBindGroupLayout, capacity: usize) -> Result<(GpuBuffer, GpuBindGroup), JsValue> {
+    let buffer = device.create_buffer(&GpuBufferDescriptor::new(
+        (capacity * NODE_TRANSFORM_STRIDE) as u32,
+        gpu_buffer_usage::UNIFORM | gpu_buffer_usage::COPY_DST,
+    ))?;
+
+    // One transform is visible at a time, the dynamic offset picks which
+    let binding = GpuBufferBinding::new(&buffer);
+    binding.set_size(NODE_TRANSFORM_SIZE as u32);
+    let group = device.create_bind_group(&GpuBindGroupDescriptor::new(&[GpuBindGroupEntry::new_with_gpu_buffer_binding(0, &binding)], layout));
+
+    Ok((buffer, group))
+}
+
+fn uniform_layout_entry(binding: u32, dynamic_offset: bool) -> GpuBindGroupLayoutEntry {
+    let buffer = GpuBufferBindingLayout::new();
+    buffer.set_type(GpuBufferBindingType::Uniform);
+    buffer.set_has_dynamic_offset(dynamic_offset);
+
+    let entry = GpuBindGroupLayoutEntry::new(binding, gpu_shader_stage::VERTEX);
+    entry.set_buffer(&buffer);
+    entry
+}
+
+fn texture_layout_entry(binding: u32) -> GpuBindGroupLayoutEntry {
+    let texture = GpuTextureBindingLayout::new();
+    texture.set_sample_type(GpuTextureSampleType::Float);
+
+    let entry = GpuBindGroupLayoutEntry::new(binding, gpu_shader_stage::FRAGMENT);
+    entry.set_texture(&texture);
+    entry
+}
+
+/// Pipeline of `SCENE_WGSL` with the state of the default WebGL material
+fn create_pipeline(device: &GpuDevice, format: GpuTextureFormat, layouts: &[GpuBindGroupLayout]) -> Result<GpuRenderPipeline, JsValue> {
+    let module = device.create_shader_module(&GpuShaderModuleDescriptor::new(SCENE_WGSL));
+
+    // One buffer per attribute, as `Primitive` keeps them
+    let attributes = [(GpuVertexFormat::Float32x3, 12), (GpuVertexFormat::Float32x2, 8), (GpuVertexFormat::Float32x3, 12), (GpuVertexFormat::Float32x3, 12)];
+    let buffers: Vec<JsNullable<GpuVertexBufferLayout>> = attributes
+        .iter()
+        .enumerate()
+        .map(|(location, (format, stride))| {
+            JsNullable::wrap(GpuVertexBufferLayout::new(*stride, &[GpuVertexAttribute::new(*format, 0, location as u32)]))
+        })
+        .collect();
+    let vertex = GpuVertexState::new(&module);
+    vertex.set_entry_point("vs_main");
+    vertex.set_buffers(&buffers);
+
+    let fragment = GpuFragmentState::new(&module, &[JsNullable::wrap(GpuColorTargetState::new(format))]);
+    fragment.set_entry_point("fs_main");
+
+    let primitive = GpuPrimitiveState::new();
+    primitive.set_topology(GpuPrimitiveTopology::TriangleList);
+    primitive.set_cull_mode(GpuCullMode::Back);
+
+    let depth_stencil = GpuDepthStencilState::new(DEPTH_FORMAT);
+    depth_stencil.set_depth_write_enabled(true);
+    depth_stencil.set_depth_compare(GpuCompareFunction::LessEqual);
+
+    let layouts: Vec<JsNullable<GpuBindGroupLayout>> = layouts.iter().cloned().map(JsNullable::wrap).collect();
+    let descriptor = GpuRenderPipelineDescriptor::new(&device.create_pipeline_layout(&GpuPipelineLayoutDescriptor::new(&layouts)), &vertex);
+    descriptor.set_fragment(&fragment);
+    descriptor.set_primitive(&primitive);
+    descriptor.set_depth_stencil(&depth_stencil);
+
+    device.create_render_pipeline(&descriptor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+    }
+
+    #[test]
+    fn depth_is_remapped_to_the_zero_to_one_range() {
+        let projection = gpu_clip_space(&na::Perspective3::new(1.5, 45.0f32.to_radians(), 0.1, 100.0).to_homogeneous());
+        let depth = |z: f32| {
+            let clip = projection * na::Vector4::new(0.3, -0.2, z, 1.0);
+            clip.z / clip.w
+        };
+
+        assert!(depth(-0.1).abs() < 1e-5);
+        assert!((depth(-100.0) - 1.0).abs() < 1e-5);
+        assert!(depth(-10.0) > 0.0 && depth(-10.0) < 1.0);
+    }
+
+    #[test]
+    fn transforms_are_packed_at_the_stride() {
+        // Scaled by 2 and moved back, column-major
+        let mut model = [0.0; 16];
+        model[0] = 2.0;
+        model[5] = 2.0;
+        model[10] = 2.0;
+        model[14] = -5.0;
+        model[15] = 1.0;
+
+        let data = pack_node_transforms(&[model, model]);
+        assert_eq!(data.len(), 2 * NODE_TRANSFORM_STRIDE);
+
+        let second = floats(&data[NODE_TRANSFORM_STRIDE..]);
+        assert_eq!(second[..16], model);
+        // Normals shrink where the model grows, without the translation
+        assert_eq!(second[16..32], [0.5, 0.0, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!(second[32..].iter().all(|value| *value == 0.0));
+    }
+}
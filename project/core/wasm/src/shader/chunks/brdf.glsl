@@ -0,0 +1,58 @@
+// Cook-Torrance metallic-roughness BRDF, mirrored by model::brdf for CPU tests
+const float PI = 3.14159265359;
+const float MIN_ROUGHNESS = 0.045;
+// Dielectric reflectance at normal incidence
+const vec3 DIELECTRIC_F0 = vec3(0.04);
+
+// GGX / Trowbridge-Reitz normal distribution, alpha = roughness^2
+float distribution_ggx(float n_dot_h, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * d * d);
+}
+
+// Schlick-GGX masking for one direction, k remapped for analytic lights
+float geometry_schlick_ggx(float n_dot_x, float roughness)
+{
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Smith shadowing-masking
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness)
+{
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0)
+{
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Radiance reflected towards v from light arriving along l
+vec3 brdf_direct(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 albedo, float metallic, float roughness)
+{
+    roughness = clamp(roughness, MIN_ROUGHNESS, 1.0);
+    vec3 h = normalize(v + l);
+
+    float n_dot_v = max(dot(n, v), 1e-4);
+    float n_dot_l = max(dot(n, l), 0.0);
+    float n_dot_h = max(dot(n, h), 0.0);
+    float h_dot_v = max(dot(h, v), 0.0);
+
+    vec3 f0 = mix(DIELECTRIC_F0, albedo, metallic);
+    float d = distribution_ggx(n_dot_h, roughness);
+    float g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    vec3 f = fresnel_schlick(h_dot_v, f0);
+
+    vec3 specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 1e-4);
+    // Metals have no diffuse term, energy reflected specularly is not diffused
+    vec3 kd = (vec3(1.0) - f) * (1.0 - metallic);
+
+    return (kd * albedo / PI + specular) * radiance * n_dot_l;
+}
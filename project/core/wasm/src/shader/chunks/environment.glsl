@@ -0,0 +1,12 @@
+// Environment texels to linear radiance, following hdr::HdrEncoding
+vec3 decode_environment(vec4 texel)
+{
+#if defined(ENV_RGBE)
+    // Bytes r, g, b, e encode (r, g, b) * 2^(e - 136)
+    return texel.rgb * 255.0 * exp2(texel.a * 255.0 - 136.0);
+#elif defined(ENV_LINEAR)
+    return texel.rgb;
+#else
+    return pow(texel.rgb, vec3(2.2));
+#endif
+}
@@ -0,0 +1,17 @@
+// Per-frame values shared by the scene programs, from a uniform buffer on WebGL2
+// Sources that stay at ES 1.00 on WebGL2 fall back to plain uniforms
+#if defined(UNIFORM_BUFFERS) && __VERSION__ >= 300
+layout(std140) uniform Frame {
+    mat4 perspective;
+    // xyz position, or direction towards the light when w is 0
+    vec4 light_position[LIGHT_COUNT];
+    // Colour scaled by intensity
+    vec3 light_color[LIGHT_COUNT];
+    vec3 ambient_color;
+};
+#else
+uniform mat4 perspective;
+uniform vec4 light_position[LIGHT_COUNT];
+uniform vec3 light_color[LIGHT_COUNT];
+uniform vec3 ambient_color;
+#endif
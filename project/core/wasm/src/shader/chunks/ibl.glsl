@@ -0,0 +1,58 @@
+// Image-based lighting inputs, precomputed by model::ibl
+#include "brdf"
+#include "environment"
+
+// Irradiance SH9 coefficients, already convolved with the cosine lobe
+uniform vec3 sh_irradiance[9];
+// GGX-prefiltered radiance, roughness grows with the mip level
+uniform samplerCube env_specular;
+// Split-sum scale and bias, x is n.v and y is roughness
+uniform sampler2D brdf_lut;
+uniform float env_max_lod;
+
+vec3 irradiance_sh(vec3 n)
+{
+    return sh_irradiance[0] * 0.282095
+        + sh_irradiance[1] * (0.488603 * n.y)
+        + sh_irradiance[2] * (0.488603 * n.z)
+        + sh_irradiance[3] * (0.488603 * n.x)
+        + sh_irradiance[4] * (1.092548 * n.x * n.y)
+        + sh_irradiance[5] * (1.092548 * n.y * n.z)
+        + sh_irradiance[6] * (0.315392 * (3.0 * n.z * n.z - 1.0))
+        + sh_irradiance[7] * (1.092548 * n.x * n.z)
+        + sh_irradiance[8] * (0.546274 * (n.x * n.x - n.y * n.y));
+}
+
+vec3 prefiltered_radiance(vec3 r, float roughness)
+{
+    float lod = roughness * env_max_lod;
+#ifdef TEXTURE_LOD
+    return decode_environment(textureCubeLodEXT(env_specular, r, lod));
+#else
+    // Without explicit LOD the bias is only an approximation of the level
+    return decode_environment(textureCube(env_specular, r, lod));
+#endif
+}
+
+// Fresnel with a roughness-dependent horizon, for the ambient term
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness)
+{
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 ambient_ibl(vec3 n, vec3 v, vec3 albedo, float metallic, float roughness)
+{
+    roughness = clamp(roughness, MIN_ROUGHNESS, 1.0);
+    float n_dot_v = max(dot(n, v), 1e-4);
+
+    vec3 f0 = mix(DIELECTRIC_F0, albedo, metallic);
+    vec3 f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    vec3 kd = (vec3(1.0) - f) * (1.0 - metallic);
+
+    vec3 diffuse = kd * albedo * irradiance_sh(n) / PI;
+
+    vec2 env_brdf = texture2D(brdf_lut, vec2(n_dot_v, roughness)).rg;
+    vec3 specular = prefiltered_radiance(reflect(-v, n), roughness) * (f0 * env_brdf.x + env_brdf.y);
+
+    return diffuse + specular;
+}
@@ -0,0 +1,14 @@
+#include "matrix"
+
+// Matrix taking view space vectors into tangent space
+mat3 tangent_space(mat4 model_view, vec3 tang, vec3 bitang)
+{
+    vec3 norm = cross(bitang, tang);
+    mat3 norm_mtx = transpose(inverse(mat3(model_view)));
+
+    vec3 t = normalize(norm_mtx * tang);
+    vec3 b = normalize(norm_mtx * bitang);
+    vec3 n = normalize(norm_mtx * norm);
+
+    return transpose(mat3(t, b, n));
+}
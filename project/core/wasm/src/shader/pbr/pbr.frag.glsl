@@ -0,0 +1,111 @@
+#if defined(IBL) && defined(TEXTURE_LOD)
+#extension GL_EXT_shader_texture_lod : enable
+#endif
+
+precision highp float;
+
+#include "brdf"
+#ifdef IBL
+#include "ibl"
+#endif
+
+#ifdef BASE_COLOR_MAP
+uniform sampler2D tex_diffuse;
+#endif
+#ifdef METALLIC_ROUGHNESS_MAP
+// Roughness in green, metalness in blue, as in glTF
+uniform sampler2D tex_metallic_roughness;
+#endif
+#ifdef NORMAL_MAP
+uniform sampler2D tex_norm;
+#endif
+#ifdef OCCLUSION_MAP
+uniform sampler2D tex_occlusion;
+#endif
+#ifdef EMISSIVE_MAP
+uniform sampler2D tex_emissive;
+#endif
+
+uniform vec4 base_color_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+uniform vec3 emissive_factor;
+uniform float occlusion_strength;
+
+#include "frame"
+
+// Zero (unset) values keep the output opaque
+uniform float alpha_cutoff;
+uniform float alpha_blend;
+
+varying vec2 frag_uv;
+varying vec3 view_pos;
+varying vec3 view_tang;
+varying vec3 view_bitang;
+varying vec3 view_norm;
+
+vec3 srgb_to_linear(vec3 color)
+{
+    return pow(color, vec3(2.2));
+}
+
+void main(void)
+{
+    vec4 base_color = base_color_factor;
+#ifdef BASE_COLOR_MAP
+    vec4 base_sample = texture2D(tex_diffuse, frag_uv);
+    base_color *= vec4(srgb_to_linear(base_sample.rgb), base_sample.a);
+#endif
+    if (base_color.a < alpha_cutoff) {
+        discard;
+    }
+
+    float metallic = metallic_factor;
+    float roughness = roughness_factor;
+#ifdef METALLIC_ROUGHNESS_MAP
+    vec4 mr = texture2D(tex_metallic_roughness, frag_uv);
+    roughness *= mr.g;
+    metallic *= mr.b;
+#endif
+
+    vec3 n = normalize(view_norm);
+#ifdef NORMAL_MAP
+    mat3 tbn = mat3(normalize(view_tang), normalize(view_bitang), n);
+    n = normalize(tbn * (texture2D(tex_norm, frag_uv).rgb * 2.0 - 1.0));
+#endif
+    vec3 v = normalize(-view_pos);
+
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < LIGHT_COUNT; i++) {
+        vec4 light = light_position[i];
+        vec3 l = light.xyz - view_pos * light.w;
+        // Inverse-square falloff for point lights only
+        float attenuation = mix(1.0, 1.0 / max(dot(l, l), 1e-4), light.w);
+
+        color += brdf_direct(n, v, normalize(l), light_color[i] * attenuation, base_color.rgb, metallic, roughness);
+    }
+
+    float occlusion = 1.0;
+#ifdef OCCLUSION_MAP
+    occlusion = mix(1.0, texture2D(tex_occlusion, frag_uv).r, occlusion_strength);
+#endif
+#ifdef IBL
+    color += ambient_ibl(n, v, base_color.rgb, metallic, roughness) * occlusion;
+#else
+    color += ambient_color * base_color.rgb * occlusion;
+#endif
+
+    vec3 emissive = emissive_factor;
+#ifdef EMISSIVE_MAP
+    emissive *= srgb_to_linear(texture2D(tex_emissive, frag_uv).rgb);
+#endif
+    color += emissive;
+
+#ifndef LINEAR_OUTPUT
+    // Reinhard and gamma, skipped when the post-processing chain tone maps
+    color = color / (color + vec3(1.0));
+    color = pow(color, vec3(1.0 / 2.2));
+#endif
+
+    gl_FragColor = vec4(color, mix(1.0, base_color.a, alpha_blend));
+}
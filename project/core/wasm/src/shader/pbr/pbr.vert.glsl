@@ -0,0 +1,40 @@
+precision highp float;
+
+#include "matrix"
+#include "frame"
+
+attribute vec3 vert_pos;
+attribute vec3 vert_tang;
+attribute vec3 vert_bitang;
+attribute vec2 vert_uv;
+
+#ifdef INSTANCING
+// One draw covers every node sharing the mesh
+attribute mat4 instance_model;
+#else
+uniform mat4 model_view;
+#endif
+
+varying vec2 frag_uv;
+// View space values, the camera is always at the origin
+varying vec3 view_pos;
+varying vec3 view_tang;
+varying vec3 view_bitang;
+varying vec3 view_norm;
+
+void main(void)
+{
+#ifdef INSTANCING
+    mat4 model_view = instance_model;
+#endif
+    vec4 pos = model_view * vec4(vert_pos, 1.0);
+    gl_Position = perspective * pos;
+    view_pos = pos.xyz;
+
+    mat3 norm_mtx = transpose(inverse(mat3(model_view)));
+    view_tang = normalize(norm_mtx * vert_tang);
+    view_bitang = normalize(norm_mtx * vert_bitang);
+    view_norm = normalize(norm_mtx * cross(vert_bitang, vert_tang));
+
+    frag_uv = vert_uv;
+}
@@ -0,0 +1,9 @@
+precision highp float;
+
+// Node id encoded into RGBA8 on the CPU side
+uniform vec4 id_color;
+
+void main(void)
+{
+    gl_FragColor = id_color;
+}
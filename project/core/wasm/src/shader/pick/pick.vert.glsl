@@ -0,0 +1,11 @@
+precision highp float;
+
+attribute vec3 vert_pos;
+
+uniform mat4 model_view;
+uniform mat4 perspective;
+
+void main(void)
+{
+    gl_Position = perspective * model_view * vec4(vert_pos, 1.0);
+}
@@ -0,0 +1,25 @@
+precision highp float;
+
+uniform sampler2D tex_input;
+uniform vec2 resolution;
+uniform float radius;
+
+varying vec2 frag_uv;
+
+void main(void)
+{
+    vec2 texel = radius / resolution;
+
+    // 3x3 gaussian kernel (1 2 1 / 2 4 2 / 1 2 1) / 16
+    vec4 color = texture2D(tex_input, frag_uv) * 4.0;
+    color += texture2D(tex_input, frag_uv + vec2(texel.x, 0.0)) * 2.0;
+    color += texture2D(tex_input, frag_uv - vec2(texel.x, 0.0)) * 2.0;
+    color += texture2D(tex_input, frag_uv + vec2(0.0, texel.y)) * 2.0;
+    color += texture2D(tex_input, frag_uv - vec2(0.0, texel.y)) * 2.0;
+    color += texture2D(tex_input, frag_uv + texel);
+    color += texture2D(tex_input, frag_uv - texel);
+    color += texture2D(tex_input, frag_uv + vec2(texel.x, -texel.y));
+    color += texture2D(tex_input, frag_uv + vec2(-texel.x, texel.y));
+
+    gl_FragColor = color / 16.0;
+}
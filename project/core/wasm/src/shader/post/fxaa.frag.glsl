@@ -0,0 +1,52 @@
+precision highp float;
+
+uniform sampler2D tex_input;
+uniform vec2 resolution;
+uniform float span_max;
+
+varying vec2 frag_uv;
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+
+// Simplified FXAA (Timothy Lottes), luma based edge detection
+void main(void)
+{
+    vec2 texel = 1.0 / resolution;
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    float luma_nw = dot(texture2D(tex_input, frag_uv + vec2(-1.0, -1.0) * texel).rgb, luma);
+    float luma_ne = dot(texture2D(tex_input, frag_uv + vec2(1.0, -1.0) * texel).rgb, luma);
+    float luma_sw = dot(texture2D(tex_input, frag_uv + vec2(-1.0, 1.0) * texel).rgb, luma);
+    float luma_se = dot(texture2D(tex_input, frag_uv + vec2(1.0, 1.0) * texel).rgb, luma);
+    vec4 color_m = texture2D(tex_input, frag_uv);
+    float luma_m = dot(color_m.rgb, luma);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-span_max), vec2(span_max)) * texel;
+
+    vec3 rgb_a = 0.5 * (
+        texture2D(tex_input, frag_uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture2D(tex_input, frag_uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+        texture2D(tex_input, frag_uv + dir * -0.5).rgb +
+        texture2D(tex_input, frag_uv + dir * 0.5).rgb
+    );
+
+    float luma_b = dot(rgb_b, luma);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        gl_FragColor = vec4(rgb_a, color_m.a);
+    } else {
+        gl_FragColor = vec4(rgb_b, color_m.a);
+    }
+}
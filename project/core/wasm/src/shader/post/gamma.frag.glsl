@@ -0,0 +1,12 @@
+precision highp float;
+
+uniform sampler2D tex_input;
+uniform float gamma;
+
+varying vec2 frag_uv;
+
+void main(void)
+{
+    vec4 color = texture2D(tex_input, frag_uv);
+    gl_FragColor = vec4(pow(color.rgb, vec3(1.0 / gamma)), color.a);
+}
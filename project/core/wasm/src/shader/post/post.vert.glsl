@@ -0,0 +1,11 @@
+precision highp float;
+
+attribute vec2 vert_pos;
+
+varying vec2 frag_uv;
+
+void main(void)
+{
+    frag_uv = vert_pos * 0.5 + 0.5;
+    gl_Position = vec4(vert_pos, 0.0, 1.0);
+}
@@ -0,0 +1,17 @@
+precision highp float;
+
+uniform sampler2D tex_input;
+uniform float exposure;
+
+varying vec2 frag_uv;
+
+void main(void)
+{
+    vec4 color = texture2D(tex_input, frag_uv);
+
+    // Reinhard operator applied to the exposed colour
+    vec3 mapped = color.rgb * exposure;
+    mapped = mapped / (mapped + vec3(1.0));
+
+    gl_FragColor = vec4(mapped, color.a);
+}
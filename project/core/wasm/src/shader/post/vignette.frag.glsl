@@ -0,0 +1,17 @@
+precision highp float;
+
+uniform sampler2D tex_input;
+uniform float strength;
+uniform float radius;
+
+varying vec2 frag_uv;
+
+void main(void)
+{
+    vec4 color = texture2D(tex_input, frag_uv);
+
+    float dist = distance(frag_uv, vec2(0.5));
+    float falloff = 1.0 - smoothstep(radius - 0.25, radius, dist);
+
+    gl_FragColor = vec4(color.rgb * mix(1.0 - strength, 1.0, falloff), color.a);
+}
@@ -0,0 +1,22 @@
+precision highp float;
+
+#include "environment"
+
+uniform samplerCube env_specular;
+
+varying vec3 view_dir;
+
+void main(void)
+{
+    vec3 color = decode_environment(textureCube(env_specular, normalize(view_dir)));
+
+#ifndef LINEAR_OUTPUT
+#if defined(ENV_RGBE) || defined(ENV_LINEAR)
+    // HDR skies get the same Reinhard curve as the scene
+    color = color / (color + vec3(1.0));
+#endif
+    color = pow(color, vec3(1.0 / 2.2));
+#endif
+
+    gl_FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,16 @@
+precision highp float;
+
+attribute vec2 vert_pos;
+
+uniform mat4 inv_perspective;
+
+varying vec3 view_dir;
+
+void main(void)
+{
+    // The camera never rotates, so view space directions index the environment directly
+    vec4 view = inv_perspective * vec4(vert_pos, 1.0, 1.0);
+    view_dir = view.xyz / view.w;
+
+    gl_Position = vec4(vert_pos, 1.0, 1.0);
+}
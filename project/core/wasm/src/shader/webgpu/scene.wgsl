@@ -0,0 +1,66 @@
+// Port of obj.vert.glsl and obj.frag.glsl, the camera is always at the origin
+
+struct Frame {
+    perspective: mat4x4<f32>,
+};
+
+struct NodeTransform {
+    model_view: mat4x4<f32>,
+    // Inverse transpose of model_view, WGSL has no inverse()
+    norm_mtx: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> frame: Frame;
+@group(0) @binding(1) var tex_sampler: sampler;
+@group(0) @binding(2) var tex_diffuse: texture_2d<f32>;
+@group(0) @binding(3) var tex_norm: texture_2d<f32>;
+// Bound with a dynamic offset per node
+@group(1) @binding(0) var<uniform> node: NodeTransform;
+
+const LIGHT_POS: vec3<f32> = vec3<f32>(1.0, 2.0, 0.0);
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) frag_uv: vec2<f32>,
+    @location(1) view_pos: vec3<f32>,
+    @location(2) view_tang: vec3<f32>,
+    @location(3) view_bitang: vec3<f32>,
+    @location(4) view_norm: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) vert_pos: vec3<f32>,
+    @location(1) vert_uv: vec2<f32>,
+    @location(2) vert_tang: vec3<f32>,
+    @location(3) vert_bitang: vec3<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let pos = node.model_view * vec4<f32>(vert_pos, 1.0);
+    out.position = frame.perspective * pos;
+    out.view_pos = pos.xyz;
+
+    let norm_mtx = mat3x3<f32>(node.norm_mtx[0].xyz, node.norm_mtx[1].xyz, node.norm_mtx[2].xyz);
+    out.view_tang = normalize(norm_mtx * vert_tang);
+    out.view_bitang = normalize(norm_mtx * vert_bitang);
+    out.view_norm = normalize(norm_mtx * cross(vert_bitang, vert_tang));
+
+    out.frag_uv = vert_uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let tbn = mat3x3<f32>(normalize(in.view_tang), normalize(in.view_bitang), normalize(in.view_norm));
+    let light_dir = normalize(LIGHT_POS - in.view_pos);
+
+    let albedo = textureSample(tex_diffuse, tex_sampler, in.frag_uv).rgb;
+    let ambient = 0.7 * albedo;
+
+    // Normal mapping, done in view space instead of moving the light into tangent space
+    let norm = normalize(tbn * (textureSample(tex_norm, tex_sampler, in.frag_uv).rgb * 2.0 - 1.0));
+    let diffuse = max(dot(light_dir, norm), 0.0);
+
+    return vec4<f32>(diffuse * albedo + ambient, 1.0);
+}
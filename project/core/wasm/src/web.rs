@@ -0,0 +1,1115 @@
+//! Browser entry points, the wasm-bindgen exports creating and drawing the WebGL and WebGPU scenes
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use nalgebra as na;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+use crate::model::{self, *};
+use crate::model::common::obj_file_data_to_vertex_vector_data;
+use crate::model::material::PbrParams;
+use crate::model::hdr::{self, HdrImage};
+use crate::model::texture::HdrFormat;
+use crate::model::image::{DecodeTiming, ImageFormat};
+use crate::model::js_import::TextureData;
+use crate::model::ktx::{self, CompressionSupport, Variant};
+use crate::model::atlas::{AtlasPacker, MaterialAtlas};
+use crate::model::texture::DomImageSource;
+use crate::model::video::VideoTexture;
+use crate::model::context_loss::{ContextLossMonitor, FrameAction};
+use crate::model::program_cache;
+use crate::model::backend::{self, BackendKind};
+use crate::model::frame_uniforms::{self, FrameBuffer};
+use crate::model::scene;
+use crate::model::webgpu::WebGpuRenderer;
+use crate::model::device::WebGlDevice;
+use std::rc::Rc;
+
+use wasm_bindgen::__rt::WasmRefCell;
+use rand::Rng;
+use crate::utils;
+
+#[wasm_bindgen]
+extern {
+    fn alert(s: &str);
+}
+
+#[wasm_bindgen]
+pub fn greet() {
+    alert("Hello, md!");
+}
+
+#[wasm_bindgen]
+extern "C" {
+    // Use `js_namespace` here to bind `console.log(..)` instead of just
+    // `log(..)`
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+#[export_name = "get_info"]
+#[allow(non_snake_case, improper_ctypes_definitions)]
+pub extern "C" fn __wasm_bindgen_generated_Context_get_info(me: u32) -> SceneInfo {
+    let me = me as *mut WasmRefCell<Context>;
+    wasm_bindgen::__rt::assert_not_null(me);
+    let me = unsafe { &*me };
+    return me.borrow().get_info();
+}
+
+// Wrap web-sys console log function in a println! style macro
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        log(&format!( $( $t )* ));
+    }
+}
+
+#[wasm_bindgen]
+pub struct SceneInit {
+    canvas_id: String,
+    obj_file_data: String,
+    shaders: JsShaders,
+    textures_images: Vec<JsTextureImage>,
+    render_params: JsRenderParams,
+}
+
+#[wasm_bindgen]
+impl SceneInit {
+    #[allow(deprecated)]
+    pub fn new(
+        canvas_id: &str,
+        obj_file_data: &str,
+        js_shaders: &JsValue,
+        js_textures_images: &JsValue,
+        js_render_params: &JsValue
+    ) -> Self {
+        // console_error_panic_hook::set_once();
+        // log(format!("{:?}", render_params).as_ref());
+
+        let shaders: JsShaders = js_shaders.into_serde().unwrap();
+        let textures_images: Vec<JsTextureImage> = js_sys::Array::from(js_textures_images)
+            .iter()
+            .map(|value| JsTextureImage::from_js(&value))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let render_params: JsRenderParams = js_render_params.into_serde().unwrap();
+
+        Self { canvas_id: canvas_id.to_string(), obj_file_data: obj_file_data.to_string(), shaders, textures_images, render_params }
+    }
+}
+
+#[wasm_bindgen]
+pub struct SceneInfo {
+    triangles: i32,
+    vertex_count: i32,
+    culled: i32,
+    drawn: i32,
+    draw_calls: i32,
+    state_changes: i32,
+    programs_compiled: i32,
+    programs_shared: i32,
+    shader_build_ms: f64,
+    texture_decode_ms: f64,
+    texture_bytes: f64,
+    upload_bytes: f64,
+    total_upload_bytes: f64,
+    context_losses: u32
+}
+
+#[wasm_bindgen]
+impl SceneInfo {
+
+    pub fn new(triangles: i32, vertex_count: i32) -> Self {
+        Self {
+            triangles,
+            vertex_count,
+            culled: 0,
+            drawn: 0,
+            draw_calls: 0,
+            state_changes: 0,
+            programs_compiled: 0,
+            programs_shared: 0,
+            shader_build_ms: 0.0,
+            texture_decode_ms: 0.0,
+            texture_bytes: 0.0,
+            upload_bytes: 0.0,
+            total_upload_bytes: 0.0,
+            context_losses: 0
+        }
+    }
+
+    pub fn get_triangles(&self) -> i32 {
+        self.triangles
+    }
+
+    pub fn get_vertex_count(&self) -> i32 {
+        self.vertex_count
+    }
+
+    /// Nodes skipped by frustum culling in the last frame
+    pub fn get_culled(&self) -> i32 {
+        self.culled
+    }
+
+    /// Nodes drawn in the last frame
+    pub fn get_drawn(&self) -> i32 {
+        self.drawn
+    }
+
+    /// Draw calls issued by the render queue in the last frame, instancing draws nodes sharing a mesh at once
+    pub fn get_draw_calls(&self) -> i32 {
+        self.draw_calls
+    }
+
+    /// GL state changes issued by the render queue in the last frame
+    pub fn get_state_changes(&self) -> i32 {
+        self.state_changes
+    }
+
+    /// Programs compiled by this context
+    pub fn get_programs_compiled(&self) -> i32 {
+        self.programs_compiled
+    }
+
+    /// Programs reused from another context on the same canvas
+    pub fn get_programs_shared(&self) -> i32 {
+        self.programs_shared
+    }
+
+    /// Total shader compile and link time in milliseconds
+    pub fn get_shader_build_ms(&self) -> f64 {
+        self.shader_build_ms
+    }
+
+    /// Total texture decoding time in milliseconds, spent in wasm during initialization
+    pub fn get_texture_decode_ms(&self) -> f64 {
+        self.texture_decode_ms
+    }
+
+    /// Estimated GPU memory of all textures in bytes, environment maps included
+    pub fn get_texture_bytes(&self) -> f64 {
+        self.texture_bytes
+    }
+
+    /// Texture bytes streamed for the last frame, video frames and region updates since the frame before
+    pub fn get_upload_bytes(&self) -> f64 {
+        self.upload_bytes
+    }
+
+    /// Texture bytes streamed since the context was created
+    pub fn get_total_upload_bytes(&self) -> f64 {
+        self.total_upload_bytes
+    }
+
+    /// WebGL context losses since the context was created
+    pub fn get_context_losses(&self) -> u32 {
+        self.context_losses
+    }
+
+}
+
+/// Index of the pipeline built from the scene shaders
+const SCENE_PIPELINE: usize = 0;
+
+#[wasm_bindgen]
+pub struct Context {
+    canvas: HtmlCanvasElement,
+    obj: Geometry,
+    gl: WebGlRenderingContext,
+    /// Per-frame uniforms of the scene programs, WebGL2 only
+    frame_buffer: Option<FrameBuffer>,
+    /// Nodes with their materials and pipelines, `SCENE_PIPELINE` is built from the scene shaders
+    /// and materials selecting another shading model add a pipeline of their own
+    scene: Scene<WebGlDevice>,
+    lights: Vec<Light>,
+    ambient: [f32; 3],
+    environment: Option<Environment>,
+    /// Scene texture ids and their index in the scene textures
+    texture_ids: Vec<(String, usize)>,
+    videos: Vec<VideoTexture>,
+    /// Region updates since the last frame
+    pending_upload_bytes: usize,
+    upload_bytes: usize,
+    total_upload_bytes: usize,
+    triangles: i32,
+    vertex_count: i32,
+    /// Scene inputs, kept to create every GPU resource again after a context loss
+    init: Rc<SceneInit>,
+    loss: Rc<ContextLossMonitor>,
+    post_process: Option<PostProcessChain>,
+    picker: Option<Picker>,
+    custom_uniforms: Vec<(String, UniformValue)>,
+    shaders: JsShaders,
+    shader_variants: ShaderVariants,
+    features: ShaderFeatures,
+    decode_timings: Vec<DecodeTiming>,
+    next_node_id: u32
+}
+
+#[wasm_bindgen]
+impl Context {
+
+    pub fn new(scene_init: SceneInit) -> Result<Context, JsValue> {
+        let canvas = get_canvas(scene_init.canvas_id.as_ref())?;
+        let kind = match scene_init.render_params.backend.as_deref() {
+            Some(name) => BackendKind::from_name(name).ok_or_else(|| format!("Unknown backend: {}", name))?,
+            None => BackendKind::WebGl1,
+        };
+        if kind == BackendKind::WebGpu {
+            return Err("The WebGPU backend is created with GpuContext.create".into());
+        }
+        let gl = backend::create_context(&canvas, kind)?;
+        let loss = Rc::new(ContextLossMonitor::new(&canvas, &gl)?);
+
+        Self::build(Rc::new(scene_init), canvas, gl, loss)
+    }
+
+    /// Creates the GPU side of the scene, also used to start over after a context loss
+    fn build(scene_init: Rc<SceneInit>, canvas: HtmlCanvasElement, gl: GL, loss: Rc<ContextLossMonitor>) -> Result<Context, JsValue> {
+        let device = WebGlDevice::new(gl.clone());
+
+        let obj = load_obj(&scene_init);
+        let triangles = obj.triangles;
+        let vertex_count = obj.vertex_count;
+
+        let environment_ids = scene_init.render_params.environment.as_ref().map_or(vec![], |env| env.image_ids());
+        let scene_images: Vec<&JsTextureImage> = scene_init.textures_images
+            .iter()
+            .filter(|jti| !environment_ids.contains(&jti.id.as_str()))
+            .collect();
+        let texture_keys: Vec<&str> = scene_images.iter().map(|jti| jti.id.as_str()).collect();
+
+        let render_params = &scene_init.render_params;
+
+        let mut lights = render_params.lights.iter().map(Light::from_js).collect::<Result<Vec<_>, _>>()?;
+        if lights.is_empty() {
+            lights.push(Light::default());
+        }
+
+        // Float storage only pays off for HDR sources, 8-bit ones keep sRGB cube maps
+        let env_format = render_params.environment
+            .as_ref()
+            .filter(|env| environment::has_hdr_images(env, &scene_init.textures_images))
+            .map(|_| HdrFormat::detect(&device));
+
+        let gl2 = device.webgl2().cloned();
+        let features = ShaderFeatures {
+            normal_map: texture_keys.contains(&"tex_norm"),
+            base_color_map: texture_keys.contains(&"tex_diffuse"),
+            metallic_roughness_map: texture_keys.contains(&"tex_metallic_roughness"),
+            occlusion_map: texture_keys.contains(&"tex_occlusion"),
+            emissive_map: texture_keys.contains(&"tex_emissive"),
+            ibl: render_params.environment.is_some(),
+            // Core in GLSL ES 3.00
+            texture_lod: render_params.environment.is_some()
+                && (gl2.is_some() || gl.get_extension("EXT_shader_texture_lod")?.is_some()),
+            env_encoding: env_format.map(|format| format.encoding),
+            shadows: render_params.shadows,
+            instancing: gl2.is_some(),
+            uniform_buffers: gl2.is_some(),
+            light_count: light::light_count(render_params.light_count, &lights)?,
+        };
+
+        let pbr = is_pbr_shading(render_params.shading.as_deref())?;
+        let shaders = shading_shaders(&scene_init, pbr);
+
+        // Shading model of every pipeline, materials only add the ones they select
+        let mut pipeline_pbr = vec![pbr];
+        let mut material_pipelines = vec![];
+        for js in render_params.materials.iter() {
+            let material_pbr = match js.shading.as_deref() {
+                Some(shading) => is_pbr_shading(Some(shading))?,
+                None => pbr,
+            };
+            let pipeline = match pipeline_pbr.iter().position(|pipeline| *pipeline == material_pbr) {
+                Some(pipeline) => pipeline,
+                None => {
+                    pipeline_pbr.push(material_pbr);
+                    pipeline_pbr.len() - 1
+                }
+            };
+            material_pipelines.push(pipeline);
+        }
+
+        let mut shader_variants = ShaderVariants::new(device.clone());
+        for (name, source) in shaders.chunks.iter() {
+            shader_variants.chunks.register(name, source);
+        }
+
+        let mut pipelines = vec![create_default_program(&mut shader_variants, &shaders, &features)?];
+        for pbr in pipeline_pbr[1..].iter() {
+            pipelines.push(create_default_program(&mut shader_variants, &shading_shaders(&scene_init, *pbr), &features)?);
+        }
+
+        let alpha_mode = match scene_init.render_params.alpha_mode.as_ref() {
+            Some(name) => AlphaMode::from_name(name, scene_init.render_params.alpha_cutoff)
+                .ok_or_else(|| format!("Unknown alpha mode: {}", name))?,
+            None => AlphaMode::Opaque,
+        };
+
+        let mut materials: Vec<Material> = if scene_init.render_params.materials.is_empty() {
+            let mut material = Material::new("default", SCENE_PIPELINE, &texture_keys);
+            material.render_state.alpha_mode = alpha_mode;
+            vec![material]
+        } else {
+            scene_init.render_params.materials
+                .iter()
+                .zip(material_pipelines.iter())
+                .map(|(js, pipeline)| Material::from_js(js, *pipeline, &texture_keys))
+                .collect::<Result<_, _>>()?
+        };
+
+        for material in materials.iter_mut().filter(|material| pipeline_pbr[material.pipeline] && material.pbr.is_none()) {
+            let mut params = PbrParams::default();
+            // Without a map the metallic factor alone would turn everything into metal
+            if !features.metallic_roughness_map {
+                params.metallic = 0.0;
+            }
+            material.pbr = Some(params);
+        }
+
+        for material in materials.iter() {
+            for warning in material.validate(&pipelines[material.pipeline])? {
+                log!("Warning: {}", warning);
+            }
+        }
+
+        let post_process = if scene_init.render_params.post_process.is_empty() {
+            None
+        } else {
+            Some(PostProcessChain::new(&mut shader_variants, &scene_init.render_params.post_process, canvas.width(), canvas.height())?)
+        };
+
+        let mut textures = vec![];
+        let mut decode_timings = vec![];
+        let mut videos = vec![];
+        // Also enables the extensions, compressed formats can't be uploaded before that
+        let compression = CompressionSupport::detect(&gl);
+
+        for (i, jti) in scene_images.iter().enumerate() {
+            let texture_num = GL::TEXTURE0 + i as u32;
+            let start = common::now_ms();
+
+            let variants: Vec<&[u8]> = match &jti.data {
+                TextureData::Encoded(data) => vec![data.as_ref()],
+                TextureData::Variants(variants) => variants.iter().map(|data| data.as_ref()).collect(),
+                TextureData::Dom(source) => {
+                    // Browsers may decode <img> lazily, so the upload carries the decoding cost
+                    let texture = Texture::from_dom(device.clone(), source, texture_num)
+                        .map_err(|msg| format!("Texture '{}': {}", jti.id, msg))?;
+                    decode_timings.push(DecodeTiming {
+                        id: jti.id.clone(),
+                        format: None,
+                        width: texture.width,
+                        height: texture.height,
+                        decode_ms: common::now_ms() - start,
+                    });
+                    if let DomImageSource::Video(video) = source {
+                        videos.push(VideoTexture::new(video.clone(), i, true));
+                    }
+                    textures.push(LoadedTexture::Uploaded(texture));
+                    continue;
+                }
+            };
+
+            let data = match ktx::select_variant(&variants, &compression).map_err(|msg| format!("Texture '{}': {}", jti.id, msg))? {
+                Variant::Compressed(ktx) => {
+                    let texture = Texture::from_compressed(device.clone(), &ktx, texture_num)
+                        .map_err(|msg| format!("Texture '{}': {}", jti.id, msg))?;
+                    textures.push(LoadedTexture::Uploaded(texture));
+                    continue;
+                }
+                Variant::Encoded(data) => data,
+            };
+
+            if hdr::is_hdr(data) {
+                let image = HdrImage::decode(data).map_err(|msg| format!("Texture '{}': {}", jti.id, msg))?;
+                decode_timings.push(DecodeTiming {
+                    id: jti.id.clone(),
+                    format: ImageFormat::detect(data),
+                    width: image.width,
+                    height: image.height,
+                    decode_ms: common::now_ms() - start,
+                });
+                textures.push(LoadedTexture::Uploaded(Texture::from_hdr(device.clone(), &image, texture_num, HdrFormat::detect(&device))?));
+                continue;
+            }
+
+            let image = Image::decode(data).map_err(|msg| format!("Texture '{}': {}", jti.id, msg))?;
+            decode_timings.push(DecodeTiming {
+                id: jti.id.clone(),
+                format: ImageFormat::detect(data),
+                width: image.width,
+                height: image.height,
+                decode_ms: common::now_ms() - start,
+            });
+
+            textures.push(LoadedTexture::Decoded(image));
+        }
+
+        let material_geometries = match scene_init.render_params.atlas.as_ref() {
+            Some(js_atlas) => {
+                let packer = AtlasPacker { page_size: js_atlas.page_size, padding: js_atlas.padding, gutter: js_atlas.gutter };
+                pack_atlas(&packer, &obj, &mut materials, &mut textures)?
+            }
+            None => vec![None; materials.len()],
+        };
+        let (textures, indices) = upload_textures(&device, textures, &mut materials);
+        for video in videos.iter_mut() {
+            video.texture = indices[video.texture].expect("Uploaded textures are kept");
+        }
+        let texture_ids = scene_images
+            .iter()
+            .zip(indices.iter())
+            .filter_map(|(jti, index)| index.map(|index| (jti.id.clone(), index)))
+            .collect();
+
+        // One mesh per material, materials packed into the atlas get UVs remapped
+        let obj_primitive = Rc::new(Primitive::new(device.clone(), &obj));
+        let primitives: Vec<Rc<Primitive<WebGlDevice>>> = material_geometries
+            .iter()
+            .map(|geometry| match geometry {
+                Some(geometry) => Rc::new(Primitive::new(device.clone(), geometry)),
+                None => obj_primitive.clone(),
+            })
+            .collect();
+
+        let mut nodes = vec![];
+
+        let mut obj_node = model::Node::with_bounds(0, obj.bounds);
+        obj_node.id = 0;
+        let init_pos = scene_init.render_params.init_pos.as_ref();
+        obj_node.set_x_y_z(init_pos[0], init_pos[1], init_pos[2]);
+        nodes.push(obj_node);
+
+        let environment = match scene_init.render_params.environment.as_ref() {
+            Some(js_environment) => {
+                let max_slots = (environment::ENV_SPECULAR_UNIT - GL::TEXTURE0) as usize;
+                if let Some(material) = materials.iter().find(|material| material.textures.len() > max_slots) {
+                    return Err(format!(
+                        "Material '{}' uses {} textures, at most {} are available next to the environment",
+                        material.name, material.textures.len(), max_slots
+                    ).into());
+                }
+
+                let cube = environment::load_cube(js_environment, &scene_init.textures_images)?;
+                let mut skybox_defines = features.to_defines();
+                skybox_defines.extend(shaders.defines.iter().map(|(name, value)| (name.clone(), value.clone())));
+
+                let skybox = if js_environment.skybox { Some((&mut shader_variants, &skybox_defines)) } else { None };
+                Some(Environment::new(device.clone(), &cube, env_format, skybox)?)
+            }
+            None => None,
+        };
+
+        // Set graphics state, capabilities are set per material
+        gl.depth_func(GL::LEQUAL);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+
+        let mut scene = Scene::new(GlState::new(device.clone()), pipelines, materials, textures, primitives);
+        scene.nodes = nodes;
+
+        let ret = Context {
+            canvas,
+            obj,
+            gl: gl.clone(),
+            frame_buffer: gl2.map(FrameBuffer::new),
+            scene,
+            lights,
+            ambient: scene_init.render_params.ambient.unwrap_or([0.03, 0.03, 0.03]),
+            environment,
+            texture_ids,
+            videos,
+            pending_upload_bytes: 0,
+            upload_bytes: 0,
+            total_upload_bytes: 0,
+            triangles,
+            vertex_count,
+            init: scene_init.clone(),
+            loss,
+            post_process,
+            picker: None,
+            custom_uniforms: vec![],
+            shaders,
+            shader_variants,
+            features,
+            decode_timings,
+            next_node_id: 1
+        };
+
+        Ok(ret)
+    }
+
+    pub fn get_info(&self) -> SceneInfo {
+        let timings = &self.shader_variants.timings;
+        SceneInfo {
+            triangles: self.triangles,
+            vertex_count: self.vertex_count,
+            culled: self.scene.culled,
+            drawn: self.scene.drawn,
+            draw_calls: self.scene.draw_calls,
+            state_changes: self.scene.state.state_changes,
+            programs_compiled: timings.iter().filter(|timing| !timing.shared).count() as i32,
+            programs_shared: timings.iter().filter(|timing| timing.shared).count() as i32,
+            shader_build_ms: self.shader_variants.build_time_ms(),
+            texture_decode_ms: self.decode_timings.iter().map(|timing| timing.decode_ms).sum(),
+            texture_bytes: self.texture_bytes() as f64,
+            upload_bytes: self.upload_bytes as f64,
+            total_upload_bytes: self.total_upload_bytes as f64,
+            context_losses: self.loss.losses()
+        }
+    }
+
+    fn texture_bytes(&self) -> usize {
+        let environment = self.environment.as_ref().map_or(0, |env| env.specular.gpu_bytes + env.brdf_lut.gpu_bytes);
+        self.scene.textures.iter().map(|texture| texture.gpu_bytes).sum::<usize>() + environment
+    }
+
+    /// Decoding time of every scene texture as `[{id, format, width, height, decode_ms}]`,
+    /// `format` is null for browser-decoded sources, timed by their upload
+    #[allow(deprecated)]
+    pub fn get_decode_timings(&self) -> Result<JsValue, JsValue> {
+        JsValue::from_serde(&self.decode_timings)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Compile and link time of every program variant as `[{name, id, compile_ms, link_ms, shared}]`,
+    /// including the skybox, post-process and picking passes
+    #[allow(deprecated)]
+    pub fn get_program_timings(&self) -> Result<JsValue, JsValue> {
+        JsValue::from_serde(&self.shader_variants.timings)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn get_context(&self) -> WebGlRenderingContext {
+        self.gl.to_owned()
+    }
+
+    /// "webgl2" or "webgl1", the one actually created when WebGL2 was requested but is missing
+    pub fn get_backend(&self) -> String {
+        BackendKind::of(&self.gl).name().to_string()
+    }
+
+    /// Active uniforms and attributes of the scene program as `{uniforms, attributes}`
+    #[allow(deprecated)]
+    pub fn get_reflection(&self) -> Result<JsValue, JsValue> {
+        JsValue::from_serde(&self.scene.pipelines[SCENE_PIPELINE].program.reflection)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Replaces a rectangle of a scene texture with tightly packed RGB or RGBA bytes, matching the texture
+    pub fn update_texture_region(&mut self, id: &str, x: u32, y: u32, width: u32, height: u32, data: &[u8]) -> Result<(), JsValue> {
+        let index = self.texture_ids
+            .iter()
+            .find(|(texture_id, _)| texture_id == id)
+            .map(|(_, index)| *index)
+            .ok_or_else(|| format!("Unknown texture '{}', atlas-packed textures can't be updated", id))?;
+
+        let texture = &self.scene.textures[index];
+        self.scene.state.bind_texture(GL::TEXTURE0, texture.target, &texture.handle);
+        let bytes = texture.update_region(x, y, width, height, data).map_err(|msg| format!("Texture '{}': {}", id, msg))?;
+        self.pending_upload_bytes += bytes;
+
+        Ok(())
+    }
+
+    /// Loses the WebGL context through `WEBGL_lose_context`, to test the restore path
+    pub fn lose_context(&self) -> Result<(), JsValue> {
+        Ok(self.loss.lose_context()?)
+    }
+
+    /// Restores a context lost through `lose_context`, the scene is rebuilt on the next draw
+    pub fn restore_context(&self) -> Result<(), JsValue> {
+        Ok(self.loss.restore_context()?)
+    }
+
+    /// Draws the scene, nothing is drawn while the context is lost
+    pub fn draw(&mut self, t: f32) -> Result<(), JsValue> {
+        match self.loss.next_frame() {
+            FrameAction::Skip => return Ok(()),
+            FrameAction::Restore => self.restore()?,
+            FrameAction::Draw => {}
+        }
+
+        let width = self.canvas.width();
+        let height = self.canvas.height();
+
+        // Scene goes into the offscreen target when post-processing is enabled
+        match self.post_process.as_mut() {
+            Some(chain) => {
+                chain.resize(width, height);
+                chain.begin();
+            }
+            None => RenderTarget::bind_default(&self.gl, width, height),
+        }
+
+        self.scene.state.begin_frame();
+
+        // New video frames go up before anything samples them
+        let mut upload_bytes = std::mem::take(&mut self.pending_upload_bytes);
+        for video in self.videos.iter_mut() {
+            upload_bytes += video.update(&mut self.scene.textures[video.texture], &mut self.scene.state)?;
+        }
+        self.upload_bytes = upload_bytes;
+        self.total_upload_bytes += upload_bytes;
+
+        // Perspective
+        let perspective = self.perspective();
+        let (light_positions, light_colors) = light::light_uniforms(&self.lights, self.features.light_count as usize);
+        // Programs with a `Frame` block read these from the buffer and ignore the uniforms below
+        if let Some(frame_buffer) = self.frame_buffer.as_ref() {
+            let (positions, colors) = light::light_arrays(&self.lights, self.features.light_count as usize);
+            frame_buffer.upload(&frame_uniforms::pack_frame(perspective.to_homogeneous().as_slice(), &positions, &colors, self.ambient));
+        }
+        for pipeline in self.scene.pipelines.iter() {
+            self.scene.state.use_program(&pipeline.program);
+            pipeline.set_perspective(perspective.to_homogeneous().as_slice())?;
+
+            // Only declared by the PBR shaders
+            pipeline.program.set_optional_uniform("light_position", light_positions.clone())?;
+            pipeline.program.set_optional_uniform("light_color", light_colors.clone())?;
+            pipeline.program.set_optional_uniform("ambient_color", UniformValue::Vec3(self.ambient))?;
+
+            if let Some(environment) = self.environment.as_ref() {
+                environment.set_uniforms(&pipeline.program)?;
+            }
+        }
+
+        // Values set from JS, unchanged ones are skipped by the uniform cache
+        let scene_pipeline = &self.scene.pipelines[SCENE_PIPELINE];
+        self.scene.state.use_program(&scene_pipeline.program);
+        for (name, value) in self.custom_uniforms.iter() {
+            scene_pipeline.program.set_uniform(name, value.clone())?;
+        }
+
+        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
+        self.gl.clear(GL::COLOR_BUFFER_BIT);
+        self.gl.clear(GL::DEPTH_BUFFER_BIT);
+
+        // Background first, scene geometry is depth tested against the cleared buffer
+        if let Some(environment) = self.environment.as_ref() {
+            let mut inv_perspective = [0.0; 16];
+            inv_perspective.copy_from_slice(perspective.inverse().as_slice());
+            environment.draw_skybox(inv_perspective, &mut self.scene.state)?;
+            environment.bind_textures(&mut self.scene.state);
+        }
+
+        self.scene.draw_nodes(&perspective.to_homogeneous(), t)?;
+
+        if let Some(chain) = self.post_process.as_ref() {
+            chain.apply()?;
+            self.scene.state.invalidate();
+        }
+
+        let render_params = &self.init.render_params;
+
+        // add new object
+        if render_params.multiple {
+            // Each material draws its own mesh
+            let material = self.next_node_id as usize % self.scene.materials.len();
+
+            let mut obj_node = spawn_node(render_params, self.next_node_id, material, self.obj.bounds);
+            obj_node.material = material;
+            self.next_node_id += 1;
+            self.scene.nodes.push(obj_node);
+
+            self.triangles += self.obj.triangles;
+            self.vertex_count += self.obj.vertex_count;
+        }
+
+        Ok(())
+    }
+
+    /// Sets a custom uniform of the scene program, kept and applied every frame
+    ///
+    /// `value` is `{type, value}`, e.g. `{type: "vec3", value: [1, 0, 0]}`.
+    #[allow(deprecated)]
+    pub fn set_uniform(&mut self, name: &str, value: &JsValue) -> Result<(), JsValue> {
+        let value: UniformValue = value.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.scene.pipelines[SCENE_PIPELINE].program.check_uniform(name, &value)?;
+
+        match self.custom_uniforms.iter_mut().find(|(key, _)| key == name) {
+            Some(entry) => entry.1 = value,
+            None => self.custom_uniforms.push((name.to_string(), value)),
+        }
+
+        Ok(())
+    }
+
+    /// Sets (or removes with `undefined`) a shader define and switches to the matching program variant
+    ///
+    /// Variants are compiled once per define set and reused afterwards.
+    pub fn set_define(&mut self, name: &str, value: Option<String>) -> Result<(), JsValue> {
+        let mut shaders = self.shaders.clone();
+        match value {
+            Some(value) => shaders.defines.insert(name.to_string(), value),
+            None => shaders.defines.remove(name),
+        };
+
+        self.swap_program(shaders)
+    }
+
+    /// Compiles new scene shaders and swaps them in on success
+    ///
+    /// On compile or link errors the error log is returned and the old program keeps running.
+    pub fn reload_shaders(&mut self, vert_str: &str, frag_str: &str) -> Result<(), JsValue> {
+        let mut shaders = self.shaders.clone();
+        shaders.vert_str = vert_str.to_string();
+        shaders.frag_str = frag_str.to_string();
+
+        self.swap_program(shaders)
+    }
+
+    /// Returns the id of the node under canvas pixel (x, y), measured from the top-left corner
+    pub fn pick(&mut self, x: i32, y: i32) -> Option<u32> {
+        let width = self.canvas.width();
+        let height = self.canvas.height();
+        let perspective = self.perspective();
+
+        if self.picker.is_none() {
+            match Picker::new(&mut self.shader_variants, width, height) {
+                Ok(picker) => self.picker = Some(picker),
+                Err(err) => {
+                    log!("Picking is unavailable: {}", err);
+                    return None;
+                }
+            }
+        }
+        let picker = self.picker.as_mut()?;
+
+        let mut matrix = [0.0; 16];
+        matrix.copy_from_slice(perspective.to_homogeneous().as_slice());
+
+        let id = picker.pick(&mut self.scene, &matrix, x, y, width, height);
+        self.scene.state.invalidate();
+
+        id
+    }
+
+    /// Rebuilds every GPU resource after the context came back, keeping nodes and settings made from JS
+    ///
+    /// Texture regions updated from JS are back to their loaded content.
+    fn restore(&mut self) -> Result<(), JsValue> {
+        // Cached programs belong to the lost context
+        program_cache::forget_context(&self.gl);
+
+        let mut restored = Context::build(self.init.clone(), self.canvas.clone(), self.gl.clone(), self.loss.clone())?;
+
+        restored.custom_uniforms = std::mem::take(&mut self.custom_uniforms);
+        restored.swap_program(self.shaders.clone())?;
+
+        // Nodes only refer to meshes and materials by index, which the rebuild keeps
+        restored.scene.nodes = std::mem::take(&mut self.scene.nodes);
+        restored.triangles = self.triangles;
+        restored.vertex_count = self.vertex_count;
+        restored.next_node_id = self.next_node_id;
+        restored.total_upload_bytes = self.total_upload_bytes;
+        // Initial decoding times stay meaningful, the restore decoded everything again
+        restored.decode_timings = std::mem::take(&mut self.decode_timings);
+
+        *self = restored;
+        Ok(())
+    }
+
+    /// Builds the pipeline for the shaders and replaces the current one only if everything succeeds
+    fn swap_program(&mut self, shaders: JsShaders) -> Result<(), JsValue> {
+        let pipeline = create_default_program(&mut self.shader_variants, &shaders, &self.features)?;
+
+        for material in self.scene.materials.iter().filter(|material| material.pipeline == SCENE_PIPELINE) {
+            for warning in material.validate(&pipeline)? {
+                log!("Warning: {}", warning);
+            }
+        }
+        for (name, value) in self.custom_uniforms.iter() {
+            pipeline.program.check_uniform(name, value)?;
+        }
+
+        self.scene.pipelines[SCENE_PIPELINE].unbind(&mut self.scene.state);
+        self.scene.pipelines[SCENE_PIPELINE] = pipeline;
+        self.shaders = shaders;
+        self.scene.state.invalidate();
+
+        Ok(())
+    }
+
+    fn perspective(&self) -> na::Perspective3<f32> {
+        let width = self.canvas.width() as f32;
+        let height = self.canvas.height() as f32;
+        na::Perspective3::new(width / height, 45.0f32.to_radians(), 0.1, 100.0)
+    }
+}
+
+/// Scene drawn with WebGPU
+///
+/// Loads the same obj and nodes as `Context`, textures other than `tex_diffuse` and `tex_norm`
+/// are ignored since the WGSL shader only samples those two.
+#[wasm_bindgen]
+pub struct GpuContext {
+    canvas: HtmlCanvasElement,
+    renderer: WebGpuRenderer,
+    obj: Geometry,
+    nodes: Vec<model::Node>,
+    triangles: i32,
+    vertex_count: i32,
+    culled: i32,
+    drawn: i32,
+    draw_calls: i32,
+    init: SceneInit,
+    next_node_id: u32
+}
+
+#[wasm_bindgen]
+impl GpuContext {
+
+    /// Resolves once the adapter and device are ready, rejects where WebGPU is unavailable
+    pub async fn create(scene_init: SceneInit) -> Result<GpuContext, JsValue> {
+        let canvas = get_canvas(scene_init.canvas_id.as_ref())?;
+        let obj = load_obj(&scene_init);
+
+        let image = |id: &str, fallback: [u8; 4]| -> Result<Image, JsValue> {
+            match scene_init.textures_images.iter().find(|jti| jti.id == id) {
+                Some(jti) => Ok(decode_scene_image(jti)?),
+                None => Ok(Image { width: 1, height: 1, channels: 4, data: fallback.to_vec() }),
+            }
+        };
+        // White and a flat normal leave the lighting unchanged
+        let diffuse = image("tex_diffuse", [255, 255, 255, 255])?;
+        let norm = image("tex_norm", [128, 128, 255, 255])?;
+
+        let mut renderer = WebGpuRenderer::new(&canvas, &diffuse, &norm).await?;
+        let mesh = renderer.upload_mesh(&obj)?;
+
+        let mut obj_node = model::Node::with_bounds(mesh, obj.bounds);
+        obj_node.id = 0;
+        let init_pos = scene_init.render_params.init_pos.as_ref();
+        obj_node.set_x_y_z(init_pos[0], init_pos[1], init_pos[2]);
+
+        Ok(GpuContext {
+            canvas,
+            renderer,
+            triangles: obj.triangles,
+            vertex_count: obj.vertex_count,
+            obj,
+            nodes: vec![obj_node],
+            culled: 0,
+            drawn: 0,
+            draw_calls: 0,
+            init: scene_init,
+            next_node_id: 1
+        })
+    }
+
+    pub fn draw(&mut self, t: f32) -> Result<(), JsValue> {
+        let width = self.canvas.width();
+        let height = self.canvas.height();
+        let perspective = na::Perspective3::new(width as f32 / height as f32, 45.0f32.to_radians(), 0.1, 100.0).to_homogeneous();
+
+        let visible = scene::visible_nodes(&mut self.nodes, &perspective, t);
+        self.drawn = visible.len() as i32;
+        self.culled = (self.nodes.len() - visible.len()) as i32;
+
+        let mut perspective_data = [0.0; 16];
+        perspective_data.copy_from_slice(perspective.as_slice());
+        self.draw_calls = self.renderer.draw(&self.nodes, &visible, &perspective_data, width, height)?;
+
+        // add new object
+        let render_params = &self.init.render_params;
+        if render_params.multiple {
+            self.nodes.push(spawn_node(render_params, self.next_node_id, 0, self.obj.bounds));
+            self.next_node_id += 1;
+
+            self.triangles += self.obj.triangles;
+            self.vertex_count += self.obj.vertex_count;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_info(&self) -> SceneInfo {
+        let mut info = SceneInfo::new(self.triangles, self.vertex_count);
+        info.culled = self.culled;
+        info.drawn = self.drawn;
+        info.draw_calls = self.draw_calls;
+
+        info
+    }
+
+    pub fn get_backend(&self) -> String {
+        BackendKind::WebGpu.name().to_string()
+    }
+
+    pub fn get_context(&self) -> GpuCanvasContext {
+        self.renderer.context().clone()
+    }
+}
+
+/// Scene texture before its upload, decoded images may still move into an atlas
+enum LoadedTexture {
+    Uploaded(Texture<WebGlDevice>),
+    Decoded(Image),
+}
+
+/// Packs the decoded images of materials into atlas pages and points their slots at the pages
+///
+/// Returns the geometry remapped for each packed material, nothing is packed when UVs repeat.
+fn pack_atlas(packer: &AtlasPacker, obj: &Geometry, materials: &mut [Material], textures: &mut Vec<LoadedTexture>) -> Result<Vec<Option<Geometry>>, String> {
+    let candidates: Vec<Option<Vec<(&str, &Image)>>> = materials
+        .iter()
+        .map(|material| {
+            material.textures
+                .iter()
+                .map(|slot| match &textures[slot.texture] {
+                    LoadedTexture::Decoded(image) => Some((slot.sampler.as_str(), image)),
+                    LoadedTexture::Uploaded(_) => None,
+                })
+                .collect()
+        })
+        .collect();
+    let atlas = MaterialAtlas::build(&candidates, packer)?;
+
+    let mut geometries = vec![None; materials.len()];
+    for (i, rect) in atlas.rects.iter().enumerate() {
+        if let Some(rect) = rect {
+            let mut geometry = obj.clone();
+            if let Err(msg) = geometry.remap_uvs(rect, atlas.page_size) {
+                log!("Warning: textures are not packed into an atlas, {}", msg);
+                return Ok(vec![None; materials.len()]);
+            }
+            geometries[i] = Some(geometry);
+        }
+    }
+
+    for (sampler, page, image) in atlas.pages {
+        let index = textures.len();
+        textures.push(LoadedTexture::Decoded(image));
+
+        for (material, rect) in materials.iter_mut().zip(atlas.rects.iter()) {
+            if rect.is_some_and(|rect| rect.page == page) {
+                for slot in material.textures.iter_mut().filter(|slot| slot.sampler == sampler) {
+                    slot.texture = index;
+                }
+            }
+        }
+    }
+
+    Ok(geometries)
+}
+
+/// Uploads the decoded images, skipping those no material samples anymore, and compacts slot indices
+///
+/// Also returns where each loaded texture ended up.
+fn upload_textures(device: &WebGlDevice, loaded: Vec<LoadedTexture>, materials: &mut [Material]) -> (Vec<Texture<WebGlDevice>>, Vec<Option<usize>>) {
+    let mut indices = vec![None; loaded.len()];
+    let mut textures = vec![];
+
+    for (i, texture) in loaded.into_iter().enumerate() {
+        let texture = match texture {
+            LoadedTexture::Uploaded(texture) => texture,
+            LoadedTexture::Decoded(image) => {
+                if !materials.iter().any(|material| material.textures.iter().any(|slot| slot.texture == i)) {
+                    continue;
+                }
+                Texture::from_image(device.clone(), &image, GL::TEXTURE0 + textures.len() as u32)
+            }
+        };
+
+        indices[i] = Some(textures.len());
+        textures.push(texture);
+    }
+
+    for slot in materials.iter_mut().flat_map(|material| material.textures.iter_mut()) {
+        slot.texture = indices[slot.texture].expect("Sampled textures are uploaded");
+    }
+
+    (textures, indices)
+}
+
+/// Parses a shading model name, true for the built-in PBR shaders
+fn is_pbr_shading(shading: Option<&str>) -> Result<bool, String> {
+    match shading {
+        None | Some("lambert") => Ok(false),
+        Some("pbr") => Ok(true),
+        Some(shading) => Err(format!("Unknown shading model: {}", shading)),
+    }
+}
+
+/// Shaders of a shading model, PBR replaces the JS sources with the built-in ones
+fn shading_shaders(scene_init: &SceneInit, pbr: bool) -> JsShaders {
+    let mut shaders = scene_init.shaders.clone();
+    if pbr {
+        shaders.vert_str = default_pipeline::PBR_VERT.to_string();
+        shaders.frag_str = default_pipeline::PBR_FRAG.to_string();
+        // Leave tone mapping to the post-processing chain when it has a pass for it
+        if scene_init.render_params.post_process.iter().any(|pass| pass.effect == "tone_mapping") {
+            shaders.defines.insert("LINEAR_OUTPUT".to_string(), "1".to_string());
+        }
+    }
+
+    shaders
+}
+
+fn create_default_program(shader_variants: &mut ShaderVariants, shaders: &JsShaders, features: &ShaderFeatures) -> Result<DefaultPipeline<WebGlDevice>, String> {
+    let mut defines = features.to_defines();
+    defines.extend(shaders.defines.iter().map(|(name, value)| (name.clone(), value.clone())));
+
+    let program = shader_variants.get(
+        &ShaderSource { name: "vertex", source: &shaders.vert_str },
+        &ShaderSource { name: "fragment", source: &shaders.frag_str },
+        &defines,
+    )?;
+
+    Ok(DefaultPipeline::from_program(program))
+}
+
+/// Geometry of the scene obj file
+fn load_obj(scene_init: &SceneInit) -> Geometry {
+    let obj_data = obj_file_data_to_vertex_vector_data(scene_init.obj_file_data.as_ref());
+
+    Geometry::new(
+        obj_data.0, obj_data.1, obj_data.2,
+        obj_data.3, obj_data.4, obj_data.5
+    )
+}
+
+/// Decodes a scene texture on the CPU, compressed variants are skipped since none can be uploaded
+fn decode_scene_image(jti: &JsTextureImage) -> Result<Image, String> {
+    let variants: Vec<&[u8]> = match &jti.data {
+        TextureData::Encoded(data) => vec![data.as_ref()],
+        TextureData::Variants(variants) => variants.iter().map(|data| data.as_ref()).collect(),
+        TextureData::Dom(_) => return Err(format!("Texture '{}': DOM sources need a WebGL context", jti.id)),
+    };
+
+    match ktx::select_variant(&variants, &CompressionSupport { families: vec![] }).map_err(|msg| format!("Texture '{}': {}", jti.id, msg))? {
+        Variant::Encoded(data) => Image::decode(data).map_err(|msg| format!("Texture '{}': {}", jti.id, msg)),
+        Variant::Compressed(_) => Err(format!("Texture '{}': compressed textures are not supported", jti.id)),
+    }
+}
+
+/// Node of `mesh` at a random position inside the spawn box of the render params
+fn spawn_node(render_params: &JsRenderParams, id: u32, mesh: usize, bounds: BoundingVolume) -> model::Node {
+    let mut rng = rand::thread_rng();
+
+    let mut node = model::Node::with_bounds(mesh, bounds);
+    node.id = id;
+    node.set_x_y_z(
+        rng.gen_range(render_params.min_max_x[0], render_params.min_max_x[1]),
+        rng.gen_range(render_params.min_max_y[0], render_params.min_max_y[1]),
+        rng.gen_range(render_params.min_max_z[0], render_params.min_max_z[1]),
+    );
+
+    node
+}
+
+/// Returns a WebGL Context
+fn get_canvas(id: &str) -> Result<HtmlCanvasElement, JsValue> {
+    utils::set_panic_hook();
+
+    let doc = window().unwrap().document().unwrap();
+    let canvas = doc
+        .get_element_by_id(id)
+        .unwrap_or_else(|| panic!("Failed to get canvas: {}", id));
+    let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>()?;
+
+    Ok(canvas)
+}
\ No newline at end of file